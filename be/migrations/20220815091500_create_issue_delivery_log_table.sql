@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE issue_delivery_log (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    -- Either 'sent' or 'skipped'
+    outcome TEXT NOT NULL,
+    details TEXT NULL,
+    recorded_at timestamptz NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, subscriber_email)
+);
@@ -0,0 +1,8 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues
+    -- Either 'scheduled', 'published' or 'cancelled'
+    ADD COLUMN status TEXT NOT NULL DEFAULT 'published',
+    ADD COLUMN send_at timestamptz NULL,
+    ALTER COLUMN published_at DROP NOT NULL;
+CREATE INDEX newsletter_issues_scheduled_idx ON newsletter_issues (send_at)
+    WHERE status = 'scheduled';
@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE newsletter_drafts (
+    draft_id uuid NOT NULL,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    updated_at timestamptz NOT NULL,
+    PRIMARY KEY(draft_id)
+);
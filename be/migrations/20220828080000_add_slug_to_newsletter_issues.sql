@@ -0,0 +1,6 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues ADD COLUMN slug TEXT NULL;
+-- Issues sent before the archive existed keep a stable, if unfriendly, permalink
+UPDATE newsletter_issues SET slug = newsletter_issue_id::text WHERE slug IS NULL;
+ALTER TABLE newsletter_issues ALTER COLUMN slug SET NOT NULL;
+ALTER TABLE newsletter_issues ADD CONSTRAINT newsletter_issues_slug_key UNIQUE (slug);
@@ -0,0 +1,21 @@
+-- Add migration script here
+ALTER TABLE subscriptions
+    -- Either 'every_issue', 'weekly' or 'monthly'
+    ADD COLUMN delivery_frequency TEXT NOT NULL DEFAULT 'every_issue';
+
+CREATE TABLE topics (
+    topic_id uuid NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY(topic_id)
+);
+
+-- Subscribers receive every topic unless they opt out of it
+CREATE TABLE topic_opt_outs (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    topic_id uuid NOT NULL REFERENCES topics (topic_id),
+    PRIMARY KEY(subscriber_id, topic_id)
+);
+
+ALTER TABLE newsletter_issues
+    ADD COLUMN topic_id uuid NULL REFERENCES topics (topic_id);
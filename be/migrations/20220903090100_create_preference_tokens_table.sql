@@ -0,0 +1,14 @@
+-- Add migration script here
+CREATE TABLE preference_tokens (
+    token_id uuid NOT NULL,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    -- Set for the links sent with an issue: one per recipient, however many
+    -- delivery attempts it takes
+    newsletter_issue_id uuid NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    created_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL,
+    revoked_at timestamptz NULL,
+    PRIMARY KEY(token_id),
+    UNIQUE(subscriber_id, newsletter_issue_id)
+);
+CREATE INDEX preference_tokens_subscriber_id_idx ON preference_tokens (subscriber_id);
@@ -0,0 +1,44 @@
+-- Add migration script here
+CREATE TABLE lists (
+    list_id uuid NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    -- Used by subscription forms to pick a list
+    slug TEXT NOT NULL UNIQUE,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY(list_id)
+);
+
+CREATE TABLE list_memberships (
+    list_id uuid NOT NULL REFERENCES lists (list_id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    -- Either 'pending_confirmation', 'confirmed' or 'unsubscribed'
+    status TEXT NOT NULL,
+    subscribed_at timestamptz NOT NULL,
+    confirmed_at timestamptz NULL,
+    PRIMARY KEY(list_id, subscriber_id)
+);
+
+-- The lists an issue is delivered to
+CREATE TABLE newsletter_issue_lists (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    list_id uuid NOT NULL REFERENCES lists (list_id),
+    PRIMARY KEY(newsletter_issue_id, list_id)
+);
+
+-- Everything published so far went to a single, default list
+INSERT INTO lists (list_id, name, slug, created_at)
+    VALUES (gen_random_uuid(), 'Newsletter', 'newsletter', now());
+INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at, confirmed_at)
+    SELECT l.list_id, s.id, s.status, s.subscribed_at,
+        CASE WHEN s.status = 'confirmed' THEN s.subscribed_at END
+        FROM subscriptions s, lists l
+        WHERE l.slug = 'newsletter';
+INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+    SELECT i.newsletter_issue_id, l.list_id
+        FROM newsletter_issues i, lists l
+        WHERE l.slug = 'newsletter';
+
+-- A confirmation link confirms the membership of a single list
+ALTER TABLE subscription_tokens ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+UPDATE subscription_tokens SET list_id = (SELECT list_id FROM lists WHERE slug = 'newsletter');
+ALTER TABLE subscription_tokens ALTER COLUMN list_id SET NOT NULL;
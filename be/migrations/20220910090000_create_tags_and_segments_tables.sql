@@ -0,0 +1,27 @@
+-- Add migration script here
+CREATE TABLE subscriber_tags (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    -- Lowercase
+    tag TEXT NOT NULL,
+    PRIMARY KEY(subscriber_id, tag)
+);
+
+-- Free-form custom attributes, e.g. ('plan', 'pro')
+CREATE TABLE subscriber_attributes (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    name TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY(subscriber_id, name)
+);
+
+CREATE TABLE segments (
+    segment_id uuid NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    -- A serialized `SegmentFilter`
+    filter jsonb NOT NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY(segment_id)
+);
+
+ALTER TABLE newsletter_issues
+    ADD COLUMN segment_id uuid NULL REFERENCES segments (segment_id);
@@ -0,0 +1,7 @@
+-- Add migration script here
+ALTER TABLE subscriptions
+    -- Set when the provider tells us the address must not be mailed anymore
+    ADD COLUMN suppressed_at timestamptz NULL,
+    -- Either 'hard_bounce', 'spam_complaint' or 'manual'
+    ADD COLUMN suppression_reason TEXT NULL,
+    ADD COLUMN soft_bounces INTEGER NOT NULL DEFAULT 0;
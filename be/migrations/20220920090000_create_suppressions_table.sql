@@ -0,0 +1,35 @@
+-- Add migration script here
+-- Addresses we must never mail, whether or not they are subscribed
+CREATE TABLE suppressions (
+    -- Trimmed and lowercased
+    email TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    -- Either 'manual', 'bounce', 'complaint', 'unsubscribe' or 'import'
+    source TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY(email)
+);
+
+-- Carry over the addresses suppressed by the provider's webhooks
+INSERT INTO suppressions (email, reason, source, created_at)
+    SELECT
+        lower(trim(email)),
+        CASE suppression_reason
+            WHEN 'hard_bounce' THEN 'Hard bounce'
+            WHEN 'spam_complaint' THEN 'Spam complaint'
+            ELSE 'Suppressed on the provider'
+        END,
+        CASE suppression_reason
+            WHEN 'hard_bounce' THEN 'bounce'
+            WHEN 'spam_complaint' THEN 'complaint'
+            ELSE 'manual'
+        END,
+        suppressed_at
+    FROM subscriptions
+    WHERE suppressed_at IS NOT NULL
+    ON CONFLICT DO NOTHING;
+
+-- The table above is the only record of who must not be mailed
+ALTER TABLE subscriptions
+    DROP COLUMN suppressed_at,
+    DROP COLUMN suppression_reason;
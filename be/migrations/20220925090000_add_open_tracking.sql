@@ -0,0 +1,17 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues
+    -- Whether the HTML body of the issue carries a tracking pixel
+    ADD COLUMN track_opens BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE issue_opens (
+    open_id uuid NOT NULL,
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id),
+    opened_at timestamptz NOT NULL,
+    user_agent TEXT NULL,
+    PRIMARY KEY(open_id)
+);
+
+CREATE INDEX issue_opens_newsletter_issue_id_idx ON issue_opens (newsletter_issue_id);
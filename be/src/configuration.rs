@@ -1,10 +1,16 @@
 use crate::domain::SubscriberEmail;
+use crate::email_client::{
+    CircuitBreakerSettings, EmailSender, FailoverSender, MailgunClient, MailgunSettings,
+    PostmarkClient, RetryPolicy, SendGridClient, SesClient, SesSettings, SmtpClient, SmtpSettings,
+};
+use crate::rate_limiter::{RateLimitedSender, RateLimiter, RateLimits};
 use config::{Config, ConfigError, File};
 use secrecy::{ExposeSecret, Secret};
 use serde::Deserialize;
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 use sqlx::ConnectOptions;
+use std::sync::Arc;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct DatabaseSettings {
@@ -23,6 +29,7 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub webhooks: WebhookSettings,
     pub redis_uri: Secret<String>,
 }
 
@@ -35,15 +42,174 @@ pub struct ApplicationSettings {
     pub hmac_secret: Secret<String>,
 }
 
+// The basic auth credentials the email provider's webhooks are set up with
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebhookSettings {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailProvider {
+    Postmark,
+    Sendgrid,
+    Mailgun,
+    Ses,
+    Smtp,
+}
+
+impl EmailProvider {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailProvider::Postmark => "postmark",
+            EmailProvider::Sendgrid => "sendgrid",
+            EmailProvider::Mailgun => "mailgun",
+            EmailProvider::Ses => "ses",
+            EmailProvider::Smtp => "smtp",
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct EmailClientSettings {
+    pub provider: EmailProvider,
+    // The provider's HTTP API
     pub base_url: String,
     pub sender_email: String,
+    // The server token or API key, unused by SES and SMTP
     pub authorization_token: Secret<String>,
     pub timeout_seconds: u64,
+    pub max_attempts: u32,
+    pub retry_base_delay_milliseconds: u64,
+    pub retry_max_delay_seconds: u64,
+    // Our plan's limits: no limit for the missing ones
+    pub max_sends_per_second: Option<u32>,
+    pub max_burst: Option<u32>,
+    pub daily_cap: Option<u32>,
+    // Required by the provider of the same name
+    pub mailgun: Option<MailgunSettings>,
+    pub ses: Option<SesSettings>,
+    pub smtp: Option<SmtpSettings>,
+    // Providers to fall back on, in order, when the one above is failing
+    #[serde(default)]
+    pub failover: Vec<ProviderSettings>,
+    pub circuit_breaker_failure_threshold: u32,
+    pub circuit_breaker_cooldown_seconds: u64,
+}
+
+/// A provider to fall back on, configured like the primary one.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProviderSettings {
+    pub provider: EmailProvider,
+    pub base_url: String,
+    pub authorization_token: Secret<String>,
+    pub mailgun: Option<MailgunSettings>,
+    pub ses: Option<SesSettings>,
+    pub smtp: Option<SmtpSettings>,
+}
+
+impl ProviderSettings {
+    fn backend(
+        self,
+        sender_email: SubscriberEmail,
+        timeout: std::time::Duration,
+    ) -> Box<dyn EmailSender> {
+        match self.provider {
+            EmailProvider::Postmark => Box::new(PostmarkClient::new(
+                self.base_url,
+                sender_email,
+                self.authorization_token,
+                timeout,
+            )),
+            EmailProvider::Sendgrid => Box::new(SendGridClient::new(
+                self.base_url,
+                sender_email,
+                self.authorization_token,
+                timeout,
+            )),
+            EmailProvider::Mailgun => {
+                let settings = self
+                    .mailgun
+                    .as_ref()
+                    .expect("The mailgun settings are required by the Mailgun provider.");
+                Box::new(MailgunClient::new(
+                    self.base_url,
+                    settings,
+                    sender_email,
+                    self.authorization_token,
+                    timeout,
+                ))
+            }
+            EmailProvider::Ses => {
+                let settings = self
+                    .ses
+                    .as_ref()
+                    .expect("The ses settings are required by the SES provider.");
+                Box::new(
+                    SesClient::new(&self.base_url, settings, sender_email, timeout)
+                        .expect("Invalid SES settings."),
+                )
+            }
+            EmailProvider::Smtp => {
+                let settings = self
+                    .smtp
+                    .as_ref()
+                    .expect("The smtp settings are required by the SMTP provider.");
+                Box::new(
+                    SmtpClient::new(settings, sender_email, timeout)
+                        .expect("Invalid SMTP settings."),
+                )
+            }
+        }
+    }
 }
 
 impl EmailClientSettings {
+    /// Build a client for the configured providers, with its own rate limiter
+    /// and circuit breakers: share it, by cloning the `Arc`, rather than
+    /// calling this again.
+    pub fn client(self) -> Arc<dyn EmailSender> {
+        let sender_email = self.sender().expect("Invalid sender email address.");
+        let timeout = self.timeout();
+        let rate_limiter = RateLimiter::new(self.rate_limits());
+        let circuit_breaker = self.circuit_breaker();
+        let backends = self
+            .providers()
+            .into_iter()
+            .map(|p| {
+                (
+                    p.provider.as_str(),
+                    p.backend(sender_email.clone(), timeout),
+                )
+            })
+            .collect();
+        let failover = FailoverSender::new(backends, circuit_breaker);
+        Arc::new(RateLimitedSender::new(Box::new(failover), rate_limiter))
+    }
+
+    /// The primary provider followed by the failover ones.
+    pub fn providers(&self) -> Vec<ProviderSettings> {
+        let primary = ProviderSettings {
+            provider: self.provider,
+            base_url: self.base_url.clone(),
+            authorization_token: self.authorization_token.clone(),
+            mailgun: self.mailgun.clone(),
+            ses: self.ses.clone(),
+            smtp: self.smtp.clone(),
+        };
+        std::iter::once(primary)
+            .chain(self.failover.iter().cloned())
+            .collect()
+    }
+
+    pub fn circuit_breaker(&self) -> CircuitBreakerSettings {
+        CircuitBreakerSettings {
+            failure_threshold: self.circuit_breaker_failure_threshold.max(1),
+            cooldown: std::time::Duration::from_secs(self.circuit_breaker_cooldown_seconds),
+        }
+    }
+
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
         SubscriberEmail::parse(self.sender_email.clone())
     }
@@ -55,6 +221,22 @@ impl EmailClientSettings {
         }
         std::time::Duration::from_secs(get_timeout)
     }
+
+    pub fn rate_limits(&self) -> RateLimits {
+        RateLimits {
+            max_sends_per_second: self.max_sends_per_second,
+            max_burst: self.max_burst,
+            daily_cap: self.daily_cap,
+        }
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.max_attempts.max(1),
+            base_delay: std::time::Duration::from_millis(self.retry_base_delay_milliseconds),
+            max_delay: std::time::Duration::from_secs(self.retry_max_delay_seconds),
+        }
+    }
 }
 
 pub fn get_configuration() -> Result<Settings, ConfigError> {
@@ -0,0 +1,73 @@
+use chrono::Duration;
+
+/// How often a subscriber wants to hear from us: issues published sooner
+/// than that after the last one they received are held back until then.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryFrequency {
+    EveryIssue,
+    Weekly,
+    Monthly,
+}
+
+impl DeliveryFrequency {
+    pub const ALL: [DeliveryFrequency; 3] = [
+        DeliveryFrequency::EveryIssue,
+        DeliveryFrequency::Weekly,
+        DeliveryFrequency::Monthly,
+    ];
+
+    pub fn parse(s: &str) -> Result<DeliveryFrequency, String> {
+        match s {
+            "every_issue" => Ok(Self::EveryIssue),
+            "weekly" => Ok(Self::Weekly),
+            "monthly" => Ok(Self::Monthly),
+            other => Err(format!("{} is not a valid delivery frequency.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::EveryIssue => "every_issue",
+            Self::Weekly => "weekly",
+            Self::Monthly => "monthly",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::EveryIssue => "Every issue",
+            Self::Weekly => "At most one issue a week",
+            Self::Monthly => "At most one issue a month",
+        }
+    }
+
+    /// The shortest time allowed between two issues, if any.
+    pub fn min_interval(&self) -> Option<Duration> {
+        match self {
+            Self::EveryIssue => None,
+            Self::Weekly => Some(Duration::days(7)),
+            Self::Monthly => Some(Duration::days(30)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::DeliveryFrequency;
+    use claim::assert_err;
+
+    #[test]
+    fn every_frequency_can_be_parsed_back() {
+        for frequency in DeliveryFrequency::ALL {
+            assert_eq!(
+                DeliveryFrequency::parse(frequency.as_str()).unwrap(),
+                frequency
+            );
+        }
+    }
+
+    #[test]
+    fn an_unknown_frequency_is_rejected() {
+        assert_err!(DeliveryFrequency::parse("daily"));
+    }
+}
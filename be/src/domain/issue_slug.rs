@@ -0,0 +1,82 @@
+/// The URL-friendly name of a newsletter issue, used in its permalink.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssueSlug(String);
+
+impl AsRef<str> for IssueSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for IssueSlug {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl IssueSlug {
+    const MAX_LENGTH: usize = 80;
+
+    /// Keep ASCII letters and digits, lowercased, and join the words with dashes.
+    pub fn from_title(title: &str) -> IssueSlug {
+        let mut slug = String::new();
+        for word in title
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|w| !w.is_empty())
+        {
+            if slug.len() + word.len() + 1 > Self::MAX_LENGTH {
+                break;
+            }
+            if !slug.is_empty() {
+                slug.push('-');
+            }
+            slug.push_str(&word.to_ascii_lowercase());
+        }
+        if slug.is_empty() {
+            slug.push_str("issue");
+        }
+        Self(slug)
+    }
+
+    /// A variant to fall back to when another issue already uses this slug.
+    pub fn with_suffix(&self, n: u32) -> IssueSlug {
+        Self(format!("{}-{}", self.0, n))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::IssueSlug;
+
+    #[test]
+    fn words_are_lowercased_and_joined_with_dashes() {
+        let slug = IssueSlug::from_title("Our First  Issue!");
+        assert_eq!(slug.as_ref(), "our-first-issue");
+    }
+
+    #[test]
+    fn punctuation_and_non_ascii_characters_are_dropped() {
+        let slug = IssueSlug::from_title("Tin tức: mùa hè / 2022");
+        assert_eq!(slug.as_ref(), "tin-t-c-m-a-h-2022");
+    }
+
+    #[test]
+    fn a_title_without_any_usable_character_falls_back_to_a_default() {
+        let slug = IssueSlug::from_title("¿¡!?");
+        assert_eq!(slug.as_ref(), "issue");
+    }
+
+    #[test]
+    fn long_titles_are_cut_at_a_word_boundary() {
+        let title = "word ".repeat(100);
+        let slug = IssueSlug::from_title(&title);
+        assert!(slug.as_ref().len() <= 80);
+        assert!(slug.as_ref().ends_with("word"));
+    }
+
+    #[test]
+    fn a_suffix_is_appended_after_a_dash() {
+        let slug = IssueSlug::from_title("Our first issue").with_suffix(2);
+        assert_eq!(slug.as_ref(), "our-first-issue-2");
+    }
+}
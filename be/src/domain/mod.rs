@@ -1,7 +1,11 @@
+mod delivery_frequency;
+mod issue_slug;
 mod new_subscriber;
 mod subscriber_name;
 mod subscriber_email;
 
+pub use delivery_frequency::DeliveryFrequency;
+pub use issue_slug::IssueSlug;
 pub use new_subscriber::NewSubscriber;
 pub use subscriber_name::SubscriberName;
 pub use subscriber_email::SubscriberEmail;
@@ -0,0 +1,356 @@
+use crate::email_client::{EmailSender, OutgoingEmail, SendEmailError};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy)]
+pub struct CircuitBreakerSettings {
+    // Consecutive transient failures before a provider is taken out of rotation
+    pub failure_threshold: u32,
+    // How long a failing provider is skipped before we try it again
+    pub cooldown: Duration,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// Keep track of a provider's health.
+///
+/// The circuit opens after `failure_threshold` transient failures in a row.
+/// Once the cooldown is over a single trial request goes through: the circuit
+/// closes if it succeeds and opens again for another cooldown if it fails.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    settings: CircuitBreakerSettings,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(settings: CircuitBreakerSettings) -> Self {
+        Self {
+            settings,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// Whether the provider should be tried now, claiming the trial request
+    /// when the cooldown is over.
+    pub fn allows_request(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.open_until {
+            None => true,
+            Some(open_until) => {
+                let now = Instant::now();
+                if now < open_until {
+                    return false;
+                }
+                // Concurrent sends keep skipping the provider while we find out
+                state.open_until = Some(now + self.settings.cooldown);
+                true
+            }
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state.lock().unwrap().open_until.is_some()
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = 0;
+        state.open_until = None;
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+        if state.consecutive_failures >= self.settings.failure_threshold.max(1) {
+            state.open_until = Some(Instant::now() + self.settings.cooldown);
+        }
+    }
+}
+
+struct Provider {
+    name: &'static str,
+    sender: Box<dyn EmailSender>,
+    circuit_breaker: CircuitBreaker,
+}
+
+/// Send through the first healthy provider of an ordered list.
+///
+/// Transient errors and exhausted quotas move on to the next provider, while
+/// permanent errors are returned right away: the email would be refused
+/// elsewhere too. Providers whose circuit is open are skipped, unless every
+/// circuit is open.
+pub struct FailoverSender {
+    providers: Vec<Provider>,
+}
+
+impl FailoverSender {
+    pub fn new(
+        providers: Vec<(&'static str, Box<dyn EmailSender>)>,
+        settings: CircuitBreakerSettings,
+    ) -> Self {
+        assert!(!providers.is_empty(), "At least one provider is required.");
+        let providers = providers
+            .into_iter()
+            .map(|(name, sender)| Provider {
+                name,
+                sender,
+                circuit_breaker: CircuitBreaker::new(settings),
+            })
+            .collect();
+        Self { providers }
+    }
+
+    /// Run `attempt` with the first provider that doesn't fail transiently or
+    /// run out of quota.
+    async fn try_providers<'a, T, F, Fut>(&'a self, attempt: F) -> Result<T, SendEmailError>
+    where
+        F: Fn(&'a dyn EmailSender) -> Fut,
+        Fut: std::future::Future<Output = Result<T, SendEmailError>>,
+    {
+        let mut last_error = None;
+        for long_shot in [false, true] {
+            for provider in &self.providers {
+                if !long_shot && !provider.circuit_breaker.allows_request() {
+                    continue;
+                }
+                let outcome = attempt(provider.sender.as_ref()).await;
+                Self::record(provider, &outcome);
+                match outcome {
+                    Ok(value) => {
+                        tracing::info!(provider = provider.name, "Sent through the provider");
+                        return Ok(value);
+                    }
+                    Err(e) if e.is_transient() => last_error = Some(e),
+                    Err(SendEmailError::QuotaExhausted(e)) => {
+                        tracing::warn!(
+                            provider = provider.name,
+                            error.cause_chain = ?e,
+                            "The provider is out of quota, trying the next one",
+                        );
+                        last_error = Some(SendEmailError::QuotaExhausted(e));
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            if let Some(e) = last_error {
+                return Err(e);
+            }
+            // Every circuit is open: better a long shot than failing without trying
+        }
+        unreachable!("At least one provider is tried.")
+    }
+
+    fn record<T>(provider: &Provider, outcome: &Result<T, SendEmailError>) {
+        match outcome {
+            Ok(_) => {
+                if provider.circuit_breaker.is_open() {
+                    tracing::info!(provider = provider.name, "The provider is healthy again");
+                }
+                provider.circuit_breaker.record_success();
+            }
+            Err(e) if e.is_transient() => {
+                provider.circuit_breaker.record_failure();
+                tracing::warn!(
+                    provider = provider.name,
+                    error.cause_chain = ?e,
+                    "The provider failed, trying the next one",
+                );
+            }
+            // The provider is healthy, we just can't use it until the reset
+            Err(SendEmailError::QuotaExhausted(_)) => {}
+            // The provider is up, it just refused the email
+            Err(_) => provider.circuit_breaker.record_success(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for FailoverSender {
+    async fn send(&self, email: &OutgoingEmail<'_>) -> Result<(), SendEmailError> {
+        self.try_providers(|sender| sender.send(email)).await
+    }
+
+    async fn send_batch(
+        &self,
+        emails: &[OutgoingEmail<'_>],
+    ) -> Result<Vec<Result<(), SendEmailError>>, SendEmailError> {
+        self.try_providers(|sender| sender.send_batch(emails)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CircuitBreakerSettings, FailoverSender};
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailSender, OutgoingEmail, SendEmailError};
+    use crate::rate_limiter::QuotaExhausted;
+    use claim::{assert_matches, assert_ok};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Fails transiently while `down` is set, counting the attempts.
+    #[derive(Clone, Default)]
+    struct StubProvider {
+        down: Arc<AtomicBool>,
+        refuses: bool,
+        out_of_quota: bool,
+        n_attempts: Arc<AtomicUsize>,
+    }
+
+    impl StubProvider {
+        fn down() -> Self {
+            let provider = Self::default();
+            provider.down.store(true, Ordering::SeqCst);
+            provider
+        }
+
+        fn attempts(&self) -> usize {
+            self.n_attempts.load(Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl EmailSender for StubProvider {
+        async fn send(&self, _email: &OutgoingEmail<'_>) -> Result<(), SendEmailError> {
+            self.n_attempts.fetch_add(1, Ordering::SeqCst);
+            if self.refuses {
+                Err(SendEmailError::Permanent(anyhow::anyhow!("Refused")))
+            } else if self.out_of_quota {
+                Err(SendEmailError::QuotaExhausted(QuotaExhausted {
+                    daily_cap: 100,
+                    resets_at: chrono::Utc::now(),
+                }))
+            } else if self.down.load(Ordering::SeqCst) {
+                Err(SendEmailError::Transient(anyhow::anyhow!("Outage")))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn failover(
+        primary: &StubProvider,
+        secondary: &StubProvider,
+        cooldown: Duration,
+    ) -> FailoverSender {
+        FailoverSender::new(
+            vec![
+                ("primary", Box::new(primary.clone())),
+                ("secondary", Box::new(secondary.clone())),
+            ],
+            CircuitBreakerSettings {
+                failure_threshold: 2,
+                cooldown,
+            },
+        )
+    }
+
+    async fn send(sender: &FailoverSender) -> Result<(), SendEmailError> {
+        let recipient = SubscriberEmail::parse("longle@gmail.com".into()).unwrap();
+        sender
+            .send_email(&recipient, "Subject", "<p>HTML</p>", "Text")
+            .await
+    }
+
+    #[tokio::test]
+    async fn transient_errors_fail_over_to_the_next_provider() {
+        let primary = StubProvider::down();
+        let secondary = StubProvider::default();
+        let sender = failover(&primary, &secondary, Duration::from_secs(60));
+
+        assert_ok!(send(&sender).await);
+        assert_eq!(primary.attempts(), 1);
+        assert_eq!(secondary.attempts(), 1);
+    }
+
+    #[tokio::test]
+    async fn permanent_errors_are_not_retried_with_another_provider() {
+        let primary = StubProvider {
+            refuses: true,
+            ..StubProvider::default()
+        };
+        let secondary = StubProvider::default();
+        let sender = failover(&primary, &secondary, Duration::from_secs(60));
+
+        assert_matches!(send(&sender).await, Err(SendEmailError::Permanent(_)));
+        assert_eq!(secondary.attempts(), 0);
+    }
+
+    #[tokio::test]
+    async fn an_exhausted_quota_fails_over_without_opening_the_circuit() {
+        let primary = StubProvider {
+            out_of_quota: true,
+            ..StubProvider::default()
+        };
+        let secondary = StubProvider::default();
+        let sender = failover(&primary, &secondary, Duration::from_secs(60));
+
+        for _ in 0..3 {
+            assert_ok!(send(&sender).await);
+        }
+
+        // Still tried every time: the quota may reset before the cooldown would
+        assert_eq!(primary.attempts(), 3);
+        assert_eq!(secondary.attempts(), 3);
+        assert!(!sender.providers[0].circuit_breaker.is_open());
+    }
+
+    #[tokio::test]
+    async fn a_failing_provider_is_skipped_once_its_circuit_opens() {
+        let primary = StubProvider::down();
+        let secondary = StubProvider::default();
+        let sender = failover(&primary, &secondary, Duration::from_secs(60));
+
+        for _ in 0..5 {
+            assert_ok!(send(&sender).await);
+        }
+
+        assert_eq!(primary.attempts(), 2);
+        assert_eq!(secondary.attempts(), 5);
+    }
+
+    #[tokio::test]
+    async fn the_primary_is_tried_again_after_the_cooldown() {
+        let primary = StubProvider::down();
+        let secondary = StubProvider::default();
+        let sender = failover(&primary, &secondary, Duration::from_millis(50));
+        send(&sender).await.unwrap();
+        send(&sender).await.unwrap();
+
+        // Still down: the trial fails and the circuit opens again
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        send(&sender).await.unwrap();
+        send(&sender).await.unwrap();
+        assert_eq!(primary.attempts(), 3);
+
+        // Back up: the trial closes the circuit
+        primary.down.store(false, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        send(&sender).await.unwrap();
+        send(&sender).await.unwrap();
+        assert_eq!(primary.attempts(), 5);
+        assert_eq!(secondary.attempts(), 4);
+    }
+
+    #[tokio::test]
+    async fn every_provider_is_tried_when_all_circuits_are_open() {
+        let primary = StubProvider::down();
+        let secondary = StubProvider::down();
+        let sender = failover(&primary, &secondary, Duration::from_secs(60));
+        for _ in 0..2 {
+            assert_matches!(send(&sender).await, Err(SendEmailError::Transient(_)));
+        }
+
+        secondary.down.store(false, Ordering::SeqCst);
+
+        assert_ok!(send(&sender).await);
+        assert_eq!(primary.attempts(), 3);
+        assert_eq!(secondary.attempts(), 3);
+    }
+}
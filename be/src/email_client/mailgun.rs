@@ -0,0 +1,203 @@
+use crate::domain::SubscriberEmail;
+use crate::email_client::{http_error, EmailSender, OutgoingEmail, SendEmailError};
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct MailgunSettings {
+    // The sending domain, e.g. `mg.example.com`
+    pub domain: String,
+}
+
+/// Deliver emails through Mailgun's Messages API.
+#[derive(Debug, Clone)]
+pub struct MailgunClient {
+    sender: SubscriberEmail,
+    base_url: String,
+    domain: String,
+    http_client: Client,
+    api_key: Secret<String>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    message: String,
+}
+
+impl MailgunClient {
+    pub fn new(
+        base_url: String,
+        settings: &MailgunSettings,
+        sender: SubscriberEmail,
+        api_key: Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            sender,
+            base_url,
+            domain: settings.domain.clone(),
+            http_client,
+            api_key,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for MailgunClient {
+    async fn send(&self, email: &OutgoingEmail<'_>) -> Result<(), SendEmailError> {
+        let mut form = vec![
+            ("from".to_string(), self.sender.as_ref()),
+            ("to".to_string(), email.recipient.as_ref()),
+            ("subject".to_string(), email.subject),
+            ("text".to_string(), email.text_body),
+            ("html".to_string(), email.html_body),
+        ];
+        // Custom headers are form fields prefixed with `h:`
+        form.extend(
+            email
+                .headers
+                .iter()
+                .map(|h| (format!("h:{}", h.name), h.value)),
+        );
+        let response = self
+            .http_client
+            .post(format!("{}/v3/{}/messages", self.base_url, self.domain))
+            .basic_auth("api", Some(self.api_key.expose_secret()))
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| SendEmailError::Transient(e.into()))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let body = response.text().await.unwrap_or_default();
+        let details = match serde_json::from_str::<ErrorResponse>(&body) {
+            Ok(e) => e.message,
+            Err(_) => body,
+        };
+        Err(http_error(status, &details))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{
+        EmailHeader, EmailSender, MailgunClient, MailgunSettings, SendEmailError,
+    };
+    use claim::{assert_matches, assert_ok};
+    use secrecy::Secret;
+    use wiremock::matchers::{any, body_string_contains, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse("longle@gmail.com".into()).unwrap()
+    }
+
+    fn email_client(base_url: String) -> MailgunClient {
+        MailgunClient::new(
+            base_url,
+            &MailgunSettings {
+                domain: "mg.example.com".into(),
+            },
+            SubscriberEmail::parse("newsletter@gmail.com".into()).unwrap(),
+            Secret::new("mailgun-api-key".into()),
+            std::time::Duration::from_secs(2),
+        )
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header(
+            "Authorization",
+            format!("Basic {}", base64::encode("api:mailgun-api-key")).as_str(),
+        ))
+        .and(header("Content-Type", "application/x-www-form-urlencoded"))
+        .and(path("/v3/mg.example.com/messages"))
+        .and(method("POST"))
+        .and(body_string_contains("to=longle%40gmail.com"))
+        .and(body_string_contains("text=Text"))
+        .and(body_string_contains("html=%3Cp%3EHTML%3C%2Fp%3E"))
+        .and(body_string_contains(
+            "h%3AList-Unsubscribe-Post=List-Unsubscribe%3DOne-Click",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "id": "<20220101.1@mg.example.com>",
+            "message": "Queued. Thank you."
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+        // Act
+        let outcome = email_client
+            .send_email_with_headers(
+                &email(),
+                "Subject",
+                "<p>HTML</p>",
+                "Text",
+                &[EmailHeader {
+                    name: "List-Unsubscribe-Post",
+                    value: "List-Unsubscribe=One-Click",
+                }],
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_reports_the_error_returned_by_mailgun() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "message": "'to' parameter is not a valid address. please check documentation"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), "Subject", "<p>HTML</p>", "Text")
+            .await;
+
+        // Assert
+        let error = outcome.unwrap_err();
+        assert!(format!("{:?}", error).contains("'to' parameter is not a valid address"));
+        assert_matches!(error, SendEmailError::Permanent(_));
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_transiently_if_mailgun_returns_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), "Subject", "<p>HTML</p>", "Text")
+            .await;
+
+        // Assert
+        assert_matches!(outcome, Err(SendEmailError::Transient(_)));
+    }
+}
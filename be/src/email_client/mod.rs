@@ -0,0 +1,155 @@
+mod failover;
+mod mailgun;
+mod postmark;
+mod sendgrid;
+mod ses;
+mod smtp;
+
+pub use failover::*;
+pub use mailgun::*;
+pub use postmark::*;
+pub use sendgrid::*;
+pub use ses::*;
+pub use smtp::*;
+
+use crate::domain::SubscriberEmail;
+use crate::rate_limiter::QuotaExhausted;
+use reqwest::StatusCode;
+use serde::Serialize;
+use std::time::Duration;
+
+#[derive(thiserror::Error, Debug)]
+pub enum SendEmailError {
+    // Timeouts, connection failures, 5xx and 429 responses: worth retrying later.
+    #[error("A transient error was encountered while sending an email.")]
+    Transient(#[source] anyhow::Error),
+    // The provider refused the email: sending it again won't help.
+    #[error("The email provider rejected the email.")]
+    Permanent(#[source] anyhow::Error),
+    // We stopped before going over the plan's daily limit: nothing was sent.
+    #[error(transparent)]
+    QuotaExhausted(#[from] QuotaExhausted),
+}
+
+impl SendEmailError {
+    pub fn is_transient(&self) -> bool {
+        matches!(self, SendEmailError::Transient(_))
+    }
+}
+
+/// Classify an error reply from a provider's HTTP API: server errors and
+/// throttling are worth retrying, anything else means the email was refused.
+fn http_error(status: StatusCode, details: &str) -> SendEmailError {
+    let error = anyhow::anyhow!("The provider replied {}: {}", status, details);
+    if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+        SendEmailError::Transient(error)
+    } else {
+        SendEmailError::Permanent(error)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Delay to wait before the next attempt, doubling after every retry.
+    pub fn backoff(&self, n_retries: u32) -> Duration {
+        let factor = 2u32.saturating_pow(n_retries.saturating_sub(1));
+        self.base_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+/// An extra header to set on an outgoing email, e.g. `List-Unsubscribe`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmailHeader<'a> {
+    pub name: &'a str,
+    pub value: &'a str,
+}
+
+#[derive(Debug)]
+pub struct OutgoingEmail<'a> {
+    pub recipient: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_body: &'a str,
+    pub text_body: &'a str,
+    pub headers: &'a [EmailHeader<'a>],
+}
+
+/// An email delivery backend, picked with the `provider` key of the
+/// `email_client` settings or of one of its `failover` entries.
+#[async_trait::async_trait]
+pub trait EmailSender: Send + Sync {
+    async fn send(&self, email: &OutgoingEmail<'_>) -> Result<(), SendEmailError>;
+
+    /// Send several emails, one at a time unless the provider has a batch API.
+    ///
+    /// The outer error means that the whole batch failed. Otherwise there is
+    /// one result per email, in order: some may have been refused while the
+    /// others went out.
+    async fn send_batch(
+        &self,
+        emails: &[OutgoingEmail<'_>],
+    ) -> Result<Vec<Result<(), SendEmailError>>, SendEmailError> {
+        let mut results = Vec::with_capacity(emails.len());
+        for email in emails {
+            results.push(self.send(email).await);
+        }
+        Ok(results)
+    }
+
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_body: &str,
+        text_body: &str,
+    ) -> Result<(), SendEmailError> {
+        self.send_email_with_headers(recipient, subject, html_body, text_body, &[])
+            .await
+    }
+
+    async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_body: &str,
+        text_body: &str,
+        headers: &[EmailHeader<'_>],
+    ) -> Result<(), SendEmailError> {
+        self.send(&OutgoingEmail {
+            recipient,
+            subject,
+            html_body,
+            text_body,
+            headers,
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::email_client::RetryPolicy;
+
+    #[test]
+    fn backoff_doubles_after_every_retry_up_to_the_maximum_delay() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: std::time::Duration::from_secs(1),
+            max_delay: std::time::Duration::from_secs(5),
+        };
+
+        assert_eq!(policy.backoff(1).as_secs(), 1);
+        assert_eq!(policy.backoff(2).as_secs(), 2);
+        assert_eq!(policy.backoff(3).as_secs(), 4);
+        assert_eq!(policy.backoff(4).as_secs(), 5);
+        assert_eq!(policy.backoff(u32::MAX).as_secs(), 5);
+    }
+}
@@ -0,0 +1,472 @@
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailHeader, EmailSender, OutgoingEmail, SendEmailError};
+use reqwest::{Client, StatusCode};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+/// Postmark accepts at most this many messages in a single batch.
+pub const MAX_BATCH_SIZE: usize = 500;
+
+#[derive(Debug, Clone)]
+pub struct PostmarkClient {
+    sender: SubscriberEmail,
+    base_url: String,
+    http_client: Client,
+    // We don't want to log this by accident
+    authorization_token: Secret<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    headers: &'a [EmailHeader<'a>],
+}
+
+// An entry of the response array of `/email/batch`, in the order of the request
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BatchMessageResponse {
+    error_code: i64,
+    message: String,
+}
+
+impl PostmarkClient {
+    pub fn new(
+        base_url: String,
+        sender: SubscriberEmail,
+        authorization_token: Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            base_url,
+            sender,
+            authorization_token,
+        }
+    }
+
+    fn request_body<'a>(&'a self, email: &OutgoingEmail<'a>) -> SendEmailRequest<'a> {
+        SendEmailRequest {
+            from: self.sender.as_ref(),
+            to: email.recipient.as_ref(),
+            subject: email.subject,
+            html_body: email.html_body,
+            text_body: email.text_body,
+            headers: email.headers,
+        }
+    }
+
+    async fn post(
+        &self,
+        path: &str,
+        body: &impl Serialize,
+    ) -> Result<reqwest::Response, SendEmailError> {
+        let url = format!("{}/{}", self.base_url, path);
+        let response = self
+            .http_client
+            .post(&url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| SendEmailError::Transient(e.into()))?;
+
+        let status = response.status();
+        match response.error_for_status() {
+            Ok(response) => Ok(response),
+            Err(e) if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS => {
+                Err(SendEmailError::Transient(e.into()))
+            }
+            Err(e) => Err(SendEmailError::Permanent(e.into())),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for PostmarkClient {
+    async fn send(&self, email: &OutgoingEmail<'_>) -> Result<(), SendEmailError> {
+        self.post("email", &self.request_body(email)).await?;
+
+        Ok(())
+    }
+
+    /// Up to [`MAX_BATCH_SIZE`] emails with a single call to `/email/batch`.
+    async fn send_batch(
+        &self,
+        emails: &[OutgoingEmail<'_>],
+    ) -> Result<Vec<Result<(), SendEmailError>>, SendEmailError> {
+        if emails.is_empty() {
+            return Ok(Vec::new());
+        }
+        if emails.len() > MAX_BATCH_SIZE {
+            return Err(SendEmailError::Permanent(anyhow::anyhow!(
+                "A batch holds at most {} emails, not {}.",
+                MAX_BATCH_SIZE,
+                emails.len()
+            )));
+        }
+        let request_body: Vec<_> = emails.iter().map(|e| self.request_body(e)).collect();
+        let response = self.post("email/batch", &request_body).await?;
+
+        // The batch went through: retrying it would send duplicates
+        let results: Vec<BatchMessageResponse> = response
+            .json()
+            .await
+            .map_err(|e| SendEmailError::Permanent(e.into()))?;
+        if results.len() != emails.len() {
+            return Err(SendEmailError::Permanent(anyhow::anyhow!(
+                "The provider returned {} results for a batch of {} emails.",
+                results.len(),
+                emails.len()
+            )));
+        }
+        Ok(results
+            .into_iter()
+            .map(|result| match result.error_code {
+                0 => Ok(()),
+                code => Err(SendEmailError::Permanent(anyhow::anyhow!(
+                    "Postmark error {}: {}",
+                    code,
+                    result.message
+                ))),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{
+        EmailHeader, EmailSender, OutgoingEmail, PostmarkClient, SendEmailError, MAX_BATCH_SIZE,
+    };
+    use claim::{assert_err, assert_matches, assert_ok};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Fake, Faker};
+    use secrecy::Secret;
+    use wiremock::matchers::any;
+    use wiremock::matchers::{body_partial_json, header, header_exists, method, path};
+    use wiremock::Request;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                // dbg!(&body);
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    /// Generate a random email subject
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    /// Generate a random email content
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    /// Generate a random subscriber email
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    /// Get a test instance of `EmailClient`
+    fn email_client(base_url: String) -> PostmarkClient {
+        // Much lower than 10s!
+        let timeout = std::time::Duration::from_secs(2);
+        PostmarkClient::new(base_url, email(), Secret::new(Faker.fake()), timeout)
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+    }
+
+    #[tokio::test]
+    async fn send_email_with_headers_includes_the_headers_in_the_request() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(body_partial_json(serde_json::json!({
+                "Headers": [{"Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click"}]
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email_with_headers(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                &[EmailHeader {
+                    name: "List-Unsubscribe-Post",
+                    value: "List-Unsubscribe=One-Click",
+                }],
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_server_returns_200() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_fail_if_the_server_returns_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_matches!(outcome, Err(SendEmailError::Transient(_)));
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_permanently_if_the_server_returns_422() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_matches!(outcome, Err(SendEmailError::Permanent(_)));
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(61)); // 61 seconds
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_matches!(outcome, Err(SendEmailError::Transient(_)));
+    }
+
+    #[tokio::test]
+    async fn send_batch_sends_every_email_in_a_single_request() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let (first, second) = (email(), email());
+        let (subject, content) = (subject(), content());
+        let emails = [&first, &second].map(|recipient| OutgoingEmail {
+            recipient,
+            subject: &subject,
+            html_body: &content,
+            text_body: &content,
+            headers: &[],
+        });
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(path("/email/batch"))
+            .and(method("POST"))
+            .and(body_partial_json(serde_json::json!([
+                {"To": first.as_ref()},
+                {"To": second.as_ref()}
+            ])))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {"ErrorCode": 0, "Message": "OK", "To": first.as_ref()},
+                {"ErrorCode": 0, "Message": "OK", "To": second.as_ref()}
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client.send_batch(&emails).await;
+
+        // Assert
+        let results = outcome.unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.is_ok()));
+    }
+
+    #[tokio::test]
+    async fn send_batch_reports_failures_per_email() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let (first, second) = (email(), email());
+        let (subject, content) = (subject(), content());
+        let emails = [&first, &second].map(|recipient| OutgoingEmail {
+            recipient,
+            subject: &subject,
+            html_body: &content,
+            text_body: &content,
+            headers: &[],
+        });
+
+        Mock::given(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {"ErrorCode": 0, "Message": "OK"},
+                {"ErrorCode": 406, "Message": "You tried to send to an inactive recipient."}
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let results = email_client.send_batch(&emails).await.unwrap();
+
+        // Assert
+        assert_ok!(&results[0]);
+        let error = results[1].as_ref().unwrap_err();
+        assert_matches!(error, SendEmailError::Permanent(_));
+        assert!(format!("{:?}", error).contains("inactive recipient"));
+    }
+
+    #[tokio::test]
+    async fn send_batch_fails_as_a_whole_if_the_server_returns_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipient = email();
+        let emails = [OutgoingEmail {
+            recipient: &recipient,
+            subject: "Subject",
+            html_body: "Body",
+            text_body: "Body",
+            headers: &[],
+        }];
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client.send_batch(&emails).await;
+
+        // Assert
+        assert_matches!(outcome, Err(SendEmailError::Transient(_)));
+    }
+
+    #[tokio::test]
+    async fn send_batch_rejects_oversized_batches_without_calling_the_server() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipient = email();
+        let emails: Vec<_> = (0..=MAX_BATCH_SIZE)
+            .map(|_| OutgoingEmail {
+                recipient: &recipient,
+                subject: "Subject",
+                html_body: "Body",
+                text_body: "Body",
+                headers: &[],
+            })
+            .collect();
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client.send_batch(&emails).await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+}
@@ -0,0 +1,237 @@
+use crate::domain::SubscriberEmail;
+use crate::email_client::{http_error, EmailSender, OutgoingEmail, SendEmailError};
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Deliver emails through SendGrid's v3 Mail Send API.
+#[derive(Debug, Clone)]
+pub struct SendGridClient {
+    sender: SubscriberEmail,
+    base_url: String,
+    http_client: Client,
+    api_key: Secret<String>,
+}
+
+#[derive(Serialize)]
+struct SendMailRequest<'a> {
+    personalizations: [Personalization<'a>; 1],
+    from: Address<'a>,
+    subject: &'a str,
+    // SendGrid wants the plain text first
+    content: [Content<'a>; 2],
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    headers: HashMap<&'a str, &'a str>,
+}
+
+#[derive(Serialize)]
+struct Personalization<'a> {
+    to: [Address<'a>; 1],
+}
+
+#[derive(Serialize)]
+struct Address<'a> {
+    email: &'a str,
+}
+
+#[derive(Serialize)]
+struct Content<'a> {
+    #[serde(rename = "type")]
+    content_type: &'a str,
+    value: &'a str,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    errors: Vec<ErrorDetails>,
+}
+
+#[derive(Deserialize)]
+struct ErrorDetails {
+    message: String,
+    field: Option<String>,
+}
+
+impl SendGridClient {
+    pub fn new(
+        base_url: String,
+        sender: SubscriberEmail,
+        api_key: Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            sender,
+            base_url,
+            http_client,
+            api_key,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for SendGridClient {
+    async fn send(&self, email: &OutgoingEmail<'_>) -> Result<(), SendEmailError> {
+        let request_body = SendMailRequest {
+            personalizations: [Personalization {
+                to: [Address {
+                    email: email.recipient.as_ref(),
+                }],
+            }],
+            from: Address {
+                email: self.sender.as_ref(),
+            },
+            subject: email.subject,
+            content: [
+                Content {
+                    content_type: "text/plain",
+                    value: email.text_body,
+                },
+                Content {
+                    content_type: "text/html",
+                    value: email.html_body,
+                },
+            ],
+            headers: email.headers.iter().map(|h| (h.name, h.value)).collect(),
+        };
+        let response = self
+            .http_client
+            .post(format!("{}/v3/mail/send", self.base_url))
+            .bearer_auth(self.api_key.expose_secret())
+            .json(&request_body)
+            .send()
+            .await
+            .map_err(|e| SendEmailError::Transient(e.into()))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let body = response.text().await.unwrap_or_default();
+        let details = match serde_json::from_str::<ErrorResponse>(&body) {
+            Ok(e) => e
+                .errors
+                .into_iter()
+                .map(|e| match e.field {
+                    Some(field) => format!("{} ({})", e.message, field),
+                    None => e.message,
+                })
+                .collect::<Vec<_>>()
+                .join("; "),
+            Err(_) => body,
+        };
+        Err(http_error(status, &details))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailHeader, EmailSender, SendEmailError, SendGridClient};
+    use claim::{assert_matches, assert_ok};
+    use secrecy::Secret;
+    use wiremock::matchers::{any, body_partial_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse("longle@gmail.com".into()).unwrap()
+    }
+
+    fn email_client(base_url: String) -> SendGridClient {
+        SendGridClient::new(
+            base_url,
+            SubscriberEmail::parse("newsletter@gmail.com".into()).unwrap(),
+            Secret::new("sendgrid-api-key".into()),
+            std::time::Duration::from_secs(2),
+        )
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header("Authorization", "Bearer sendgrid-api-key"))
+            .and(path("/v3/mail/send"))
+            .and(method("POST"))
+            .and(body_partial_json(serde_json::json!({
+                "personalizations": [{"to": [{"email": "longle@gmail.com"}]}],
+                "from": {"email": "newsletter@gmail.com"},
+                "subject": "Subject",
+                "content": [
+                    {"type": "text/plain", "value": "Text"},
+                    {"type": "text/html", "value": "<p>HTML</p>"}
+                ],
+                "headers": {"List-Unsubscribe-Post": "List-Unsubscribe=One-Click"}
+            })))
+            .respond_with(ResponseTemplate::new(202))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email_with_headers(
+                &email(),
+                "Subject",
+                "<p>HTML</p>",
+                "Text",
+                &[EmailHeader {
+                    name: "List-Unsubscribe-Post",
+                    value: "List-Unsubscribe=One-Click",
+                }],
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_reports_the_errors_returned_by_sendgrid() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "errors": [{"message": "Does not contain a valid address.", "field": "from.email"}]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), "Subject", "<p>HTML</p>", "Text")
+            .await;
+
+        // Assert
+        let error = outcome.unwrap_err();
+        assert!(format!("{:?}", error).contains("Does not contain a valid address. (from.email)"));
+        assert_matches!(error, SendEmailError::Permanent(_));
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_transiently_if_sendgrid_throttles_us() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), "Subject", "<p>HTML</p>", "Text")
+            .await;
+
+        // Assert
+        assert_matches!(outcome, Err(SendEmailError::Transient(_)));
+    }
+}
@@ -0,0 +1,378 @@
+use crate::domain::SubscriberEmail;
+use crate::email_client::{http_error, EmailSender, OutgoingEmail, SendEmailError};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::{Client, Url};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SesSettings {
+    // e.g. `eu-west-1`, matching the `base_url`: https://email.eu-west-1.amazonaws.com
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: Secret<String>,
+}
+
+/// Deliver emails through the SESv2 `SendEmail` API, with SigV4-signed requests.
+#[derive(Debug, Clone)]
+pub struct SesClient {
+    sender: SubscriberEmail,
+    url: Url,
+    settings: SesSettings,
+    http_client: Client,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from_email_address: &'a str,
+    destination: Destination<'a>,
+    content: EmailContent<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Destination<'a> {
+    to_addresses: [&'a str; 1],
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct EmailContent<'a> {
+    simple: SimpleMessage<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SimpleMessage<'a> {
+    subject: Data<'a>,
+    body: Body<'a>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<Header<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Body<'a> {
+    text: Data<'a>,
+    html: Data<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Data<'a> {
+    data: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Header<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    #[serde(alias = "Message")]
+    message: String,
+}
+
+impl SesClient {
+    pub fn new(
+        base_url: &str,
+        settings: &SesSettings,
+        sender: SubscriberEmail,
+        timeout: std::time::Duration,
+    ) -> Result<Self, anyhow::Error> {
+        let url = Url::parse(&format!("{}/v2/email/outbound-emails", base_url))?;
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Ok(Self {
+            sender,
+            url,
+            settings: settings.clone(),
+            http_client,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for SesClient {
+    async fn send(&self, email: &OutgoingEmail<'_>) -> Result<(), SendEmailError> {
+        let request_body = SendEmailRequest {
+            from_email_address: self.sender.as_ref(),
+            destination: Destination {
+                to_addresses: [email.recipient.as_ref()],
+            },
+            content: EmailContent {
+                simple: SimpleMessage {
+                    subject: Data {
+                        data: email.subject,
+                    },
+                    body: Body {
+                        text: Data {
+                            data: email.text_body,
+                        },
+                        html: Data {
+                            data: email.html_body,
+                        },
+                    },
+                    headers: email
+                        .headers
+                        .iter()
+                        .map(|h| Header {
+                            name: h.name,
+                            value: h.value,
+                        })
+                        .collect(),
+                },
+            },
+        };
+        // The signature covers the exact bytes we send
+        let payload =
+            serde_json::to_vec(&request_body).map_err(|e| SendEmailError::Permanent(e.into()))?;
+        let now = Utc::now();
+        let authorization = sign(&self.url, &payload, &self.settings, "ses", now);
+        let response = self
+            .http_client
+            .post(self.url.clone())
+            .header("Authorization", authorization)
+            .header("X-Amz-Date", amz_date(now))
+            .header("Content-Type", "application/json")
+            .body(payload)
+            .send()
+            .await
+            .map_err(|e| SendEmailError::Transient(e.into()))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        // e.g. `MessageRejected` or `TooManyRequestsException`
+        let error_type = response
+            .headers()
+            .get("x-amzn-ErrorType")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.split(':').next().unwrap_or(v).to_string());
+        let body = response.text().await.unwrap_or_default();
+        let message = match serde_json::from_str::<ErrorResponse>(&body) {
+            Ok(e) => e.message,
+            Err(_) => body,
+        };
+        let details = match error_type {
+            Some(error_type) => format!("{} {}", error_type, message),
+            None => message,
+        };
+        Err(http_error(status, &details))
+    }
+}
+
+fn amz_date(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// The `Authorization` header of an AWS Signature Version 4 POST request,
+/// signing the `host` and `x-amz-date` headers.
+fn sign(
+    url: &Url,
+    payload: &[u8],
+    settings: &SesSettings,
+    service: &str,
+    time: DateTime<Utc>,
+) -> String {
+    let amz_date = amz_date(time);
+    let date = time.format("%Y%m%d").to_string();
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    let canonical_request = format!(
+        "POST\n{}\n{}\nhost:{}\nx-amz-date:{}\n\nhost;x-amz-date\n{}",
+        url.path(),
+        url.query().unwrap_or_default(),
+        host,
+        amz_date,
+        hex::encode(Sha256::digest(payload))
+    );
+    let scope = format!("{}/{}/{}/aws4_request", date, settings.region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let secret = format!("AWS4{}", settings.secret_access_key.expose_secret());
+    let key = hmac_sha256(secret.as_bytes(), &date);
+    let key = hmac_sha256(&key, &settings.region);
+    let key = hmac_sha256(&key, service);
+    let key = hmac_sha256(&key, "aws4_request");
+    let signature = hex::encode(hmac_sha256(&key, &string_to_sign));
+
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders=host;x-amz-date, Signature={}",
+        settings.access_key_id, scope, signature
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sign;
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailHeader, EmailSender, SendEmailError, SesClient, SesSettings};
+    use chrono::{TimeZone, Utc};
+    use claim::{assert_matches, assert_ok};
+    use reqwest::Url;
+    use secrecy::Secret;
+    use wiremock::matchers::{any, body_partial_json, header_exists, header_regex, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn settings() -> SesSettings {
+        SesSettings {
+            region: "us-east-1".into(),
+            access_key_id: "AKIDEXAMPLE".into(),
+            secret_access_key: Secret::new("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".into()),
+        }
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse("longle@gmail.com".into()).unwrap()
+    }
+
+    fn email_client(base_url: &str) -> SesClient {
+        SesClient::new(
+            base_url,
+            &settings(),
+            SubscriberEmail::parse("newsletter@gmail.com".into()).unwrap(),
+            std::time::Duration::from_secs(2),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn requests_are_signed_with_sigv4() {
+        // "post-vanilla" from AWS' Signature Version 4 test suite
+        let url = Url::parse("https://example.amazonaws.com/").unwrap();
+        let time = Utc.ymd(2015, 8, 30).and_hms(12, 36, 0);
+
+        let authorization = sign(&url, b"", &settings(), "service", time);
+
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+            SignedHeaders=host;x-amz-date, \
+            Signature=5da7c1a2acd57cee7505fc6676e4e544621c30862966e37dddb68e92efbe5d6b"
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(&mock_server.uri());
+
+        // wiremock splits header values on commas, every part has to match
+        Mock::given(header_regex(
+            "Authorization",
+            r"^\s*(AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/\d{8}/us-east-1/ses/aws4_request|SignedHeaders=host;x-amz-date|Signature=[0-9a-f]{64})$",
+        ))
+        .and(header_exists("X-Amz-Date"))
+        .and(path("/v2/email/outbound-emails"))
+        .and(method("POST"))
+        .and(body_partial_json(serde_json::json!({
+            "FromEmailAddress": "newsletter@gmail.com",
+            "Destination": {"ToAddresses": ["longle@gmail.com"]},
+            "Content": {"Simple": {
+                "Subject": {"Data": "Subject"},
+                "Body": {"Text": {"Data": "Text"}, "Html": {"Data": "<p>HTML</p>"}},
+                "Headers": [{"Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click"}]
+            }}
+        })))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({"MessageId": "1"})),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+        // Act
+        let outcome = email_client
+            .send_email_with_headers(
+                &email(),
+                "Subject",
+                "<p>HTML</p>",
+                "Text",
+                &[EmailHeader {
+                    name: "List-Unsubscribe-Post",
+                    value: "List-Unsubscribe=One-Click",
+                }],
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_reports_the_error_returned_by_ses() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(&mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(
+                ResponseTemplate::new(400)
+                    .insert_header(
+                        "x-amzn-ErrorType",
+                        "MessageRejected:http://internal.amazon.com/coral/com.amazonaws.sesv2/",
+                    )
+                    .set_body_json(
+                        serde_json::json!({"message": "Email address is not verified."}),
+                    ),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), "Subject", "<p>HTML</p>", "Text")
+            .await;
+
+        // Assert
+        let error = outcome.unwrap_err();
+        assert!(format!("{:?}", error).contains("MessageRejected Email address is not verified."));
+        assert_matches!(error, SendEmailError::Permanent(_));
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_transiently_if_ses_throttles_us() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(&mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(
+                ResponseTemplate::new(429).set_body_json(
+                    serde_json::json!({"message": "Maximum sending rate exceeded."}),
+                ),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), "Subject", "<p>HTML</p>", "Text")
+            .await;
+
+        // Assert
+        assert_matches!(outcome, Err(SendEmailError::Transient(_)));
+    }
+}
@@ -0,0 +1,121 @@
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailSender, OutgoingEmail, SendEmailError};
+use anyhow::Context;
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::PoolConfig;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use serde_aux::field_attributes::deserialize_number_from_string;
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    // Plain text, only for a relay on the same host or network
+    None,
+    // Upgrade the connection with STARTTLS, refusing servers that don't offer it
+    Starttls,
+    // TLS from the start, usually on port 465
+    Tls,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub tls: SmtpTls,
+    // AUTH is skipped without a username
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+    pub max_connections: u32,
+}
+
+/// Deliver emails through an SMTP relay, keeping a pool of open connections.
+#[derive(Clone)]
+pub struct SmtpClient {
+    sender: Mailbox,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpClient {
+    pub fn new(
+        settings: &SmtpSettings,
+        sender: SubscriberEmail,
+        timeout: std::time::Duration,
+    ) -> Result<Self, anyhow::Error> {
+        let builder = match settings.tls {
+            SmtpTls::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host)
+            }
+            SmtpTls::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)
+                    .context("Failed to set up STARTTLS for the SMTP relay.")?
+            }
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host)
+                .context("Failed to set up TLS for the SMTP relay.")?,
+        };
+        let mut builder = builder
+            .port(settings.port)
+            .timeout(Some(timeout))
+            .pool_config(PoolConfig::new().max_size(settings.max_connections.max(1)));
+        if let Some(username) = &settings.username {
+            let password = settings
+                .password
+                .as_ref()
+                .map(|p| p.expose_secret().clone())
+                .unwrap_or_default();
+            builder = builder.credentials(Credentials::new(username.clone(), password));
+        }
+        let sender = sender
+            .as_ref()
+            .parse()
+            .context("The sender email is not a valid mailbox.")?;
+
+        Ok(Self {
+            sender,
+            transport: builder.build(),
+        })
+    }
+
+    fn message(&self, email: &OutgoingEmail<'_>) -> Result<Message, anyhow::Error> {
+        let recipient: Mailbox = email
+            .recipient
+            .as_ref()
+            .parse()
+            .context("The recipient is not a valid mailbox.")?;
+        let mut builder = Message::builder()
+            .from(self.sender.clone())
+            .to(recipient)
+            .subject(email.subject);
+        for header in email.headers {
+            let name = HeaderName::new_from_ascii(header.name.to_string())
+                .with_context(|| format!("{} is not a valid header name.", header.name))?;
+            builder = builder.raw_header(HeaderValue::new(name, header.value.to_string()));
+        }
+        let message = builder
+            .multipart(MultiPart::alternative_plain_html(
+                email.text_body.to_string(),
+                email.html_body.to_string(),
+            ))
+            .context("Failed to build a multipart email.")?;
+
+        Ok(message)
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for SmtpClient {
+    async fn send(&self, email: &OutgoingEmail<'_>) -> Result<(), SendEmailError> {
+        let message = self.message(email).map_err(SendEmailError::Permanent)?;
+        match self.transport.send(message).await {
+            Ok(_) => Ok(()),
+            // 5xx replies: the relay won't take the email
+            Err(e) if e.is_permanent() => Err(SendEmailError::Permanent(e.into())),
+            // 4xx replies, timeouts and connection failures
+            Err(e) => Err(SendEmailError::Transient(e.into())),
+        }
+    }
+}
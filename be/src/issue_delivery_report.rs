@@ -0,0 +1,148 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Debug, serde::Serialize)]
+pub struct DeliverySummary {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub published_at: DateTime<Utc>,
+    pub n_recipients: i64,
+    pub n_sent: i64,
+    pub n_pending: i64,
+    pub n_failed: i64,
+    pub n_skipped: i64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct FailedRecipient {
+    pub subscriber_email: String,
+    pub n_attempts: i16,
+    pub last_error: String,
+    pub failed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct DeliveryReport {
+    #[serde(flatten)]
+    pub summary: DeliverySummary,
+    pub failed_recipients: Vec<FailedRecipient>,
+}
+
+struct SummaryRow {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+    n_sent: i64,
+    n_pending: i64,
+    n_failed: i64,
+    n_skipped: i64,
+}
+
+impl From<SummaryRow> for DeliverySummary {
+    fn from(r: SummaryRow) -> Self {
+        // Every recipient is in exactly one of the four states
+        let n_recipients = r.n_sent + r.n_pending + r.n_failed + r.n_skipped;
+        Self {
+            newsletter_issue_id: r.newsletter_issue_id,
+            title: r.title,
+            published_at: r.published_at,
+            n_recipients,
+            n_sent: r.n_sent,
+            n_pending: r.n_pending,
+            n_failed: r.n_failed,
+            n_skipped: r.n_skipped,
+        }
+    }
+}
+
+#[tracing::instrument(name = "Get delivery summaries", skip(pool))]
+pub async fn get_delivery_summaries(pool: &PgPool) -> Result<Vec<DeliverySummary>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        SummaryRow,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.published_at as "published_at!",
+            (SELECT COUNT(*) FROM issue_delivery_log l
+                WHERE l.newsletter_issue_id = i.newsletter_issue_id AND l.outcome = 'sent'
+            ) as "n_sent!",
+            (SELECT COUNT(*) FROM issue_delivery_queue q
+                WHERE q.newsletter_issue_id = i.newsletter_issue_id
+            ) as "n_pending!",
+            (SELECT COUNT(*) FROM issue_delivery_dead_letters d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id
+            ) as "n_failed!",
+            (SELECT COUNT(*) FROM issue_delivery_log l
+                WHERE l.newsletter_issue_id = i.newsletter_issue_id AND l.outcome = 'skipped'
+            ) as "n_skipped!"
+        FROM newsletter_issues i
+            WHERE i.status = 'published'
+            ORDER BY i.published_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve delivery summaries.")?;
+
+    Ok(rows.into_iter().map(DeliverySummary::from).collect())
+}
+
+#[tracing::instrument(name = "Get delivery report", skip(pool))]
+pub async fn get_delivery_report(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<DeliveryReport>, anyhow::Error> {
+    let row = sqlx::query_as!(
+        SummaryRow,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.published_at as "published_at!",
+            (SELECT COUNT(*) FROM issue_delivery_log l
+                WHERE l.newsletter_issue_id = i.newsletter_issue_id AND l.outcome = 'sent'
+            ) as "n_sent!",
+            (SELECT COUNT(*) FROM issue_delivery_queue q
+                WHERE q.newsletter_issue_id = i.newsletter_issue_id
+            ) as "n_pending!",
+            (SELECT COUNT(*) FROM issue_delivery_dead_letters d
+                WHERE d.newsletter_issue_id = i.newsletter_issue_id
+            ) as "n_failed!",
+            (SELECT COUNT(*) FROM issue_delivery_log l
+                WHERE l.newsletter_issue_id = i.newsletter_issue_id AND l.outcome = 'skipped'
+            ) as "n_skipped!"
+        FROM newsletter_issues i
+            WHERE i.newsletter_issue_id = $1 AND i.status = 'published'
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve a delivery summary.")?;
+    let summary = match row {
+        Some(row) => DeliverySummary::from(row),
+        None => return Ok(None),
+    };
+
+    let failed_recipients = sqlx::query_as!(
+        FailedRecipient,
+        r#"
+        SELECT subscriber_email, n_attempts, last_error, failed_at
+            FROM issue_delivery_dead_letters
+            WHERE newsletter_issue_id = $1
+            ORDER BY failed_at DESC
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve failed recipients.")?;
+
+    Ok(Some(DeliveryReport {
+        summary,
+        failed_recipients,
+    }))
+}
@@ -0,0 +1,568 @@
+use crate::configuration::Settings;
+use crate::domain::{DeliveryFrequency, SubscriberEmail};
+use crate::email_client::{EmailHeader, EmailSender, RetryPolicy, SendEmailError};
+use crate::lists::DEFAULT_LIST_SLUG;
+use crate::merge_tags::{IssueTemplate, MergeContext};
+use crate::routes::{
+    add_tracking_pixel, issue_preferences_url, open_tracking_url, unsubscribe_url,
+};
+use crate::startup::get_connection_pool;
+use crate::suppressions::get_suppression;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use secrecy::Secret;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{field::display, Span};
+use uuid::Uuid;
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(
+        newsletter_issue_id=tracing::field::Empty,
+        subscriber_email=tracing::field::Empty
+    ),
+    err
+)]
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &dyn EmailSender,
+    retry_policy: &RetryPolicy,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let task = dequeue_task(pool).await?;
+    if task.is_none() {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    }
+    let (mut transaction, task) = task.unwrap();
+    Span::current()
+        .record("newsletter_issue_id", &display(task.newsletter_issue_id))
+        .record("subscriber_email", &display(&task.subscriber_email));
+
+    match SubscriberEmail::parse(task.subscriber_email.clone()) {
+        Ok(email) => {
+            let issue = get_issue(pool, task.newsletter_issue_id).await?;
+            let subscriber = match get_subscriber(pool, email.as_ref()).await? {
+                Some(subscriber) => subscriber,
+                None => {
+                    let details = "The subscriber no longer exists.";
+                    complete_task(transaction, &task, DeliveryOutcome::Skipped, Some(details))
+                        .await?;
+                    return Ok(ExecutionOutcome::TaskCompleted);
+                }
+            };
+            // They may have unsubscribed after the issue was queued
+            if subscriber.status != "confirmed" {
+                let details = "The subscriber has unsubscribed.";
+                complete_task(transaction, &task, DeliveryOutcome::Skipped, Some(details)).await?;
+                return Ok(ExecutionOutcome::TaskCompleted);
+            }
+            // Or bounced, or complained
+            if let Some(suppression) = get_suppression(pool, email.as_ref()).await? {
+                let details = format!(
+                    "The address is suppressed ({}): {}",
+                    suppression.source, suppression.reason
+                );
+                complete_task(transaction, &task, DeliveryOutcome::Skipped, Some(&details)).await?;
+                return Ok(ExecutionOutcome::TaskCompleted);
+            }
+            let frequency = DeliveryFrequency::parse(&subscriber.delivery_frequency)
+                .unwrap_or(DeliveryFrequency::EveryIssue);
+            // Too soon for their delivery frequency: the issue waits for the
+            // next slot rather than being dropped
+            if let Some(interval) = frequency.min_interval() {
+                if let Some(last_sent_at) = last_issue_sent_at(pool, email.as_ref()).await? {
+                    let next_slot = last_sent_at + interval;
+                    if next_slot > Utc::now() {
+                        tracing::info!(
+                            "The subscriber asked for {}, postponing the delivery until {}.",
+                            frequency.label().to_lowercase(),
+                            next_slot
+                        );
+                        postpone_task(transaction, &task, next_slot).await?;
+                        return Ok(ExecutionOutcome::TaskCompleted);
+                    }
+                }
+            }
+            let unsubscribe_url = unsubscribe_url(base_url, hmac_secret, subscriber.id);
+            // Written with the outcome of the task, so that retries reuse the link
+            let preferences_url = issue_preferences_url(
+                &mut transaction,
+                base_url,
+                hmac_secret,
+                subscriber.id,
+                Some(task.newsletter_issue_id),
+            )
+            .await?;
+            let context = MergeContext {
+                name: &subscriber.name,
+                email: email.as_ref(),
+                unsubscribe_url: &unsubscribe_url,
+                preferences_url: &preferences_url,
+            };
+            let (subject, mut html_content, text_content) = issue.render(base_url, &context);
+            // Plain-text clients cannot load images: only the HTML body is tracked
+            if issue.track_opens {
+                let tracking_url = open_tracking_url(
+                    base_url,
+                    hmac_secret,
+                    task.newsletter_issue_id,
+                    subscriber.id,
+                );
+                html_content = add_tracking_pixel(&html_content, &tracking_url);
+            }
+            // One-click unsubscribe, as described in RFC 8058
+            let list_unsubscribe = format!("<{}>", unsubscribe_url);
+            let headers = [
+                EmailHeader {
+                    name: "List-Unsubscribe",
+                    value: &list_unsubscribe,
+                },
+                EmailHeader {
+                    name: "List-Unsubscribe-Post",
+                    value: "List-Unsubscribe=One-Click",
+                },
+            ];
+            match email_client
+                .send_email_with_headers(&email, &subject, &html_content, &text_content, &headers)
+                .await
+            {
+                Ok(()) => complete_task(transaction, &task, DeliveryOutcome::Sent, None).await?,
+                Err(e) => handle_failed_delivery(transaction, &task, e, retry_policy).await?,
+            }
+        }
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Skipping a confirmed subscriber. \
+                Their stored contact details are invalid",
+            );
+            complete_task(transaction, &task, DeliveryOutcome::Skipped, Some(&e)).await?;
+        }
+    }
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+async fn handle_failed_delivery(
+    transaction: PgTransaction,
+    task: &DeliveryTask,
+    error: SendEmailError,
+    retry_policy: &RetryPolicy,
+) -> Result<(), anyhow::Error> {
+    // Nothing was sent: wait for the quota to reset without using up an attempt
+    if let SendEmailError::QuotaExhausted(e) = &error {
+        tracing::warn!(
+            error.message = %e,
+            "Postponing the delivery of an issue until {}.",
+            e.resets_at
+        );
+        return postpone_task(transaction, task, e.resets_at).await;
+    }
+    let n_attempts = task.n_retries as u32 + 1;
+    if error.is_transient() && n_attempts < retry_policy.max_attempts {
+        let delay = retry_policy.backoff(n_attempts);
+        tracing::warn!(
+            error.cause_chain = ?error,
+            error.message = %error,
+            "Failed to deliver issue to a confirmed subscriber. \
+            Retrying in {:?}.",
+            delay
+        );
+        retry_task(transaction, task, delay).await
+    } else {
+        tracing::error!(
+            error.cause_chain = ?error,
+            error.message = %error,
+            "Failed to deliver issue to a confirmed subscriber after {} attempt(s). \
+            Moving it to the dead letters.",
+            n_attempts
+        );
+        // Keep the provider's explanation next to our own description of the failure
+        let last_error = match std::error::Error::source(&error) {
+            Some(source) => format!("{} {}", error, source),
+            None => error.to_string(),
+        };
+        move_task_to_dead_letters(transaction, task, n_attempts, &last_error).await
+    }
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+struct DeliveryTask {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: i16,
+}
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_task(
+    pool: &PgPool,
+) -> Result<Option<(PgTransaction, DeliveryTask)>, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    // Rows locked by another worker are skipped, so that several
+    // replicas can drain the queue concurrently.
+    let task = sqlx::query_as!(
+        DeliveryTask,
+        r#"
+        SELECT newsletter_issue_id, subscriber_email, n_retries
+            FROM issue_delivery_queue
+            WHERE execute_after <= now()
+            FOR UPDATE
+            SKIP LOCKED
+            LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+
+    Ok(task.map(|task| (transaction, task)))
+}
+
+#[derive(Debug, Clone, Copy)]
+enum DeliveryOutcome {
+    Sent,
+    Skipped,
+}
+
+impl DeliveryOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryOutcome::Sent => "sent",
+            DeliveryOutcome::Skipped => "skipped",
+        }
+    }
+}
+
+/// Remove a task from the queue, keeping a record of how it went for the delivery report.
+#[tracing::instrument(skip_all)]
+async fn complete_task(
+    mut transaction: PgTransaction,
+    task: &DeliveryTask,
+    outcome: DeliveryOutcome,
+    details: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+            WHERE
+                newsletter_issue_id = $1 AND
+                subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_log (
+            newsletter_issue_id,
+            subscriber_email,
+            outcome,
+            details,
+            recorded_at
+        )
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+            SET
+                outcome = EXCLUDED.outcome,
+                details = EXCLUDED.details,
+                recorded_at = EXCLUDED.recorded_at
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        outcome.as_str(),
+        details
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn retry_task(
+    mut transaction: PgTransaction,
+    task: &DeliveryTask,
+    delay: Duration,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+            SET
+                n_retries = n_retries + 1,
+                execute_after = now() + make_interval(secs => $3)
+            WHERE
+                newsletter_issue_id = $1 AND
+                subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        delay.as_secs_f64()
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn postpone_task(
+    mut transaction: PgTransaction,
+    task: &DeliveryTask,
+    execute_after: DateTime<Utc>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+            SET execute_after = $3
+            WHERE
+                newsletter_issue_id = $1 AND
+                subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        execute_after
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn move_task_to_dead_letters(
+    mut transaction: PgTransaction,
+    task: &DeliveryTask,
+    n_attempts: u32,
+    last_error: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+            WHERE
+                newsletter_issue_id = $1 AND
+                subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_dead_letters (
+            newsletter_issue_id,
+            subscriber_email,
+            n_attempts,
+            last_error,
+            failed_at
+        )
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+            SET
+                n_attempts = EXCLUDED.n_attempts,
+                last_error = EXCLUDED.last_error,
+                failed_at = EXCLUDED.failed_at
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        n_attempts as i16,
+        last_error
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+struct NewsletterIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+    slug: String,
+    track_opens: bool,
+    // Whether the archive shows it, see `crate::routes::issues_archive`
+    archived: bool,
+}
+
+impl NewsletterIssue {
+    /// Personalise the issue for one recipient, then point them to the archived copy
+    /// at the top of both bodies, if there is one, and to the unsubscribe link at the
+    /// bottom, unless the issue already has one.
+    fn render(&self, base_url: &str, context: &MergeContext) -> (String, String, String) {
+        let (subject, html_content, text_content) =
+            match IssueTemplate::parse(&self.title, &self.html_content, &self.text_content) {
+                Ok(template) => template.render(context),
+                // Issues stored before merge tags existed were never validated
+                Err(_) => (
+                    self.title.clone(),
+                    self.html_content.clone(),
+                    self.text_content.clone(),
+                ),
+            };
+        let permalink = format!("{}/issues/{}", base_url, self.slug);
+        let unsubscribe_url = context.unsubscribe_url;
+        let mut html_content = if self.archived {
+            format!(
+                r#"<p><a href="{}">View this issue in your browser</a></p>{}"#,
+                permalink, html_content
+            )
+        } else {
+            html_content
+        };
+        if !html_content.contains(&encode_minimal(unsubscribe_url)) {
+            html_content.push_str(&format!(
+                r#"<p>Don't want to receive these emails? <a href="{}">Unsubscribe</a> or <a href="{}">manage your preferences</a>.</p>"#,
+                encode_minimal(unsubscribe_url),
+                encode_minimal(context.preferences_url)
+            ));
+        }
+        let mut text_content = if self.archived {
+            format!(
+                "View this issue in your browser: {}\n\n{}",
+                permalink, text_content
+            )
+        } else {
+            text_content
+        };
+        if !text_content.contains(unsubscribe_url) {
+            text_content.push_str(&format!(
+                "\n\n--\nDon't want to receive these emails? Unsubscribe: {}\n\
+                Manage your preferences: {}",
+                unsubscribe_url, context.preferences_url
+            ));
+        }
+        (subject, html_content, text_content)
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT
+            title,
+            text_content,
+            html_content,
+            slug,
+            track_opens,
+            segment_id IS NULL AND topic_id IS NULL AND EXISTS (
+                SELECT 1
+                    FROM newsletter_issue_lists
+                    JOIN lists USING (list_id)
+                    WHERE newsletter_issue_id = $1 AND lists.slug = $2
+            ) AS "archived!"
+            FROM newsletter_issues
+            WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+        DEFAULT_LIST_SLUG
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(issue)
+}
+
+struct Subscriber {
+    id: Uuid,
+    name: String,
+    status: String,
+    delivery_frequency: String,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_subscriber(pool: &PgPool, email: &str) -> Result<Option<Subscriber>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, name, status, delivery_frequency
+            FROM subscriptions
+            WHERE email = $1
+        "#,
+        email
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(subscriber)
+}
+
+/// When the subscriber was last sent an issue, if ever.
+#[tracing::instrument(skip(pool))]
+async fn last_issue_sent_at(
+    pool: &PgPool,
+    email: &str,
+) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT MAX(recorded_at) AS last_sent_at
+            FROM issue_delivery_log
+            WHERE subscriber_email = $1 AND outcome = 'sent'
+        "#,
+        email
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row.last_sent_at)
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    email_client: Arc<dyn EmailSender>,
+    retry_policy: RetryPolicy,
+    base_url: String,
+    hmac_secret: Secret<String>,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_execute_task(
+            &pool,
+            email_client.as_ref(),
+            &retry_policy,
+            &base_url,
+            &hmac_secret,
+        )
+        .await
+        {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    email_client: Arc<dyn EmailSender>,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    let retry_policy = configuration.email_client.retry_policy();
+    worker_loop(
+        connection_pool,
+        email_client,
+        retry_policy,
+        configuration.application.base_url,
+        configuration.application.hmac_secret,
+    )
+    .await
+}
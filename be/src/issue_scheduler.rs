@@ -0,0 +1,81 @@
+use crate::configuration::Settings;
+use crate::issue_delivery_worker::ExecutionOutcome;
+use crate::routes::{enqueue_delivery_tasks, prune_preference_tokens};
+use crate::startup::get_connection_pool;
+use sqlx::PgPool;
+use std::time::{Duration, Instant};
+use tracing::{field::display, Span};
+
+/// Publish the next scheduled issue whose `send_at` has passed, if any.
+#[tracing::instrument(skip_all, fields(newsletter_issue_id=tracing::field::Empty), err)]
+pub async fn try_publish_due_issue(pool: &PgPool) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    // The row lock is held until the deliveries are enqueued: another instance
+    // polling at the same time skips this issue instead of publishing it twice.
+    let issue = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id
+            FROM newsletter_issues
+            WHERE status = 'scheduled' AND send_at <= now()
+            ORDER BY send_at
+            FOR UPDATE
+            SKIP LOCKED
+            LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    let newsletter_issue_id = match issue {
+        Some(issue) => issue.newsletter_issue_id,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    Span::current().record("newsletter_issue_id", &display(newsletter_issue_id));
+
+    enqueue_delivery_tasks(&mut transaction, newsletter_issue_id).await?;
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+            SET status = 'published', published_at = now()
+            WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// How often expired and revoked preference tokens are deleted.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+async fn scheduler_loop(pool: PgPool) -> Result<(), anyhow::Error> {
+    let mut next_prune_at = Instant::now();
+    loop {
+        if Instant::now() >= next_prune_at {
+            match prune_preference_tokens(&pool).await {
+                Ok(n_pruned) => tracing::info!(n_pruned, "Pruned preference tokens"),
+                Err(e) => tracing::error!(
+                    error.cause_chain = ?e,
+                    "Failed to prune preference tokens"
+                ),
+            }
+            next_prune_at = Instant::now() + PRUNE_INTERVAL;
+        }
+        match try_publish_due_issue(&pool).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+pub async fn run_scheduler_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    scheduler_loop(connection_pool).await
+}
@@ -2,8 +2,18 @@ pub mod authentication;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
+pub mod idempotency;
+pub mod issue_delivery_report;
+pub mod issue_delivery_worker;
+pub mod issue_scheduler;
+pub mod lists;
+pub mod markdown;
+pub mod merge_tags;
+pub mod rate_limiter;
 pub mod routes;
+pub mod segments;
 pub mod session_state;
 pub mod startup;
+pub mod suppressions;
 pub mod telemetry;
 pub mod utils;
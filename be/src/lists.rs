@@ -0,0 +1,73 @@
+use anyhow::Context;
+use sqlx::{Executor, PgPool, Postgres};
+use uuid::Uuid;
+
+/// The list subscription forms and publications target when none is chosen.
+pub const DEFAULT_LIST_SLUG: &str = "newsletter";
+
+#[derive(Debug)]
+pub struct List {
+    pub list_id: Uuid,
+    pub name: String,
+    pub slug: String,
+}
+
+#[tracing::instrument(name = "Get a list by slug", skip(executor))]
+pub async fn get_list_by_slug<'a, E>(executor: E, slug: &str) -> Result<Option<List>, anyhow::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let list = sqlx::query_as!(
+        List,
+        r#"SELECT list_id, name, slug FROM lists WHERE slug = $1"#,
+        slug
+    )
+    .fetch_optional(executor)
+    .await
+    .context("Failed to perform a query to retrieve a list.")?;
+
+    Ok(list)
+}
+
+#[tracing::instrument(name = "Get the default list", skip(executor))]
+pub async fn get_default_list<'a, E>(executor: E) -> Result<List, anyhow::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    get_list_by_slug(executor, DEFAULT_LIST_SLUG)
+        .await?
+        .context("The default list is missing.")
+}
+
+#[tracing::instrument(name = "Get lists", skip(pool))]
+pub async fn get_lists(pool: &PgPool) -> Result<Vec<List>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        List,
+        r#"SELECT list_id, name, slug FROM lists ORDER BY name"#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve lists.")?;
+
+    Ok(lists)
+}
+
+/// Check that every list exists, returning the first unknown one otherwise.
+#[tracing::instrument(name = "Find unknown lists", skip(pool))]
+pub async fn find_unknown_list(
+    pool: &PgPool,
+    list_ids: &[Uuid],
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let known: Vec<Uuid> = sqlx::query!(
+        r#"SELECT list_id FROM lists WHERE list_id = ANY($1)"#,
+        list_ids
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to check lists.")?
+    .into_iter()
+    .map(|r| r.list_id)
+    .collect();
+
+    Ok(list_ids.iter().find(|id| !known.contains(id)).copied())
+}
@@ -1,6 +1,10 @@
 use email_newsletter::configuration::get_configuration;
+use email_newsletter::issue_delivery_worker::run_worker_until_stopped;
+use email_newsletter::issue_scheduler::run_scheduler_until_stopped;
 use email_newsletter::startup::Application;
 use email_newsletter::telemetry::{get_subscriber, init_subscriber};
+use std::fmt::{Debug, Display};
+use tokio::task::JoinError;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -11,8 +15,45 @@ async fn main() -> anyhow::Result<()> {
     );
     init_subscriber(subscriber);
     let configuration = get_configuration().expect("Failed to read configuration.");
-    let application = Application::build(configuration).await?;
-    application.run_until_stopped().await?;
+    // A single client, so that the API and the worker share the sending limits
+    let email_client = configuration.email_client.clone().client();
+    let application = Application::build(configuration.clone(), email_client.clone()).await?;
+    let application_task = tokio::spawn(application.run_until_stopped());
+    let worker_task = tokio::spawn(run_worker_until_stopped(
+        configuration.clone(),
+        email_client,
+    ));
+    let scheduler_task = tokio::spawn(run_scheduler_until_stopped(configuration));
+
+    tokio::select! {
+        o = application_task => report_exit("API", o),
+        o = worker_task => report_exit("Background worker", o),
+        o = scheduler_task => report_exit("Issue scheduler", o),
+    };
 
     Ok(())
 }
+
+fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
+    match outcome {
+        Ok(Ok(())) => {
+            tracing::info!("{} has exited", task_name)
+        }
+        Ok(Err(e)) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} failed",
+                task_name
+            )
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} task failed to complete",
+                task_name
+            )
+        }
+    }
+}
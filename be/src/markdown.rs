@@ -0,0 +1,404 @@
+use pulldown_cmark::{html, Event, HeadingLevel, LinkType, Options, Parser, Tag};
+
+fn parser(markdown: &str) -> Parser<'_, '_> {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TASKLISTS);
+    Parser::new_ext(markdown, options)
+}
+
+/// Merge tags are swapped for placeholders while the Markdown is rendered, and
+/// put back verbatim afterwards: link destinations would be percent-encoded,
+/// and a tag with spaces such as `({{ unsubscribe_url }})` is not a link at all.
+struct MergeTagPlaceholders<'a> {
+    prefix: String,
+    tags: Vec<&'a str>,
+}
+
+impl<'a> MergeTagPlaceholders<'a> {
+    /// The Markdown with its merge tags replaced by plain alphanumeric words.
+    /// Unclosed tags are left alone, they are reported when the issue is parsed.
+    fn protect(markdown: &'a str) -> (Self, String) {
+        // A prefix that cannot be mistaken for a word of the issue
+        let mut prefix = String::from("mergetag");
+        while markdown.contains(&prefix) {
+            prefix.push('x');
+        }
+        let mut placeholders = Self {
+            prefix,
+            tags: Vec::new(),
+        };
+        let mut protected = String::with_capacity(markdown.len());
+        let mut rest = markdown;
+        while let Some(start) = rest.find("{{") {
+            let end = match rest[start..].find("}}") {
+                Some(end) => start + end + 2,
+                None => break,
+            };
+            protected.push_str(&rest[..start]);
+            protected.push_str(&placeholders.placeholder(placeholders.tags.len()));
+            placeholders.tags.push(&rest[start..end]);
+            rest = &rest[end..];
+        }
+        protected.push_str(rest);
+        (placeholders, protected)
+    }
+
+    // The trailing `z` keeps `mergetag1z` from matching the start of `mergetag12z`
+    fn placeholder(&self, index: usize) -> String {
+        format!("{}{}z", self.prefix, index)
+    }
+
+    fn restore(&self, rendered: String) -> String {
+        self.tags
+            .iter()
+            .enumerate()
+            .fold(rendered, |rendered, (index, tag)| {
+                rendered.replace(&self.placeholder(index), tag)
+            })
+    }
+}
+
+/// Render Markdown to HTML that is safe to mail and to serve from the archive:
+/// scripts, event handlers and other dangerous markup are stripped.
+pub fn render_html(markdown: &str) -> String {
+    let (placeholders, markdown) = MergeTagPlaceholders::protect(markdown);
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, parser(&markdown));
+    placeholders.restore(ammonia::clean(&unsafe_html))
+}
+
+/// Render Markdown to a plain-text email body.
+///
+/// Headings are underlined, lists keep their markers and links are replaced
+/// by numbered references, listed as footnotes at the end of the text.
+pub fn render_text(markdown: &str) -> String {
+    let (placeholders, markdown) = MergeTagPlaceholders::protect(markdown);
+    let mut renderer = TextRenderer::default();
+    for event in parser(&markdown) {
+        renderer.handle(event);
+    }
+    placeholders.restore(renderer.finish())
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Break {
+    None,
+    Line,
+    Blank,
+}
+
+struct TextRenderer {
+    out: String,
+    // Separator to write before the next piece of content
+    pending: Break,
+    quote_depth: usize,
+    // One entry per open list: the number of the next item, if ordered
+    lists: Vec<Option<u64>>,
+    // The first block of a list item or a quote follows its marker directly
+    at_container_start: bool,
+    in_code_block: bool,
+    heading_start: usize,
+    links: Vec<String>,
+}
+
+impl Default for TextRenderer {
+    fn default() -> Self {
+        Self {
+            out: String::new(),
+            pending: Break::Line,
+            quote_depth: 0,
+            lists: Vec::new(),
+            at_container_start: false,
+            in_code_block: false,
+            heading_start: 0,
+            links: Vec::new(),
+        }
+    }
+}
+
+impl TextRenderer {
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Start(Tag::Paragraph) => self.start_block(),
+            Event::Start(Tag::Heading(level, _, _)) => {
+                self.start_block();
+                self.flush();
+                if level >= HeadingLevel::H3 {
+                    self.out.push_str(&"#".repeat(level as usize));
+                    self.out.push(' ');
+                }
+                self.heading_start = self.out.len();
+            }
+            Event::End(Tag::Heading(level, _, _)) => {
+                let underline = match level {
+                    HeadingLevel::H1 => "=",
+                    HeadingLevel::H2 => "-",
+                    _ => return,
+                };
+                let width = self.out[self.heading_start..].chars().count();
+                self.line_break();
+                self.write(&underline.repeat(width));
+            }
+            Event::Start(Tag::BlockQuote) => {
+                // The blank line before a quote is not part of it
+                self.start_block();
+                if self.pending == Break::Blank && !self.out.is_empty() {
+                    self.out.push('\n');
+                    self.out.push_str(self.prefix().trim_end());
+                    self.pending = Break::Line;
+                }
+                self.quote_depth += 1;
+                self.at_container_start = true;
+            }
+            Event::End(Tag::BlockQuote) => {
+                self.quote_depth -= 1;
+                self.pending = Break::Blank;
+            }
+            Event::Start(Tag::CodeBlock(_)) => {
+                self.start_block();
+                self.in_code_block = true;
+            }
+            Event::End(Tag::CodeBlock(_)) => self.in_code_block = false,
+            Event::Start(Tag::List(first_number)) => {
+                if self.lists.is_empty() {
+                    self.start_block();
+                }
+                self.lists.push(first_number);
+            }
+            Event::End(Tag::List(_)) => {
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.pending = Break::Blank;
+                }
+            }
+            Event::Start(Tag::Item) => self.start_item(),
+            Event::End(Tag::Link(link_type, destination, _)) => match link_type {
+                // The text of an autolink already is the address
+                LinkType::Autolink | LinkType::Email => {}
+                _ => self.write_reference(&destination),
+            },
+            Event::Start(Tag::Image(..)) => self.write("[image: "),
+            Event::End(Tag::Image(_, destination, _)) => {
+                self.write("]");
+                self.write_reference(&destination);
+            }
+            Event::Text(text) if self.in_code_block => {
+                for line in text.lines() {
+                    self.write(&format!("    {}", line));
+                    self.line_break();
+                }
+            }
+            Event::Text(text) | Event::Code(text) => self.write(&text),
+            Event::SoftBreak | Event::HardBreak => self.line_break(),
+            Event::Rule => {
+                self.start_block();
+                self.write("----------");
+            }
+            Event::TaskListMarker(checked) => self.write(if checked { "[x] " } else { "[ ] " }),
+            // Emphasis has no plain-text equivalent and raw HTML is left to the HTML body
+            _ => {}
+        }
+    }
+
+    fn start_block(&mut self) {
+        if self.at_container_start {
+            return;
+        }
+        self.pending = self.pending.max(Break::Blank);
+    }
+
+    fn line_break(&mut self) {
+        self.pending = self.pending.max(Break::Line);
+    }
+
+    fn quote_prefix(&self) -> String {
+        "> ".repeat(self.quote_depth)
+    }
+
+    fn prefix(&self) -> String {
+        format!("{}{}", self.quote_prefix(), "   ".repeat(self.lists.len()))
+    }
+
+    fn flush_with_prefix(&mut self, prefix: &str) {
+        match self.pending {
+            Break::None => {}
+            Break::Line => {
+                if !self.out.is_empty() {
+                    self.out.push('\n');
+                }
+                self.out.push_str(prefix);
+            }
+            Break::Blank => {
+                if !self.out.is_empty() {
+                    self.out.push('\n');
+                    self.out.push_str(prefix.trim_end());
+                    self.out.push('\n');
+                }
+                self.out.push_str(prefix);
+            }
+        }
+        self.pending = Break::None;
+    }
+
+    fn flush(&mut self) {
+        let prefix = self.prefix();
+        self.flush_with_prefix(&prefix);
+    }
+
+    fn write(&mut self, s: &str) {
+        self.flush();
+        self.at_container_start = false;
+        self.out.push_str(s);
+    }
+
+    fn start_item(&mut self) {
+        self.line_break();
+        let depth = self.lists.len().saturating_sub(1);
+        let prefix = format!("{}{}", self.quote_prefix(), "   ".repeat(depth));
+        self.flush_with_prefix(&prefix);
+        let marker = match self.lists.last_mut() {
+            Some(Some(number)) => {
+                *number += 1;
+                format!("{}. ", *number - 1)
+            }
+            _ => "- ".to_string(),
+        };
+        self.out.push_str(&marker);
+        self.at_container_start = true;
+    }
+
+    fn write_reference(&mut self, destination: &str) {
+        let n = match self.links.iter().position(|link| link == destination) {
+            Some(i) => i + 1,
+            None => {
+                self.links.push(destination.to_string());
+                self.links.len()
+            }
+        };
+        self.write(&format!(" [{}]", n));
+    }
+
+    fn finish(mut self) -> String {
+        let mut text = self.out.trim_end().to_string();
+        if !self.links.is_empty() {
+            text.push_str("\n\n");
+            for (i, link) in self.links.drain(..).enumerate() {
+                text.push_str(&format!("[{}] {}\n", i + 1, link));
+            }
+        }
+        text.trim_end().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_html, render_text};
+
+    #[test]
+    fn markdown_is_rendered_to_html() {
+        let html = render_html("# Title\n\nSome *emphasis* and a [link](https://example.com).");
+        assert_eq!(
+            html,
+            "<h1>Title</h1>\n<p>Some <em>emphasis</em> and a \
+            <a href=\"https://example.com\" rel=\"noopener noreferrer\">link</a>.</p>\n"
+        );
+    }
+
+    #[test]
+    fn dangerous_html_is_stripped() {
+        let html = render_html(
+            "Hello<script>alert('hi')</script>\n\n<img src=\"x.png\" onerror=\"alert(1)\">",
+        );
+        assert!(!html.contains("script"));
+        assert!(!html.contains("onerror"));
+        assert!(html.contains("<img src=\"x.png\">"));
+    }
+
+    #[test]
+    fn merge_tags_survive_in_links() {
+        let markdown = "Hi {{ name | default: \"friend\" }}, \
+            [Unsubscribe]({{unsubscribe_url}}) or [not]({{ preferences_url }}).";
+
+        assert_eq!(
+            render_html(markdown),
+            "<p>Hi {{ name | default: \"friend\" }}, \
+            <a href=\"{{unsubscribe_url}}\" rel=\"noopener noreferrer\">Unsubscribe</a> or \
+            <a href=\"{{ preferences_url }}\" rel=\"noopener noreferrer\">not</a>.</p>\n"
+        );
+        assert_eq!(
+            render_text(markdown),
+            "Hi {{ name | default: \"friend\" }}, Unsubscribe [1] or not [2].\n\n\
+            [1] {{unsubscribe_url}}\n[2] {{ preferences_url }}"
+        );
+    }
+
+    #[test]
+    fn paragraphs_are_separated_by_a_blank_line() {
+        let text = render_text("First paragraph\nstill the first.\n\nSecond paragraph.");
+        assert_eq!(
+            text,
+            "First paragraph\nstill the first.\n\nSecond paragraph."
+        );
+    }
+
+    #[test]
+    fn headings_are_underlined_or_prefixed() {
+        let text = render_text("# Title\n\n## Section\n\n### Subsection\n\nBody");
+        assert_eq!(
+            text,
+            "Title\n=====\n\nSection\n-------\n\n### Subsection\n\nBody"
+        );
+    }
+
+    #[test]
+    fn links_become_footnotes() {
+        let text = render_text(
+            "Read [our blog](https://example.com/blog), [the docs](https://example.com/docs) \
+            and [the blog again](https://example.com/blog).",
+        );
+        assert_eq!(
+            text,
+            "Read our blog [1], the docs [2] and the blog again [1].\n\n\
+            [1] https://example.com/blog\n\
+            [2] https://example.com/docs"
+        );
+    }
+
+    #[test]
+    fn autolinks_are_left_inline() {
+        let text = render_text("Visit <https://example.com>.");
+        assert_eq!(text, "Visit https://example.com.");
+    }
+
+    #[test]
+    fn lists_keep_their_markers() {
+        let text =
+            render_text("Intro\n\n- one\n- two\n  1. nested\n  2. again\n\n3. three\n4. four");
+        assert_eq!(
+            text,
+            "Intro\n\n- one\n- two\n   1. nested\n   2. again\n\n3. three\n4. four"
+        );
+    }
+
+    #[test]
+    fn loose_list_items_start_on_the_marker_line() {
+        let text = render_text("- first\n\n- second\n\n  more about the second");
+        assert_eq!(text, "- first\n- second\n\n   more about the second");
+    }
+
+    #[test]
+    fn code_blocks_are_indented_and_quotes_prefixed() {
+        let text = render_text("```\nlet x = 1;\nlet y = 2;\n```\n\n> Quoted\n> text\n\nAfter");
+        assert_eq!(
+            text,
+            "    let x = 1;\n    let y = 2;\n\n> Quoted\n> text\n\nAfter"
+        );
+    }
+
+    #[test]
+    fn raw_html_is_left_out_of_the_text() {
+        let text = render_text("Before\n\n<div>Only in HTML</div>\n\nAfter");
+        assert_eq!(text, "Before\n\nAfter");
+    }
+}
@@ -0,0 +1,285 @@
+use htmlescape::encode_minimal;
+
+/// The per-recipient values available to `{{ ... }}` merge tags.
+#[derive(Debug, Default)]
+pub struct MergeContext<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    pub unsubscribe_url: &'a str,
+    pub preferences_url: &'a str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MergeTag {
+    Name,
+    Email,
+    UnsubscribeUrl,
+    PreferencesUrl,
+}
+
+impl MergeTag {
+    fn parse(s: &str) -> Option<MergeTag> {
+        match s {
+            "name" => Some(MergeTag::Name),
+            "email" => Some(MergeTag::Email),
+            "unsubscribe_url" => Some(MergeTag::UnsubscribeUrl),
+            "preferences_url" => Some(MergeTag::PreferencesUrl),
+            _ => None,
+        }
+    }
+
+    fn value<'a>(&self, context: &MergeContext<'a>) -> &'a str {
+        match self {
+            MergeTag::Name => context.name,
+            MergeTag::Email => context.email,
+            MergeTag::UnsubscribeUrl => context.unsubscribe_url,
+            MergeTag::PreferencesUrl => context.preferences_url,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Tag {
+        tag: MergeTag,
+        default: Option<String>,
+    },
+}
+
+/// A subject or body with merge tags such as `{{ name | default: "friend" }}`.
+#[derive(Debug)]
+pub struct Template(Vec<Part>);
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Template, String> {
+        let mut parts = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let after_open = &rest[start + 2..];
+            let end = after_open.find("}}").ok_or_else(|| {
+                format!(
+                    "The merge tag starting with `{}` is never closed.",
+                    excerpt(&rest[start..])
+                )
+            })?;
+            parts.push(parse_tag(&after_open[..end])?);
+            rest = &after_open[end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        Ok(Self(parts))
+    }
+
+    /// Render for a subject line or a plain-text body.
+    pub fn render(&self, context: &MergeContext) -> String {
+        self.render_with(context, |value| value.to_string())
+    }
+
+    /// Render for an HTML body: substituted values are escaped.
+    pub fn render_html(&self, context: &MergeContext) -> String {
+        self.render_with(context, encode_minimal)
+    }
+
+    fn render_with(&self, context: &MergeContext, escape: impl Fn(&str) -> String) -> String {
+        let mut rendered = String::new();
+        for part in &self.0 {
+            match part {
+                Part::Literal(s) => rendered.push_str(s),
+                Part::Tag { tag, default } => {
+                    let value = match (tag.value(context), default) {
+                        ("", Some(default)) => default.as_str(),
+                        (value, _) => value,
+                    };
+                    rendered.push_str(&escape(value));
+                }
+            }
+        }
+        rendered
+    }
+}
+
+/// The subject and both bodies of a newsletter issue.
+#[derive(Debug)]
+pub struct IssueTemplate {
+    subject: Template,
+    html_content: Template,
+    text_content: Template,
+}
+
+impl IssueTemplate {
+    pub fn parse(title: &str, html_content: &str, text_content: &str) -> Result<Self, String> {
+        Ok(Self {
+            subject: Template::parse(title).map_err(|e| format!("Invalid title: {}", e))?,
+            html_content: Template::parse(html_content)
+                .map_err(|e| format!("Invalid HTML content: {}", e))?,
+            text_content: Template::parse(text_content)
+                .map_err(|e| format!("Invalid text content: {}", e))?,
+        })
+    }
+
+    /// The subject, HTML body and plain-text body for one recipient.
+    pub fn render(&self, context: &MergeContext) -> (String, String, String) {
+        (
+            self.subject.render(context),
+            self.html_content.render_html(context),
+            self.text_content.render(context),
+        )
+    }
+}
+
+/// Render a title for readers who are not subscribers, e.g. in the archive and
+/// the feeds: merge tags fall back to their defaults.
+///
+/// Issues stored before merge tags were validated are shown as they are.
+pub fn render_anonymous(source: &str) -> String {
+    match Template::parse(source) {
+        Ok(template) => template.render(&MergeContext::default()),
+        Err(_) => source.to_string(),
+    }
+}
+
+/// Like [`render_anonymous`], for an HTML body.
+///
+/// The body is sanitized too: hand-written HTML is stored as it was published,
+/// and scripts must not run on our own pages.
+pub fn render_anonymous_html(source: &str) -> String {
+    let html = match Template::parse(source) {
+        Ok(template) => template.render_html(&MergeContext::default()),
+        Err(_) => source.to_string(),
+    };
+    ammonia::clean(&html)
+}
+
+// `name` or `name | default: "fallback"`
+fn parse_tag(inner: &str) -> Result<Part, String> {
+    let invalid = || format!("`{{{{{}}}}}` is not a valid merge tag.", inner);
+    let (name, filter) = match inner.split_once('|') {
+        Some((name, filter)) => (name.trim(), Some(filter.trim())),
+        None => (inner.trim(), None),
+    };
+    let tag = MergeTag::parse(name).ok_or_else(|| {
+        if name.is_empty() {
+            invalid()
+        } else {
+            format!(
+                "`{}` is not a known merge tag: \
+                use `name`, `email`, `unsubscribe_url` or `preferences_url`.",
+                name
+            )
+        }
+    })?;
+    let default = match filter {
+        None => None,
+        Some(filter) => {
+            let argument = filter
+                .strip_prefix("default")
+                .map(str::trim_start)
+                .and_then(|s| s.strip_prefix(':'))
+                .map(str::trim)
+                .ok_or_else(invalid)?;
+            Some(parse_string_literal(argument).ok_or_else(invalid)?)
+        }
+    };
+    Ok(Part::Tag { tag, default })
+}
+
+fn parse_string_literal(s: &str) -> Option<String> {
+    for quote in ['"', '\''] {
+        if let Some(inner) = s.strip_prefix(quote).and_then(|s| s.strip_suffix(quote)) {
+            if !inner.contains(quote) {
+                return Some(inner.to_string());
+            }
+        }
+    }
+    None
+}
+
+fn excerpt(s: &str) -> String {
+    s.chars().take(20).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MergeContext, Template};
+    use claim::assert_err;
+
+    fn context() -> MergeContext<'static> {
+        MergeContext {
+            name: "Long Le",
+            email: "longle@gmail.com",
+            unsubscribe_url: "https://example.com/unsubscribe?token=abc&x=1",
+            preferences_url: "https://example.com/preferences?token=def",
+        }
+    }
+
+    #[test]
+    fn text_without_merge_tags_is_left_alone() {
+        let template = Template::parse("Hello there, { not a tag }").unwrap();
+        assert_eq!(template.render(&context()), "Hello there, { not a tag }");
+    }
+
+    #[test]
+    fn merge_tags_are_replaced_with_the_subscriber_details() {
+        let template =
+            Template::parse("Hi {{name}} ({{ email }}), bye: {{ unsubscribe_url }}").unwrap();
+        assert_eq!(
+            template.render(&context()),
+            "Hi Long Le (longle@gmail.com), bye: https://example.com/unsubscribe?token=abc&x=1"
+        );
+        let template = Template::parse("Settings: {{ preferences_url }}").unwrap();
+        assert_eq!(
+            template.render(&context()),
+            "Settings: https://example.com/preferences?token=def"
+        );
+    }
+
+    #[test]
+    fn values_are_escaped_in_html() {
+        let template =
+            Template::parse(r#"<a href="{{ unsubscribe_url }}">Unsubscribe</a>"#).unwrap();
+        assert_eq!(
+            template.render_html(&context()),
+            r#"<a href="https://example.com/unsubscribe?token=abc&amp;x=1">Unsubscribe</a>"#
+        );
+    }
+
+    #[test]
+    fn the_default_is_used_for_empty_values() {
+        let template = Template::parse(r#"Hi {{ name | default: "friend" }}!"#).unwrap();
+        let anonymous = MergeContext::default();
+        assert_eq!(template.render(&anonymous), "Hi friend!");
+        assert_eq!(template.render(&context()), "Hi Long Le!");
+    }
+
+    #[test]
+    fn single_quoted_defaults_are_accepted() {
+        let template = Template::parse("Hi {{ name|default:'friend' }}!").unwrap();
+        assert_eq!(template.render(&MergeContext::default()), "Hi friend!");
+    }
+
+    #[test]
+    fn unknown_merge_tags_are_rejected() {
+        let error = Template::parse("Hi {{ first_name }}").unwrap_err();
+        assert!(error.contains("first_name"));
+    }
+
+    #[test]
+    fn syntax_errors_are_rejected() {
+        let cases = [
+            "Hi {{ name",
+            "Hi {{ }}",
+            "Hi {{ name | uppercase }}",
+            "Hi {{ name | default }}",
+            "Hi {{ name | default: friend }}",
+            r#"Hi {{ name | default: "friend }}"#,
+        ];
+        for source in cases {
+            assert_err!(Template::parse(source), "{} should be rejected", source);
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use crate::email_client::{EmailSender, OutgoingEmail, SendEmailError};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The limits of our email provider's plan.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimits {
+    // Tokens added to the bucket every second, no limit if missing
+    pub max_sends_per_second: Option<u32>,
+    // Capacity of the bucket, `max_sends_per_second` if missing
+    pub max_burst: Option<u32>,
+    // Emails sent per UTC day, no cap if missing
+    pub daily_cap: Option<u32>,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("The daily quota of {daily_cap} emails is exhausted until {resets_at}.")]
+pub struct QuotaExhausted {
+    pub daily_cap: u32,
+    pub resets_at: DateTime<Utc>,
+}
+
+/// A token bucket and a daily cap, shared by every clone.
+///
+/// The counts live in memory: every process sending emails should use a
+/// single limiter, and the daily count starts over when the process restarts.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    // Negative when callers are already waiting for tokens
+    tokens: f64,
+    refilled_at: Instant,
+    day: NaiveDate,
+    sent_today: u32,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        let tokens = limits.burst().unwrap_or(0.);
+        Self {
+            limits,
+            state: Arc::new(Mutex::new(State {
+                tokens,
+                refilled_at: Instant::now(),
+                day: Utc::today().naive_utc(),
+                sent_today: 0,
+            })),
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::new(RateLimits::default())
+    }
+
+    /// Reserve `n` sends, waiting for the bucket to refill if needed.
+    ///
+    /// Fails right away, without reserving anything, if the sends would go
+    /// over the daily cap.
+    pub async fn acquire(&self, n: u32) -> Result<(), QuotaExhausted> {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let today = Utc::today().naive_utc();
+            if state.day != today {
+                state.day = today;
+                state.sent_today = 0;
+            }
+            if let Some(daily_cap) = self.limits.daily_cap {
+                if state.sent_today.saturating_add(n) > daily_cap {
+                    return Err(QuotaExhausted {
+                        daily_cap,
+                        resets_at: DateTime::from_utc(today.succ().and_hms(0, 0, 0), Utc),
+                    });
+                }
+            }
+            state.sent_today += n;
+
+            match (self.limits.rate(), self.limits.burst()) {
+                (Some(rate), Some(burst)) => {
+                    let now = Instant::now();
+                    let elapsed = now.duration_since(state.refilled_at).as_secs_f64();
+                    state.tokens = (state.tokens + elapsed * rate).min(burst);
+                    state.refilled_at = now;
+                    // Go into debt: later callers queue up behind this one
+                    state.tokens -= f64::from(n);
+                    (state.tokens < 0.).then(|| Duration::from_secs_f64(-state.tokens / rate))
+                }
+                _ => None,
+            }
+        };
+        if let Some(wait) = wait {
+            tracing::debug!("Waiting {:?} to respect the sending rate limit.", wait);
+            tokio::time::sleep(wait).await;
+        }
+
+        Ok(())
+    }
+}
+
+/// Every send goes through the limiter first, whatever the provider.
+pub struct RateLimitedSender {
+    inner: Box<dyn EmailSender>,
+    rate_limiter: RateLimiter,
+}
+
+impl RateLimitedSender {
+    pub fn new(inner: Box<dyn EmailSender>, rate_limiter: RateLimiter) -> Self {
+        Self {
+            inner,
+            rate_limiter,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for RateLimitedSender {
+    async fn send(&self, email: &OutgoingEmail<'_>) -> Result<(), SendEmailError> {
+        self.rate_limiter.acquire(1).await?;
+        self.inner.send(email).await
+    }
+
+    async fn send_batch(
+        &self,
+        emails: &[OutgoingEmail<'_>],
+    ) -> Result<Vec<Result<(), SendEmailError>>, SendEmailError> {
+        self.rate_limiter.acquire(emails.len() as u32).await?;
+        self.inner.send_batch(emails).await
+    }
+}
+
+impl RateLimits {
+    fn rate(&self) -> Option<f64> {
+        self.max_sends_per_second
+            .filter(|rate| *rate > 0)
+            .map(f64::from)
+    }
+
+    fn burst(&self) -> Option<f64> {
+        let burst = self.max_burst.or(self.max_sends_per_second)?;
+        Some(f64::from(burst.max(1)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RateLimitedSender, RateLimiter, RateLimits};
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailSender, OutgoingEmail, SendEmailError};
+    use claim::{assert_err, assert_matches, assert_ok};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    struct CountingSender(Arc<AtomicUsize>);
+
+    #[async_trait::async_trait]
+    impl EmailSender for CountingSender {
+        async fn send(&self, _email: &OutgoingEmail<'_>) -> Result<(), SendEmailError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn an_unlimited_limiter_never_waits() {
+        let limiter = RateLimiter::unlimited();
+        let start = Instant::now();
+        for _ in 0..1000 {
+            assert_ok!(limiter.acquire(1).await);
+        }
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn sends_beyond_the_burst_wait_for_the_bucket_to_refill() {
+        let limiter = RateLimiter::new(RateLimits {
+            max_sends_per_second: Some(20),
+            max_burst: Some(2),
+            daily_cap: None,
+        });
+        let start = Instant::now();
+        limiter.acquire(2).await.unwrap();
+        assert!(start.elapsed() < Duration::from_millis(25));
+        // Two more tokens take 100ms to come back
+        limiter.acquire(2).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[tokio::test]
+    async fn clones_share_the_same_bucket() {
+        let limiter = RateLimiter::new(RateLimits {
+            max_sends_per_second: Some(20),
+            max_burst: Some(1),
+            daily_cap: None,
+        });
+        let clone = limiter.clone();
+        let start = Instant::now();
+        limiter.acquire(1).await.unwrap();
+        clone.acquire(1).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[tokio::test]
+    async fn sends_over_the_daily_cap_are_refused() {
+        let limiter = RateLimiter::new(RateLimits {
+            max_sends_per_second: None,
+            max_burst: None,
+            daily_cap: Some(3),
+        });
+        assert_ok!(limiter.acquire(2).await);
+        // A batch is refused as a whole, leaving room for smaller ones
+        let error = limiter.acquire(2).await.unwrap_err();
+        assert_eq!(error.daily_cap, 3);
+        assert!(error.resets_at > chrono::Utc::now());
+        assert_ok!(limiter.acquire(1).await);
+        assert_err!(limiter.acquire(1).await);
+    }
+
+    #[tokio::test]
+    async fn a_rate_limited_sender_stops_before_the_daily_cap() {
+        // Arrange
+        let n_sent = Arc::new(AtomicUsize::new(0));
+        let sender = RateLimitedSender::new(
+            Box::new(CountingSender(n_sent.clone())),
+            RateLimiter::new(RateLimits {
+                daily_cap: Some(1),
+                ..RateLimits::default()
+            }),
+        );
+        let recipient = SubscriberEmail::parse("longle@gmail.com".into()).unwrap();
+
+        // Act
+        let first = sender
+            .send_email(&recipient, "Subject", "Body", "Body")
+            .await;
+        let second = sender
+            .send_email(&recipient, "Subject", "Body", "Body")
+            .await;
+
+        // Assert
+        assert_ok!(first);
+        assert_matches!(second, Err(SendEmailError::QuotaExhausted(_)));
+        assert_eq!(n_sent.load(Ordering::SeqCst), 1);
+    }
+}
@@ -32,7 +32,16 @@ pub async fn admin_dashboard(
                     <p>Welcome {username}!</p>
                     <p>Available actions:</p>
                     <ol>
+                        <li><a href="/admin/newsletters">Send a newsletter issue</a></li>
                         <li><a href="/admin/password">Change password</a></li>
+                        <li><a href="/admin/drafts">Drafts</a></li>
+                        <li><a href="/admin/issues">Published issues</a></li>
+                        <li><a href="/admin/scheduled_issues">Scheduled issues</a></li>
+                        <li><a href="/admin/dead_letters">Failed deliveries</a></li>
+                        <li><a href="/admin/lists">Lists</a></li>
+                        <li><a href="/admin/segments">Segments</a></li>
+                        <li><a href="/admin/topics">Topics</a></li>
+                        <li><a href="/admin/suppressions">Suppression list</a></li>
                     </ol>
                 </body>
             </html>
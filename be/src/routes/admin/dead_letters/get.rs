@@ -0,0 +1,114 @@
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+struct DeadLetter {
+    newsletter_issue_id: uuid::Uuid,
+    title: String,
+    subscriber_email: String,
+    n_attempts: i16,
+    last_error: String,
+    failed_at: DateTime<Utc>,
+}
+
+pub async fn dead_letters(
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut rows_html = String::new();
+    for entry in get_dead_letters(&pool).await.map_err(e500)? {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{title}</td>
+                <td>{email}</td>
+                <td>{n_attempts}</td>
+                <td>{last_error}</td>
+                <td>{failed_at}</td>
+                <td>
+                    <form action="/admin/dead_letters/requeue" method="post">
+                        <input type="hidden" name="newsletter_issue_id" value="{issue_id}">
+                        <input type="hidden" name="subscriber_email" value="{email}">
+                        <button type="submit">Requeue</button>
+                    </form>
+                </td>
+            </tr>"#,
+            title = encode_minimal(&entry.title),
+            email = encode_minimal(&entry.subscriber_email),
+            n_attempts = entry.n_attempts,
+            last_error = encode_minimal(&entry.last_error),
+            failed_at = entry.failed_at.to_rfc3339(),
+            issue_id = entry.newsletter_issue_id,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Failed deliveries</title>
+            </head>
+            <body>
+                {msg_html}
+                <table>
+                    <tr>
+                        <th>Issue</th>
+                        <th>Recipient</th>
+                        <th>Attempts</th>
+                        <th>Last error</th>
+                        <th>Failed at</th>
+                        <th></th>
+                    </tr>
+                    {rows_html}
+                </table>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+            </html>
+            "#
+        )))
+}
+
+#[tracing::instrument(name = "Get dead letters", skip(pool))]
+async fn get_dead_letters(pool: &PgPool) -> Result<Vec<DeadLetter>, anyhow::Error> {
+    let entries = sqlx::query_as!(
+        DeadLetter,
+        r#"
+        SELECT
+            d.newsletter_issue_id,
+            i.title,
+            d.subscriber_email,
+            d.n_attempts,
+            d.last_error,
+            d.failed_at
+        FROM issue_delivery_dead_letters d
+            JOIN newsletter_issues i USING (newsletter_issue_id)
+            ORDER BY d.failed_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve dead letters.")?;
+
+    Ok(entries)
+}
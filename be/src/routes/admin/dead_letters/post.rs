@@ -0,0 +1,91 @@
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+}
+
+#[tracing::instrument(
+    name = "Requeue a dead letter",
+    skip(form, session, pool),
+    fields(newsletter_issue_id = %form.newsletter_issue_id)
+)]
+pub async fn requeue_dead_letter(
+    form: web::Form<FormData>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    let requeued = requeue(&pool, form.newsletter_issue_id, &form.subscriber_email)
+        .await
+        .map_err(e500)?;
+    if requeued {
+        FlashMessage::info(format!(
+            "The delivery to {} has been requeued.",
+            htmlescape::encode_minimal(&form.subscriber_email)
+        ))
+        .send();
+    } else {
+        FlashMessage::error("The failed delivery no longer exists.").send();
+    }
+
+    Ok(see_other("/admin/dead_letters"))
+}
+
+/// Move a dead letter back to the delivery queue, with a fresh set of attempts.
+async fn requeue(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    subscriber_email: &str,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let n_deleted_rows = sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_dead_letters
+            WHERE
+                newsletter_issue_id = $1 AND
+                subscriber_email = $2
+        "#,
+        newsletter_issue_id,
+        subscriber_email
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the dead letter.")?
+    .rows_affected();
+    if n_deleted_rows == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+            VALUES ($1, $2)
+            ON CONFLICT DO NOTHING
+        "#,
+        newsletter_issue_id,
+        subscriber_email
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to enqueue the delivery task.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to requeue a dead letter.")?;
+
+    Ok(true)
+}
@@ -0,0 +1,275 @@
+use super::{get_draft, Draft};
+use crate::routes::MAX_TEST_RECIPIENTS;
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct DraftSummary {
+    draft_id: Uuid,
+    title: String,
+    updated_at: DateTime<Utc>,
+}
+
+pub async fn drafts(
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut rows_html = String::new();
+    for draft in get_draft_summaries(&pool).await.map_err(e500)? {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td><a href="/admin/drafts/{draft_id}">{title}</a></td>
+                <td>{updated_at}</td>
+                <td><a href="/admin/drafts/{draft_id}/preview">Preview</a></td>
+                <td>
+                    <form action="/admin/drafts/{draft_id}/publish" method="post">
+                        <label><input type="checkbox" name="track_opens" value="on"> Track opens</label>
+                        <button type="submit">Publish</button>
+                    </form>
+                </td>
+                <td>
+                    <form action="/admin/drafts/{draft_id}/delete" method="post">
+                        <button type="submit">Delete</button>
+                    </form>
+                </td>
+            </tr>"#,
+            draft_id = draft.draft_id,
+            title = encode_minimal(&draft.title),
+            updated_at = draft.updated_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Drafts</title>
+            </head>
+            <body>
+                {msg_html}
+                <p><a href="/admin/drafts/new">New draft</a></p>
+                <table>
+                    <tr>
+                        <th>Draft</th>
+                        <th>Last edited</th>
+                        <th></th>
+                        <th></th>
+                        <th></th>
+                    </tr>
+                    {rows_html}
+                </table>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+            </html>
+            "#
+        )))
+}
+
+pub async fn new_draft_form(
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    Ok(draft_form_page(
+        &flash_messages,
+        "New draft",
+        "/admin/drafts",
+        "",
+        "",
+        "",
+    ))
+}
+
+pub async fn edit_draft_form(
+    draft_id: web::Path<Uuid>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let draft = match get_draft(&pool, draft_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    Ok(draft_form_page(
+        &flash_messages,
+        "Edit draft",
+        &format!("/admin/drafts/{}", draft.draft_id),
+        &draft.title,
+        &draft.html_content,
+        &draft.text_content,
+    ))
+}
+
+fn draft_form_page(
+    flash_messages: &IncomingFlashMessages,
+    page_title: &str,
+    action: &str,
+    title: &str,
+    html_content: &str,
+    text_content: &str,
+) -> HttpResponse {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>{page_title}</title>
+            </head>
+            <body>
+                {msg_html}
+                <form action="{action}" method="post">
+                    <p>
+                        <label>Title
+                            <input type="text" name="title" value="{title}">
+                        </label>
+                    </p>
+                    <p>
+                        <label>HTML content
+                            <textarea name="html_content" rows="20" cols="80">{html_content}</textarea>
+                        </label>
+                    </p>
+                    <p>
+                        <label>Plain text content
+                            <textarea name="text_content" rows="20" cols="80">{text_content}</textarea>
+                        </label>
+                    </p>
+                    <button type="submit">Save draft</button>
+                </form>
+                <p><a href="/admin/drafts">&lt;- Back</a></p>
+            </body>
+            </html>
+            "#,
+            title = encode_minimal(title),
+            html_content = encode_minimal(html_content),
+            text_content = encode_minimal(text_content),
+        ))
+}
+
+/// Show the draft exactly as subscribers will receive it.
+pub async fn preview_draft(
+    draft_id: web::Path<Uuid>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let Draft {
+        draft_id,
+        title,
+        text_content,
+        html_content,
+    } = match get_draft(&pool, draft_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    // The HTML email is rendered in a sandboxed frame, so that its styles
+    // and scripts cannot leak into the admin pages.
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Preview</title>
+            </head>
+            <body>
+                {msg_html}
+                <p>Subject: {title}</p>
+                <h2>HTML</h2>
+                <iframe sandbox srcdoc="{html_content}" width="800" height="600"></iframe>
+                <h2>Plain text</h2>
+                <pre>{text_content}</pre>
+                <form action="/admin/drafts/{draft_id}/test" method="post">
+                    <p>
+                        <label>Send a test copy to
+                            <textarea
+                                placeholder="One address per line, at most {max_test_recipients}"
+                                name="recipients"
+                                rows="3"
+                                cols="40"
+                            ></textarea>
+                        </label>
+                    </p>
+                    <button type="submit">Send test copy</button>
+                </form>
+                <p><a href="/admin/drafts/{draft_id}">Edit</a></p>
+                <p><a href="/admin/drafts">&lt;- Back</a></p>
+            </body>
+            </html>
+            "#,
+            title = encode_minimal(&title),
+            html_content = encode_minimal(&html_content),
+            text_content = encode_minimal(&text_content),
+            max_test_recipients = MAX_TEST_RECIPIENTS,
+        )))
+}
+
+#[tracing::instrument(name = "Get newsletter drafts", skip(pool))]
+async fn get_draft_summaries(pool: &PgPool) -> Result<Vec<DraftSummary>, anyhow::Error> {
+    let drafts = sqlx::query_as!(
+        DraftSummary,
+        r#"
+        SELECT draft_id, title, updated_at
+            FROM newsletter_drafts
+            ORDER BY updated_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve newsletter drafts.")?;
+
+    Ok(drafts)
+}
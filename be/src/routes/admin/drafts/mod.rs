@@ -0,0 +1,34 @@
+mod get;
+mod post;
+
+pub use get::{drafts, edit_draft_form, new_draft_form, preview_draft};
+pub use post::{create_draft, delete_draft, publish_draft, send_test_draft, update_draft};
+
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+struct Draft {
+    draft_id: Uuid,
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+#[tracing::instrument(name = "Get a newsletter draft", skip(pool))]
+async fn get_draft(pool: &PgPool, draft_id: Uuid) -> Result<Option<Draft>, anyhow::Error> {
+    let draft = sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT draft_id, title, text_content, html_content
+            FROM newsletter_drafts
+            WHERE draft_id = $1
+        "#,
+        draft_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve a newsletter draft.")?;
+
+    Ok(draft)
+}
@@ -0,0 +1,319 @@
+use super::get_draft;
+use crate::email_client::{EmailSender, SendEmailError};
+use crate::lists::get_default_list;
+use crate::merge_tags::IssueTemplate;
+use crate::routes::{
+    enqueue_delivery_tasks, find_suppressed_recipient, insert_newsletter_issue,
+    parse_test_recipients, send_test_copy, IssueAudience,
+};
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    title: String,
+    html_content: String,
+    text_content: String,
+}
+
+#[tracing::instrument(name = "Create a newsletter draft", skip(form, session, pool))]
+pub async fn create_draft(
+    form: web::Form<FormData>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    if form.title.trim().is_empty() {
+        FlashMessage::error("The title must not be empty.").send();
+        return Ok(see_other("/admin/drafts/new"));
+    }
+
+    let draft_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_drafts (
+            draft_id,
+            title,
+            text_content,
+            html_content,
+            created_at,
+            updated_at
+        )
+        VALUES ($1, $2, $3, $4, now(), now())
+        "#,
+        draft_id,
+        form.title,
+        form.text_content,
+        form.html_content
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the newsletter draft.")
+    .map_err(e500)?;
+
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!("/admin/drafts/{}", draft_id)))
+}
+
+#[tracing::instrument(name = "Update a newsletter draft", skip(form, session, pool))]
+pub async fn update_draft(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let draft_id = draft_id.into_inner();
+    if form.title.trim().is_empty() {
+        FlashMessage::error("The title must not be empty.").send();
+        return Ok(see_other(&format!("/admin/drafts/{}", draft_id)));
+    }
+
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_drafts
+            SET
+                title = $2,
+                text_content = $3,
+                html_content = $4,
+                updated_at = now()
+            WHERE draft_id = $1
+        "#,
+        draft_id,
+        form.title,
+        form.text_content,
+        form.html_content
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the newsletter draft.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated_rows == 0 {
+        FlashMessage::error("The draft no longer exists.").send();
+        return Ok(see_other("/admin/drafts"));
+    }
+
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!("/admin/drafts/{}", draft_id)))
+}
+
+#[tracing::instrument(name = "Delete a newsletter draft", skip(session, pool))]
+pub async fn delete_draft(
+    draft_id: web::Path<Uuid>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    let n_deleted_rows = sqlx::query!(
+        r#"DELETE FROM newsletter_drafts WHERE draft_id = $1"#,
+        draft_id.into_inner()
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to delete the newsletter draft.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_deleted_rows == 0 {
+        FlashMessage::error("The draft no longer exists.").send();
+    } else {
+        FlashMessage::info("The draft has been deleted.").send();
+    }
+
+    Ok(see_other("/admin/drafts"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct PublishFormData {
+    // Only sent when the box is ticked
+    #[serde(default)]
+    track_opens: Option<String>,
+}
+
+#[tracing::instrument(name = "Publish a newsletter draft", skip(form, session, pool))]
+pub async fn publish_draft(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<PublishFormData>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    // The draft is removed in the same transaction that publishes it: a second
+    // click on "Publish" waits on the lock and then finds nothing to send.
+    let draft = sqlx::query!(
+        r#"
+        SELECT title, text_content, html_content
+            FROM newsletter_drafts
+            WHERE draft_id = $1
+            FOR UPDATE
+        "#,
+        *draft_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve the newsletter draft.")
+    .map_err(e500)?;
+    let draft = match draft {
+        Some(draft) => draft,
+        None => {
+            FlashMessage::error("The draft no longer exists.").send();
+            return Ok(see_other("/admin/drafts"));
+        }
+    };
+    if let Err(e) = IssueTemplate::parse(&draft.title, &draft.html_content, &draft.text_content) {
+        FlashMessage::error(encode_minimal(&e)).send();
+        return Ok(see_other(&format!("/admin/drafts/{}/preview", draft_id)));
+    }
+
+    let default_list = get_default_list(&mut transaction).await.map_err(e500)?;
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &draft.title,
+        &draft.text_content,
+        &draft.html_content,
+        None,
+        &IssueAudience {
+            list_ids: &[default_list.list_id],
+            topic_id: None,
+            segment_id: None,
+        },
+        form.track_opens.is_some(),
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(e500)?;
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .context("Failed to enqueue delivery tasks")
+        .map_err(e500)?;
+    sqlx::query!(
+        r#"DELETE FROM newsletter_drafts WHERE draft_id = $1"#,
+        draft_id.into_inner()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the published newsletter draft.")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to publish a newsletter draft.")
+        .map_err(e500)?;
+
+    FlashMessage::info("The draft has been published.").send();
+    Ok(see_other("/admin/drafts"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct TestFormData {
+    recipients: String,
+}
+
+#[tracing::instrument(
+    name = "Send a test copy of a newsletter draft",
+    skip(form, session, pool, email_client)
+)]
+pub async fn send_test_draft(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<TestFormData>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailSender>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let draft = match get_draft(&pool, draft_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Some(draft) => draft,
+        None => {
+            FlashMessage::error("The draft no longer exists.").send();
+            return Ok(see_other("/admin/drafts"));
+        }
+    };
+    let preview_path = format!("/admin/drafts/{}/preview", draft.draft_id);
+
+    // One address per line, or several on a line separated by commas
+    let recipients = form
+        .recipients
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect();
+    let recipients = match parse_test_recipients(recipients) {
+        Ok(recipients) => recipients,
+        Err(e) => {
+            FlashMessage::error(encode_minimal(&e)).send();
+            return Ok(see_other(&preview_path));
+        }
+    };
+    if let Some(email) = find_suppressed_recipient(&pool, &recipients)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error(format!(
+            "{} is on the suppression list.",
+            encode_minimal(&email)
+        ))
+        .send();
+        return Ok(see_other(&preview_path));
+    }
+    let template =
+        match IssueTemplate::parse(&draft.title, &draft.html_content, &draft.text_content) {
+            Ok(template) => template,
+            Err(e) => {
+                FlashMessage::error(encode_minimal(&e)).send();
+                return Ok(see_other(&preview_path));
+            }
+        };
+    if let Err(e) = send_test_copy(email_client.get_ref(), &recipients, &template).await {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to send a test copy of a newsletter draft"
+        );
+        let message = match e {
+            SendEmailError::QuotaExhausted(e) => format!(
+                "The daily sending quota is exhausted until {}.",
+                e.resets_at.format("%Y-%m-%d %H:%M UTC")
+            ),
+            _ => "The test copy could not be sent, please try again.".into(),
+        };
+        FlashMessage::error(message).send();
+        return Ok(see_other(&preview_path));
+    }
+
+    FlashMessage::info(format!(
+        "A test copy has been sent to {}.",
+        recipients
+            .iter()
+            .map(|r| r.as_ref())
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+    .send();
+    Ok(see_other(&preview_path))
+}
@@ -0,0 +1,151 @@
+use crate::issue_delivery_report::{get_delivery_report, get_delivery_summaries};
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub async fn newsletter_issues(
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    let mut rows_html = String::new();
+    for summary in get_delivery_summaries(&pool).await.map_err(e500)? {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td><a href="/admin/issues/{issue_id}">{title}</a></td>
+                <td>{published_at}</td>
+                <td>{n_recipients}</td>
+                <td>{n_sent}</td>
+                <td>{n_pending}</td>
+                <td>{n_failed}</td>
+                <td>{n_skipped}</td>
+            </tr>"#,
+            issue_id = summary.newsletter_issue_id,
+            title = encode_minimal(&summary.title),
+            published_at = summary.published_at.to_rfc3339(),
+            n_recipients = summary.n_recipients,
+            n_sent = summary.n_sent,
+            n_pending = summary.n_pending,
+            n_failed = summary.n_failed,
+            n_skipped = summary.n_skipped,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Newsletter issues</title>
+            </head>
+            <body>
+                <table>
+                    <tr>
+                        <th>Issue</th>
+                        <th>Published at</th>
+                        <th>Recipients</th>
+                        <th>Sent</th>
+                        <th>Pending</th>
+                        <th>Failed</th>
+                        <th>Skipped</th>
+                    </tr>
+                    {rows_html}
+                </table>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+            </html>
+            "#
+        )))
+}
+
+pub async fn newsletter_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let report = match get_delivery_report(&pool, newsletter_issue_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Some(report) => report,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let mut failed_html = String::new();
+    for recipient in &report.failed_recipients {
+        writeln!(
+            failed_html,
+            r#"<tr>
+                <td>{email}</td>
+                <td>{n_attempts}</td>
+                <td>{last_error}</td>
+                <td>{failed_at}</td>
+            </tr>"#,
+            email = encode_minimal(&recipient.subscriber_email),
+            n_attempts = recipient.n_attempts,
+            last_error = encode_minimal(&recipient.last_error),
+            failed_at = recipient.failed_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+    let summary = &report.summary;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Delivery report</title>
+            </head>
+            <body>
+                <h1>{title}</h1>
+                <p>Published at {published_at}</p>
+                <ul>
+                    <li>Recipients: {n_recipients}</li>
+                    <li>Sent: {n_sent}</li>
+                    <li>Pending: {n_pending}</li>
+                    <li>Failed: {n_failed}</li>
+                    <li>Skipped: {n_skipped}</li>
+                </ul>
+                <h2>Failed recipients</h2>
+                <table>
+                    <tr>
+                        <th>Recipient</th>
+                        <th>Attempts</th>
+                        <th>Last error</th>
+                        <th>Failed at</th>
+                    </tr>
+                    {failed_html}
+                </table>
+                <p><a href="/admin/issues">&lt;- Back</a></p>
+            </body>
+            </html>
+            "#,
+            title = encode_minimal(&summary.title),
+            published_at = summary.published_at.to_rfc3339(),
+            n_recipients = summary.n_recipients,
+            n_sent = summary.n_sent,
+            n_pending = summary.n_pending,
+            n_failed = summary.n_failed,
+            n_skipped = summary.n_skipped,
+        )))
+}
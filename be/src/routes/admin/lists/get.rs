@@ -0,0 +1,109 @@
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+struct ListSummary {
+    name: String,
+    slug: String,
+    n_confirmed: i64,
+    n_pending: i64,
+}
+
+pub async fn lists(
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut rows_html = String::new();
+    for list in get_list_summaries(&pool).await.map_err(e500)? {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{name}</td>
+                <td>{slug}</td>
+                <td>{n_confirmed}</td>
+                <td>{n_pending}</td>
+            </tr>"#,
+            name = encode_minimal(&list.name),
+            slug = encode_minimal(&list.slug),
+            n_confirmed = list.n_confirmed,
+            n_pending = list.n_pending,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Lists</title>
+            </head>
+            <body>
+                {msg_html}
+                <p>Subscription forms pick a list with a <code>list</code> field
+                holding its slug.</p>
+                <table>
+                    <tr>
+                        <th>List</th>
+                        <th>Slug</th>
+                        <th>Confirmed</th>
+                        <th>Pending</th>
+                    </tr>
+                    {rows_html}
+                </table>
+                <form action="/admin/lists" method="post">
+                    <label>Name
+                        <input type="text" name="name" placeholder="Enter the list name">
+                    </label>
+                    <label>Slug
+                        <input type="text" name="slug" placeholder="e.g. product-updates">
+                    </label>
+                    <button type="submit">Add</button>
+                </form>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+            </html>
+            "#
+        )))
+}
+
+#[tracing::instrument(name = "Get list summaries", skip(pool))]
+async fn get_list_summaries(pool: &PgPool) -> Result<Vec<ListSummary>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        ListSummary,
+        r#"
+        SELECT
+            l.name,
+            l.slug,
+            COUNT(*) FILTER (WHERE m.status = 'confirmed') as "n_confirmed!",
+            COUNT(*) FILTER (WHERE m.status = 'pending_confirmation') as "n_pending!"
+        FROM lists l
+            LEFT JOIN list_memberships m ON m.list_id = l.list_id
+            GROUP BY l.list_id
+            ORDER BY l.name
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve lists.")?;
+
+    Ok(lists)
+}
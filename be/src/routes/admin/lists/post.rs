@@ -0,0 +1,70 @@
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    slug: String,
+}
+
+#[tracing::instrument(name = "Create a list", skip(form, session, pool))]
+pub async fn create_list(
+    form: web::Form<FormData>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let name = form.name.trim();
+    let slug = form.slug.trim();
+    if name.is_empty() {
+        FlashMessage::error("The list name must not be empty.").send();
+        return Ok(see_other("/admin/lists"));
+    }
+    if !is_valid_slug(slug) {
+        FlashMessage::error("The slug must be made of lowercase letters, digits and dashes.")
+            .send();
+        return Ok(see_other("/admin/lists"));
+    }
+
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, name, slug, created_at)
+            VALUES ($1, $2, $3, now())
+            ON CONFLICT DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        name,
+        slug
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the list.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_inserted_rows == 0 {
+        FlashMessage::error(format!(
+            "There already is a list named {} or with the slug {}.",
+            encode_minimal(name),
+            encode_minimal(slug)
+        ))
+        .send();
+    } else {
+        FlashMessage::info(format!("The list {} has been added.", encode_minimal(name))).send();
+    }
+    Ok(see_other("/admin/lists"))
+}
+
+fn is_valid_slug(slug: &str) -> bool {
+    !slug.is_empty()
+        && slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
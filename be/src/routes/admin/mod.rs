@@ -1,5 +1,23 @@
 mod dashboard;
+mod dead_letters;
+mod drafts;
+mod issues;
+mod lists;
+mod newsletters;
 mod password;
+mod scheduled_issues;
+mod segments;
+mod suppressions;
+mod topics;
 
 pub use dashboard::admin_dashboard;
+pub use dead_letters::*;
+pub use drafts::*;
+pub use issues::{newsletter_issue, newsletter_issues};
+pub use lists::*;
+pub use newsletters::*;
 pub use password::*;
+pub use scheduled_issues::*;
+pub use segments::*;
+pub use suppressions::*;
+pub use topics::*;
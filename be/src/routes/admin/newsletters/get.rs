@@ -0,0 +1,143 @@
+use crate::lists::{get_lists, DEFAULT_LIST_SLUG};
+use crate::routes::get_topics;
+use crate::segments::get_segments;
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn publish_newsletter_form(
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let mut topics_html = String::new();
+    for topic in get_topics(&pool).await.map_err(e500)? {
+        writeln!(
+            topics_html,
+            r#"<option value="{}">{}</option>"#,
+            topic.topic_id,
+            encode_minimal(&topic.name)
+        )
+        .unwrap();
+    }
+    let mut lists_html = String::new();
+    for list in get_lists(&pool).await.map_err(e500)? {
+        writeln!(
+            lists_html,
+            r#"<option value="{}"{}>{}</option>"#,
+            list.list_id,
+            if list.slug == DEFAULT_LIST_SLUG {
+                " selected"
+            } else {
+                ""
+            },
+            encode_minimal(&list.name)
+        )
+        .unwrap();
+    }
+    let mut segments_html = String::new();
+    for segment in get_segments(&pool).await.map_err(e500)? {
+        writeln!(
+            segments_html,
+            r#"<option value="{}">{}</option>"#,
+            segment.segment_id,
+            encode_minimal(&segment.name)
+        )
+        .unwrap();
+    }
+    // A fresh key for every rendering of the form: submitting the same
+    // form twice publishes the issue only once.
+    let idempotency_key = uuid::Uuid::new_v4();
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Publish a newsletter issue</title>
+            </head>
+            <body>
+                {msg_html}
+                <form action="/admin/newsletters" method="post">
+                    <p>
+                        <label>Title
+                            <input
+                                type="text"
+                                placeholder="Enter the issue title"
+                                name="title"
+                            >
+                        </label>
+                    </p>
+                    <p>
+                        <label>HTML content
+                            <textarea
+                                placeholder="Enter the content in HTML format"
+                                name="html_content"
+                                rows="20"
+                                cols="80"
+                            ></textarea>
+                        </label>
+                    </p>
+                    <p>
+                        <label>Plain text content
+                            <textarea
+                                placeholder="Enter the content in plain text"
+                                name="text_content"
+                                rows="20"
+                                cols="80"
+                            ></textarea>
+                        </label>
+                    </p>
+                    <p>
+                        <label>List
+                            <select name="list_id">
+                                {lists_html}
+                            </select>
+                        </label>
+                    </p>
+                    <p>
+                        <label>Segment
+                            <select name="segment_id">
+                                <option value="">None: send to every member of the list</option>
+                                {segments_html}
+                            </select>
+                        </label>
+                    </p>
+                    <p>
+                        <label>Topic
+                            <select name="topic_id">
+                                <option value="">None: send to every subscriber</option>
+                                {topics_html}
+                            </select>
+                        </label>
+                    </p>
+                    <p>
+                        <label>
+                            <input type="checkbox" name="track_opens" value="on">
+                            Track opens
+                        </label>
+                    </p>
+                    <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
+                    <button type="submit">Publish</button>
+                </form>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+            </html>
+            "#
+        )))
+}
@@ -0,0 +1,146 @@
+use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::lists::{find_unknown_list, get_default_list};
+use crate::merge_tags::IssueTemplate;
+use crate::routes::{enqueue_delivery_tasks, insert_newsletter_issue, IssueAudience};
+use crate::segments::get_segment;
+use crate::session_state::TypedSession;
+use crate::utils::{e400, e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    title: String,
+    html_content: String,
+    text_content: String,
+    idempotency_key: String,
+    // Empty when the issue is not about a specific topic
+    #[serde(default)]
+    topic_id: String,
+    // Empty to send the issue to the default list
+    #[serde(default)]
+    list_id: String,
+    // Empty to send the issue to every member of the list
+    #[serde(default)]
+    segment_id: String,
+    // Only sent when the box is ticked
+    #[serde(default)]
+    track_opens: Option<String>,
+}
+
+#[tracing::instrument(
+    name = "Publish a newsletter issue from the admin form",
+    skip(form, session, pool),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn publish_newsletter_from_form(
+    form: web::Form<FormData>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = match session.get_user_id().map_err(e500)? {
+        Some(user_id) => user_id,
+        None => return Ok(see_other("/login")),
+    };
+    tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+    let FormData {
+        title,
+        html_content,
+        text_content,
+        idempotency_key,
+        topic_id,
+        list_id,
+        segment_id,
+        track_opens,
+    } = form.0;
+    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let topic_id = match topic_id.as_str() {
+        "" => None,
+        topic_id => Some(Uuid::parse_str(topic_id).map_err(e400)?),
+    };
+    let list_id = match list_id.as_str() {
+        "" => {
+            get_default_list(pool.get_ref())
+                .await
+                .map_err(e500)?
+                .list_id
+        }
+        list_id => Uuid::parse_str(list_id).map_err(e400)?,
+    };
+    let segment_id = match segment_id.as_str() {
+        "" => None,
+        segment_id => Some(Uuid::parse_str(segment_id).map_err(e400)?),
+    };
+    if title.trim().is_empty() {
+        FlashMessage::error("The title must not be empty.").send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+    if let Err(e) = IssueTemplate::parse(&title, &html_content, &text_content) {
+        FlashMessage::error(encode_minimal(&e)).send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+    if find_unknown_list(&pool, &[list_id])
+        .await
+        .map_err(e500)?
+        .is_some()
+    {
+        FlashMessage::error("The list no longer exists.").send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+    if let Some(segment_id) = segment_id {
+        if get_segment(pool.get_ref(), segment_id)
+            .await
+            .map_err(e500)?
+            .is_none()
+        {
+            FlashMessage::error("The segment no longer exists.").send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    }
+
+    let mut transaction = match try_processing(&pool, &idempotency_key, user_id)
+        .await
+        .map_err(e500)?
+    {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => {
+            success_message().send();
+            return Ok(saved_response);
+        }
+    };
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &title,
+        &text_content,
+        &html_content,
+        None,
+        &IssueAudience {
+            list_ids: &[list_id],
+            topic_id,
+            segment_id,
+        },
+        track_opens.is_some(),
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(e500)?;
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .context("Failed to enqueue delivery tasks")
+        .map_err(e500)?;
+
+    let response = see_other("/admin/newsletters");
+    let response = save_response(transaction, &idempotency_key, user_id, response)
+        .await
+        .map_err(e500)?;
+    success_message().send();
+    Ok(response)
+}
+
+fn success_message() -> FlashMessage {
+    FlashMessage::info("The newsletter issue has been published!")
+}
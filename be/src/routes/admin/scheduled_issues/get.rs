@@ -0,0 +1,103 @@
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct ScheduledIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    send_at: DateTime<Utc>,
+}
+
+pub async fn scheduled_issues(
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut rows_html = String::new();
+    for issue in get_scheduled_issues(&pool).await.map_err(e500)? {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{title}</td>
+                <td>{send_at}</td>
+                <td>
+                    <form action="/admin/scheduled_issues/{issue_id}/reschedule" method="post">
+                        <input type="text" name="send_at" value="{send_at}">
+                        <button type="submit">Reschedule</button>
+                    </form>
+                </td>
+                <td>
+                    <form action="/admin/scheduled_issues/{issue_id}/cancel" method="post">
+                        <button type="submit">Cancel</button>
+                    </form>
+                </td>
+            </tr>"#,
+            title = encode_minimal(&issue.title),
+            send_at = issue.send_at.to_rfc3339(),
+            issue_id = issue.newsletter_issue_id,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Scheduled issues</title>
+            </head>
+            <body>
+                {msg_html}
+                <p>Times use the RFC 3339 format, e.g. 2022-08-20T09:00:00+07:00</p>
+                <table>
+                    <tr>
+                        <th>Issue</th>
+                        <th>Send at</th>
+                        <th></th>
+                        <th></th>
+                    </tr>
+                    {rows_html}
+                </table>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+            </html>
+            "#
+        )))
+}
+
+#[tracing::instrument(name = "Get scheduled issues", skip(pool))]
+async fn get_scheduled_issues(pool: &PgPool) -> Result<Vec<ScheduledIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        ScheduledIssue,
+        r#"
+        SELECT newsletter_issue_id, title, send_at as "send_at!"
+            FROM newsletter_issues
+            WHERE status = 'scheduled'
+            ORDER BY send_at
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve scheduled issues.")?;
+
+    Ok(issues)
+}
@@ -0,0 +1,95 @@
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    send_at: String,
+}
+
+#[tracing::instrument(name = "Reschedule a newsletter issue", skip(form, session, pool))]
+pub async fn reschedule_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    let send_at = match DateTime::parse_from_rfc3339(form.send_at.trim()) {
+        Ok(send_at) => send_at.with_timezone(&Utc),
+        Err(_) => {
+            FlashMessage::error(
+                "The new time must use the RFC 3339 format, e.g. 2022-08-20T09:00:00+07:00.",
+            )
+            .send();
+            return Ok(see_other("/admin/scheduled_issues"));
+        }
+    };
+    if send_at <= Utc::now() {
+        FlashMessage::error("The new time must be in the future.").send();
+        return Ok(see_other("/admin/scheduled_issues"));
+    }
+
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+            SET send_at = $2
+            WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+        "#,
+        newsletter_issue_id.into_inner(),
+        send_at
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to reschedule the newsletter issue.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated_rows == 0 {
+        FlashMessage::error("The issue is no longer scheduled.").send();
+    } else {
+        FlashMessage::info("The issue has been rescheduled.").send();
+    }
+
+    Ok(see_other("/admin/scheduled_issues"))
+}
+
+#[tracing::instrument(name = "Cancel a scheduled newsletter issue", skip(session, pool))]
+pub async fn cancel_scheduled_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    // Waits for the scheduler if it is publishing the issue right now
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+            SET status = 'cancelled'
+            WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+        "#,
+        newsletter_issue_id.into_inner()
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to cancel the newsletter issue.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated_rows == 0 {
+        FlashMessage::error("The issue is no longer scheduled.").send();
+    } else {
+        FlashMessage::info("The issue has been cancelled.").send();
+    }
+
+    Ok(see_other("/admin/scheduled_issues"))
+}
@@ -0,0 +1,99 @@
+use crate::lists::get_lists;
+use crate::segments::{count_recipients, get_segments};
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn segments(
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    // Counted over every list: the list an issue goes to narrows it down
+    let list_ids: Vec<_> = get_lists(&pool)
+        .await
+        .map_err(e500)?
+        .into_iter()
+        .map(|list| list.list_id)
+        .collect();
+    let mut rows_html = String::new();
+    for segment in get_segments(&pool).await.map_err(e500)? {
+        let n_recipients = count_recipients(&pool, &list_ids, Some(&segment.filter))
+            .await
+            .map_err(e500)?;
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{name}</td>
+                <td><code>{filter}</code></td>
+                <td>{n_recipients}</td>
+            </tr>"#,
+            name = encode_minimal(&segment.name),
+            filter = encode_minimal(&serde_json::to_string(&segment.filter).map_err(e500)?),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Segments</title>
+            </head>
+            <body>
+                {msg_html}
+                <p>An issue sent to a segment only reaches the members of its list
+                who match the filter.</p>
+                <table>
+                    <tr>
+                        <th>Segment</th>
+                        <th>Filter</th>
+                        <th>Confirmed subscribers</th>
+                    </tr>
+                    {rows_html}
+                </table>
+                <form action="/admin/segments" method="post">
+                    <p>
+                        <label>Name
+                            <input type="text" name="name" placeholder="Enter the segment name">
+                        </label>
+                    </p>
+                    <p>
+                        <label>Filter
+                            <textarea
+                                name="filter"
+                                placeholder='{{"and": [{{"tag": "beta"}}, {{"subscribed_after": "2022-01-01T00:00:00Z"}}]}}'
+                                rows="5"
+                                cols="80"
+                            ></textarea>
+                        </label>
+                    </p>
+                    <p>Filters combine <code>and</code>, <code>or</code> and <code>not</code>
+                    over <code>tag</code>, <code>status</code>, <code>subscribed_before</code>,
+                    <code>subscribed_after</code> and
+                    <code>{{"attribute": {{"name": ..., "equals": ...}}}}</code>.</p>
+                    <button type="submit">Add</button>
+                </form>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+            </html>
+            "#
+        )))
+}
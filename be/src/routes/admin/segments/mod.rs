@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::segments;
+pub use post::create_segment_from_form;
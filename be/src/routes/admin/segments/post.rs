@@ -0,0 +1,53 @@
+use crate::segments::{create_segment, SegmentFilter};
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    filter: String,
+}
+
+#[tracing::instrument(
+    name = "Create a segment from the admin form",
+    skip(form, session, pool)
+)]
+pub async fn create_segment_from_form(
+    form: web::Form<FormData>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let name = form.name.trim();
+    if name.is_empty() {
+        FlashMessage::error("The segment name must not be empty.").send();
+        return Ok(see_other("/admin/segments"));
+    }
+    let filter = match SegmentFilter::parse(&form.filter) {
+        Ok(filter) => filter,
+        Err(e) => {
+            FlashMessage::error(encode_minimal(&e)).send();
+            return Ok(see_other("/admin/segments"));
+        }
+    };
+
+    match create_segment(&pool, name, &filter).await.map_err(e500)? {
+        Some(_) => FlashMessage::info(format!(
+            "The segment {} has been added.",
+            encode_minimal(name)
+        ))
+        .send(),
+        None => FlashMessage::error(format!(
+            "There already is a segment named {}.",
+            encode_minimal(name)
+        ))
+        .send(),
+    }
+    Ok(see_other("/admin/segments"))
+}
@@ -0,0 +1,115 @@
+use crate::session_state::TypedSession;
+use crate::suppressions::{search_suppressions, SuppressionSource, MAX_SEARCH_RESULTS};
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParameters {
+    // Part of the address, every entry if missing
+    q: Option<String>,
+}
+
+pub async fn suppressions(
+    parameters: web::Query<QueryParameters>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let query = parameters.q.as_deref().unwrap_or_default();
+    let mut rows_html = String::new();
+    for suppression in search_suppressions(&pool, query).await.map_err(e500)? {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{email}</td>
+                <td>{source}</td>
+                <td>{reason}</td>
+                <td>{created_at}</td>
+                <td>
+                    <form action="/admin/suppressions/delete" method="post">
+                        <input type="hidden" name="email" value="{email}">
+                        <button type="submit">Remove</button>
+                    </form>
+                </td>
+            </tr>"#,
+            email = encode_minimal(&suppression.email),
+            source = encode_minimal(&suppression.source),
+            reason = encode_minimal(&suppression.reason),
+            created_at = suppression.created_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+    let mut source_options = String::new();
+    for source in SuppressionSource::ALL {
+        writeln!(
+            source_options,
+            r#"<option value="{0}">{0}</option>"#,
+            source.as_str()
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Suppression list</title>
+            </head>
+            <body>
+                {msg_html}
+                <p>Suppressed addresses are never mailed, even if they subscribe again.</p>
+                <form action="/admin/suppressions" method="get">
+                    <label>Address
+                        <input type="search" name="q" value="{query}" placeholder="Part of an address">
+                    </label>
+                    <button type="submit">Search</button>
+                </form>
+                <table>
+                    <tr>
+                        <th>Address</th>
+                        <th>Source</th>
+                        <th>Reason</th>
+                        <th>Added at</th>
+                        <th></th>
+                    </tr>
+                    {rows_html}
+                </table>
+                <p>The {MAX_SEARCH_RESULTS} most recent matches are shown.</p>
+                <form action="/admin/suppressions" method="post">
+                    <label>Address
+                        <input type="email" name="email" placeholder="Enter the address to suppress">
+                    </label>
+                    <label>Source
+                        <select name="source">
+                            {source_options}
+                        </select>
+                    </label>
+                    <label>Reason
+                        <input type="text" name="reason" placeholder="Why it must not be mailed">
+                    </label>
+                    <button type="submit">Suppress</button>
+                </form>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+            </html>
+            "#,
+            query = encode_minimal(query),
+        )))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::suppressions;
+pub use post::{add_suppression_from_form, remove_suppression_from_form};
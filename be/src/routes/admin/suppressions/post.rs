@@ -0,0 +1,94 @@
+use crate::domain::SubscriberEmail;
+use crate::session_state::TypedSession;
+use crate::suppressions::{
+    add_suppression, normalize_email, remove_suppression, SuppressionSource,
+};
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct AddFormData {
+    email: String,
+    source: String,
+    reason: String,
+}
+
+#[tracing::instrument(name = "Suppress an address by hand", skip(form, session, pool))]
+pub async fn add_suppression_from_form(
+    form: web::Form<AddFormData>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let AddFormData {
+        email,
+        source,
+        reason,
+    } = form.into_inner();
+    let email = match SubscriberEmail::parse(email.trim().to_string()) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(encode_minimal(&e)).send();
+            return Ok(see_other("/admin/suppressions"));
+        }
+    };
+    let source = match SuppressionSource::parse(&source) {
+        Ok(source) => source,
+        Err(e) => {
+            FlashMessage::error(encode_minimal(&e)).send();
+            return Ok(see_other("/admin/suppressions"));
+        }
+    };
+    let reason = reason.trim();
+    if reason.is_empty() {
+        FlashMessage::error("The reason must not be empty.").send();
+        return Ok(see_other("/admin/suppressions"));
+    }
+
+    let added = add_suppression(pool.get_ref(), email.as_ref(), reason, source)
+        .await
+        .map_err(e500)?;
+    // As stored
+    let email = normalize_email(email.as_ref());
+    if added {
+        FlashMessage::info(format!("{} has been suppressed.", encode_minimal(&email))).send();
+    } else {
+        FlashMessage::error(format!("{} already is suppressed.", encode_minimal(&email))).send();
+    }
+    Ok(see_other("/admin/suppressions"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RemoveFormData {
+    email: String,
+}
+
+#[tracing::instrument(name = "Remove a suppression by hand", skip(form, session, pool))]
+pub async fn remove_suppression_from_form(
+    form: web::Form<RemoveFormData>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+
+    let removed = remove_suppression(pool.get_ref(), &form.email)
+        .await
+        .map_err(e500)?;
+    if removed {
+        FlashMessage::info(format!(
+            "{} can be mailed again.",
+            encode_minimal(&form.email)
+        ))
+        .send();
+    } else {
+        FlashMessage::error("The address was not suppressed.").send();
+    }
+    Ok(see_other("/admin/suppressions"))
+}
@@ -0,0 +1,100 @@
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub struct Topic {
+    pub topic_id: Uuid,
+    pub name: String,
+    pub n_opted_out: i64,
+}
+
+pub async fn topics(
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut rows_html = String::new();
+    for topic in get_topics(&pool).await.map_err(e500)? {
+        writeln!(
+            rows_html,
+            r#"<tr>
+                <td>{name}</td>
+                <td>{topic_id}</td>
+                <td>{n_opted_out}</td>
+            </tr>"#,
+            name = encode_minimal(&topic.name),
+            topic_id = topic.topic_id,
+            n_opted_out = topic.n_opted_out,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Topics</title>
+            </head>
+            <body>
+                {msg_html}
+                <p>Subscribers receive issues about every topic, unless they opt out
+                from their preferences.</p>
+                <table>
+                    <tr>
+                        <th>Topic</th>
+                        <th>Id</th>
+                        <th>Opted out</th>
+                    </tr>
+                    {rows_html}
+                </table>
+                <form action="/admin/topics" method="post">
+                    <label>New topic
+                        <input type="text" name="name" placeholder="Enter the topic name">
+                    </label>
+                    <button type="submit">Add</button>
+                </form>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+            </html>
+            "#
+        )))
+}
+
+#[tracing::instrument(name = "Get topics", skip(pool))]
+pub async fn get_topics(pool: &PgPool) -> Result<Vec<Topic>, anyhow::Error> {
+    let topics = sqlx::query_as!(
+        Topic,
+        r#"
+        SELECT
+            t.topic_id,
+            t.name,
+            (SELECT COUNT(*) FROM topic_opt_outs o WHERE o.topic_id = t.topic_id) as "n_opted_out!"
+        FROM topics t
+            ORDER BY t.name
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve topics.")?;
+
+    Ok(topics)
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{get_topics, topics, Topic};
+pub use post::create_topic;
@@ -0,0 +1,58 @@
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+}
+
+#[tracing::instrument(name = "Create a topic", skip(form, session, pool))]
+pub async fn create_topic(
+    form: web::Form<FormData>,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_user_id().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let name = form.name.trim();
+    if name.is_empty() {
+        FlashMessage::error("The topic name must not be empty.").send();
+        return Ok(see_other("/admin/topics"));
+    }
+
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO topics (topic_id, name, created_at)
+            VALUES ($1, $2, now())
+            ON CONFLICT (name) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        name
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the topic.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_inserted_rows == 0 {
+        FlashMessage::error(format!(
+            "There already is a topic named {}.",
+            encode_minimal(name)
+        ))
+        .send();
+    } else {
+        FlashMessage::info(format!(
+            "The topic {} has been added.",
+            encode_minimal(name)
+        ))
+        .send();
+    }
+    Ok(see_other("/admin/topics"))
+}
@@ -0,0 +1,312 @@
+use crate::lists::DEFAULT_LIST_SLUG;
+use crate::merge_tags::{render_anonymous, render_anonymous_html};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::e500;
+use actix_web::http::header::{
+    self, EntityTag, Header, HttpDate, IfModifiedSince, IfNoneMatch, LastModified,
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, TimeZone, Utc};
+use htmlescape::encode_minimal;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::fmt::Write;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+const FEED_TITLE: &str = "Our newsletter";
+const FEED_MAX_ITEMS: i64 = 20;
+
+struct FeedItem {
+    newsletter_issue_id: Uuid,
+    title: String,
+    slug: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Copy)]
+enum FeedFormat {
+    Rss,
+    Atom,
+    Json,
+}
+
+impl FeedFormat {
+    fn content_type(&self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+            FeedFormat::Json => "application/feed+json; charset=utf-8",
+        }
+    }
+
+    fn path(&self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "/feed.rss",
+            FeedFormat::Atom => "/feed.atom",
+            FeedFormat::Json => "/feed.json",
+        }
+    }
+}
+
+pub async fn rss_feed(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    feed(FeedFormat::Rss, &request, &pool, &base_url.0).await
+}
+
+pub async fn atom_feed(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    feed(FeedFormat::Atom, &request, &pool, &base_url.0).await
+}
+
+pub async fn json_feed(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    feed(FeedFormat::Json, &request, &pool, &base_url.0).await
+}
+
+#[tracing::instrument(name = "Render a feed of published issues", skip_all)]
+async fn feed(
+    format: FeedFormat,
+    request: &HttpRequest,
+    pool: &PgPool,
+    base_url: &str,
+) -> Result<HttpResponse, actix_web::Error> {
+    let items = get_feed_items(pool).await.map_err(e500)?;
+    let etag = feed_etag(format, base_url, &items);
+    let last_modified = items
+        .iter()
+        .map(|item| item.published_at)
+        .max()
+        // HTTP dates have a one-second resolution
+        .map(|published_at| Utc.timestamp(published_at.timestamp(), 0))
+        .map(|published_at| HttpDate::from(SystemTime::from(published_at)));
+
+    if is_not_modified(request, &etag, last_modified) {
+        let mut response = HttpResponse::NotModified();
+        response.insert_header(header::ETag(etag));
+        if let Some(last_modified) = last_modified {
+            response.insert_header(LastModified(last_modified));
+        }
+        return Ok(response.finish());
+    }
+
+    let body = match format {
+        FeedFormat::Rss => render_rss(base_url, &items),
+        FeedFormat::Atom => render_atom(base_url, &items),
+        FeedFormat::Json => render_json(base_url, &items).map_err(e500)?,
+    };
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type(format.content_type())
+        .insert_header(header::ETag(etag));
+    if let Some(last_modified) = last_modified {
+        response.insert_header(LastModified(last_modified));
+    }
+    Ok(response.body(body))
+}
+
+/// Published issues never change, so the list of issues identifies the feed.
+fn feed_etag(format: FeedFormat, base_url: &str, items: &[FeedItem]) -> EntityTag {
+    let mut hasher = Sha256::new();
+    hasher.update(format.path());
+    hasher.update(base_url);
+    for item in items {
+        hasher.update(item.newsletter_issue_id.as_bytes());
+        hasher.update(item.published_at.to_rfc3339());
+    }
+    let digest = hex::encode(hasher.finalize());
+    EntityTag::new_strong(digest[..32].to_string())
+}
+
+// If-None-Match takes precedence over If-Modified-Since (RFC 7232, section 6).
+fn is_not_modified(
+    request: &HttpRequest,
+    etag: &EntityTag,
+    last_modified: Option<HttpDate>,
+) -> bool {
+    if request.headers().contains_key(header::IF_NONE_MATCH) {
+        return match IfNoneMatch::parse(request) {
+            Ok(IfNoneMatch::Any) => true,
+            Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+            Err(_) => false,
+        };
+    }
+    match (IfModifiedSince::parse(request), last_modified) {
+        (Ok(IfModifiedSince(since)), Some(last_modified)) => {
+            SystemTime::from(last_modified) <= SystemTime::from(since)
+        }
+        _ => false,
+    }
+}
+
+fn permalink(base_url: &str, item: &FeedItem) -> String {
+    format!("{}/issues/{}", base_url, item.slug)
+}
+
+fn render_rss(base_url: &str, items: &[FeedItem]) -> String {
+    let mut items_xml = String::new();
+    for item in items {
+        let link = encode_minimal(&permalink(base_url, item));
+        write!(
+            items_xml,
+            r#"
+        <item>
+            <title>{title}</title>
+            <link>{link}</link>
+            <guid isPermaLink="true">{link}</guid>
+            <pubDate>{published_at}</pubDate>
+            <description>{content}</description>
+        </item>"#,
+            title = encode_minimal(&item.title),
+            published_at = item.published_at.to_rfc2822(),
+            content = encode_minimal(&item.html_content),
+        )
+        .unwrap();
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0">
+    <channel>
+        <title>{title}</title>
+        <link>{base_url}/issues</link>
+        <description>Past issues of {title}</description>{items_xml}
+    </channel>
+</rss>
+"#,
+        title = FEED_TITLE,
+        base_url = encode_minimal(base_url),
+    )
+}
+
+fn render_atom(base_url: &str, items: &[FeedItem]) -> String {
+    let mut entries_xml = String::new();
+    for item in items {
+        let link = encode_minimal(&permalink(base_url, item));
+        write!(
+            entries_xml,
+            r#"
+    <entry>
+        <title>{title}</title>
+        <link href="{link}"/>
+        <id>{link}</id>
+        <published>{published_at}</published>
+        <updated>{published_at}</updated>
+        <content type="html">{content}</content>
+    </entry>"#,
+            title = encode_minimal(&item.title),
+            published_at = item.published_at.to_rfc3339(),
+            content = encode_minimal(&item.html_content),
+        )
+        .unwrap();
+    }
+    let updated = items
+        .iter()
+        .map(|item| item.published_at)
+        .max()
+        .unwrap_or_else(|| Utc.timestamp(0, 0));
+
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+    <title>{title}</title>
+    <link href="{base_url}/issues"/>
+    <link rel="self" href="{base_url}/feed.atom"/>
+    <id>{base_url}/issues</id>
+    <updated>{updated}</updated>
+    <author>
+        <name>{title}</name>
+    </author>{entries_xml}
+</feed>
+"#,
+        title = FEED_TITLE,
+        base_url = encode_minimal(base_url),
+        updated = updated.to_rfc3339(),
+    )
+}
+
+#[derive(serde::Serialize)]
+struct JsonFeed<'a> {
+    version: &'static str,
+    title: &'static str,
+    home_page_url: String,
+    feed_url: String,
+    items: Vec<JsonFeedItem<'a>>,
+}
+
+#[derive(serde::Serialize)]
+struct JsonFeedItem<'a> {
+    id: String,
+    url: String,
+    title: &'a str,
+    content_html: &'a str,
+    date_published: String,
+}
+
+fn render_json(base_url: &str, items: &[FeedItem]) -> Result<String, serde_json::Error> {
+    let feed = JsonFeed {
+        version: "https://jsonfeed.org/version/1.1",
+        title: FEED_TITLE,
+        home_page_url: format!("{}/issues", base_url),
+        feed_url: format!("{}/feed.json", base_url),
+        items: items
+            .iter()
+            .map(|item| JsonFeedItem {
+                id: permalink(base_url, item),
+                url: permalink(base_url, item),
+                title: &item.title,
+                content_html: &item.html_content,
+                date_published: item.published_at.to_rfc3339(),
+            })
+            .collect(),
+    };
+    serde_json::to_string(&feed)
+}
+
+/// The issues of the archive, most recent first.
+#[tracing::instrument(name = "Get feed items", skip(pool))]
+async fn get_feed_items(pool: &PgPool) -> Result<Vec<FeedItem>, anyhow::Error> {
+    let items = sqlx::query_as!(
+        FeedItem,
+        r#"
+        SELECT newsletter_issue_id, title, slug, html_content, published_at as "published_at!"
+            FROM newsletter_issues
+            WHERE status = 'published'
+            AND segment_id IS NULL
+            AND topic_id IS NULL
+            AND newsletter_issue_id IN (
+                SELECT newsletter_issue_id
+                    FROM newsletter_issue_lists
+                    JOIN lists USING (list_id)
+                    WHERE lists.slug = $2
+            )
+            ORDER BY published_at DESC
+            LIMIT $1
+        "#,
+        FEED_MAX_ITEMS,
+        DEFAULT_LIST_SLUG
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve feed items.")?
+    .into_iter()
+    .map(|item| FeedItem {
+        title: render_anonymous(&item.title),
+        html_content: render_anonymous_html(&item.html_content),
+        ..item
+    })
+    .collect();
+
+    Ok(items)
+}
@@ -0,0 +1,149 @@
+use crate::lists::DEFAULT_LIST_SLUG;
+use crate::merge_tags::{render_anonymous, render_anonymous_html};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+struct ArchivedIssueSummary {
+    title: String,
+    slug: String,
+    published_at: DateTime<Utc>,
+}
+
+pub async fn issues_archive(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let mut items_html = String::new();
+    for issue in get_archived_issues(&pool).await.map_err(e500)? {
+        writeln!(
+            items_html,
+            r#"<li><a href="/issues/{slug}">{title}</a> - {published_at}</li>"#,
+            slug = issue.slug,
+            title = encode_minimal(&render_anonymous(&issue.title)),
+            published_at = issue.published_at.format("%B %-d, %Y"),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Past issues</title>
+            </head>
+            <body>
+                <h1>Past issues</h1>
+                <ul>
+                    {items_html}
+                </ul>
+                <p><a href="/">&lt;- Home</a></p>
+            </body>
+            </html>
+            "#
+        )))
+}
+
+struct ArchivedIssue {
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+pub async fn archived_issue(
+    slug: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue = match get_archived_issue(&pool, &slug).await.map_err(e500)? {
+        Some(issue) => issue,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>{title}</title>
+            </head>
+            <body>
+                <h1>{title}</h1>
+                <p>{published_at}</p>
+                {html_content}
+                <p><a href="/issues">&lt;- Past issues</a></p>
+            </body>
+            </html>
+            "#,
+            title = encode_minimal(&render_anonymous(&issue.title)),
+            published_at = issue.published_at.format("%B %-d, %Y"),
+            html_content = render_anonymous_html(&issue.html_content),
+        )))
+}
+
+/// Only issues sent to everybody are archived: those of the default list,
+/// with no segment or topic.
+#[tracing::instrument(name = "Get archived issues", skip(pool))]
+async fn get_archived_issues(pool: &PgPool) -> Result<Vec<ArchivedIssueSummary>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        ArchivedIssueSummary,
+        r#"
+        SELECT title, slug, published_at as "published_at!"
+            FROM newsletter_issues
+            WHERE status = 'published'
+            AND segment_id IS NULL
+            AND topic_id IS NULL
+            AND newsletter_issue_id IN (
+                SELECT newsletter_issue_id
+                    FROM newsletter_issue_lists
+                    JOIN lists USING (list_id)
+                    WHERE lists.slug = $1
+            )
+            ORDER BY published_at DESC
+        "#,
+        DEFAULT_LIST_SLUG
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve archived issues.")?;
+
+    Ok(issues)
+}
+
+#[tracing::instrument(name = "Get an archived issue", skip(pool))]
+async fn get_archived_issue(
+    pool: &PgPool,
+    slug: &str,
+) -> Result<Option<ArchivedIssue>, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT title, html_content, published_at as "published_at!"
+            FROM newsletter_issues
+            WHERE slug = $1 AND status = 'published'
+            AND segment_id IS NULL
+            AND topic_id IS NULL
+            AND newsletter_issue_id IN (
+                SELECT newsletter_issue_id
+                    FROM newsletter_issue_lists
+                    JOIN lists USING (list_id)
+                    WHERE lists.slug = $2
+            )
+        "#,
+        slug,
+        DEFAULT_LIST_SLUG
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve an archived issue.")?;
+
+    Ok(issue)
+}
@@ -1,15 +1,29 @@
 mod admin;
+mod feeds;
 mod health_check;
 mod home;
+mod issues;
 mod login;
 mod newsletters;
+mod open_tracking;
+mod segments;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_preferences;
+mod subscriptions_unsubscribe;
+mod webhooks;
 
 pub use admin::*;
+pub use feeds::*;
 pub use health_check::*;
 pub use home::*;
+pub use issues::*;
 pub use login::*;
 pub use newsletters::*;
+pub use open_tracking::*;
+pub use segments::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_preferences::*;
+pub use subscriptions_unsubscribe::*;
+pub use webhooks::*;
@@ -1,20 +1,31 @@
 use crate::authentication::{validate_credentials, AuthError, Credentials};
-use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::domain::{IssueSlug, SubscriberEmail};
+use crate::email_client::{EmailSender, SendEmailError};
+use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::issue_delivery_report::get_delivery_report;
+use crate::lists::{find_unknown_list, get_default_list};
+use crate::markdown;
+use crate::merge_tags::{IssueTemplate, MergeContext};
 use crate::routes::error_chain_fmt;
+use crate::segments::{get_segment, SegmentFilter};
+use crate::suppressions::find_suppressed;
 use actix_web::http::header::{HeaderMap, HeaderValue};
 use actix_web::http::{header, StatusCode};
 use actix_web::HttpRequest;
 use actix_web::{web, HttpResponse, ResponseError};
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use secrecy::Secret;
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, QueryBuilder, Transaction};
 use std::fmt::{Debug, Formatter};
+use uuid::Uuid;
 
 #[derive(thiserror::Error)]
 pub enum PublishError {
     #[error("Authentication failed.")]
     AuthError(#[source] anyhow::Error),
+    #[error("{0}")]
+    ValidationError(String),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -31,6 +42,7 @@ impl ResponseError for PublishError {
             PublishError::UnexpectedError(_) => {
                 HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
             }
+            PublishError::ValidationError(_) => HttpResponse::new(StatusCode::BAD_REQUEST),
             PublishError::AuthError(_) => {
                 let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
                 let header_value = HeaderValue::from_str(r#"Basic realm="publish""#).unwrap();
@@ -45,7 +57,7 @@ impl ResponseError for PublishError {
 }
 
 // ----------
-fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+pub(super) fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
     let header_value = headers
         .get("Authorization")
         .context("The 'Authorization' header was missing")?
@@ -77,89 +89,461 @@ fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Erro
     })
 }
 
+// ----------
+fn idempotency_key(headers: &HeaderMap) -> Result<Option<IdempotencyKey>, anyhow::Error> {
+    let header_value = match headers.get("Idempotency-Key") {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    let key = header_value
+        .to_str()
+        .context("The 'Idempotency-Key' header was not a valid UTF8 string.")?
+        .to_string();
+
+    Ok(Some(key.try_into()?))
+}
+
+// Records `username` and `user_id` on the current span.
+pub(super) async fn authenticate(
+    request: &HttpRequest,
+    pool: &PgPool,
+) -> Result<Uuid, PublishError> {
+    let credentials = basic_authentication(request.headers()).map_err(PublishError::AuthError)?;
+    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    let user_id = validate_credentials(credentials, pool)
+        .await
+        .map_err(|e| match e {
+            AuthError::InvalidCredentials(_) => PublishError::AuthError(e.into()),
+            AuthError::UnexpectedError(_) => PublishError::UnexpectedError(e.into()),
+        })?;
+    tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+
+    Ok(user_id)
+}
+
 // ----------
 #[derive(serde::Deserialize)]
 pub struct BodyData {
     title: String,
     content: Content,
+    // RFC 3339, with an explicit offset: the issue is held back until then
+    send_at: Option<DateTime<Utc>>,
+    // Subscribers who opted out of the topic don't receive the issue
+    topic_id: Option<Uuid>,
+    // The lists whose confirmed members receive the issue, the default list if missing
+    list_ids: Option<Vec<Uuid>>,
+    // Only the members who match the segment receive the issue
+    segment_id: Option<Uuid>,
+    // Add a tracking pixel to the HTML body, off by default
+    #[serde(default)]
+    track_opens: bool,
 }
 
+/// Either a single Markdown source, or hand-written HTML and plain-text bodies.
 #[derive(serde::Deserialize)]
-pub struct Content {
-    html: String,
-    text: String,
+#[serde(untagged)]
+pub enum Content {
+    Markdown { markdown: String },
+    HtmlAndText { html: String, text: String },
+}
+
+impl Content {
+    /// The HTML and plain-text bodies of the email.
+    pub fn render(&self) -> (String, String) {
+        match self {
+            Content::Markdown { markdown } => (
+                markdown::render_html(markdown),
+                markdown::render_text(markdown),
+            ),
+            Content::HtmlAndText { html, text } => (html.clone(), text.clone()),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct PublishResponse {
+    newsletter_issue_id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    send_at: Option<DateTime<Utc>>,
 }
 
 #[tracing::instrument(
     name = "Publish a newsletter issue",
-    skip(body, pool, email_client, request),
+    skip(body, pool, request),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn publish_newsletter(
     body: web::Json<BodyData>,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
     request: HttpRequest,
 ) -> Result<HttpResponse, PublishError> {
-    let credentials = basic_authentication(request.headers()).map_err(PublishError::AuthError)?;
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
-    let user_id = validate_credentials(credentials, &pool)
-        .await
-        .map_err(|e| match e {
-            AuthError::InvalidCredentials(_) => PublishError::AuthError(e.into()),
-            AuthError::UnexpectedError(_) => PublishError::UnexpectedError(e.into()),
-        })?;
-    tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+    let user_id = authenticate(&request, &pool).await?;
 
-    let subscribers = get_confirmed_subscribers(&pool).await?;
-    for subscriber in subscribers {
-        match subscriber {
-            Ok(sub) => {
-                email_client
-                    .send_email(
-                        &sub.email,
-                        &body.title,
-                        &body.content.html,
-                        &body.content.text,
-                    )
-                    .await
-                    .with_context(|| {
-                        format!("Failed to send newsletter issue to {}", &sub.email)
-                    })?;
+    // Merge tags are checked now rather than halfway through a send
+    let (html_content, text_content) = body.content.render();
+    IssueTemplate::parse(&body.title, &html_content, &text_content)
+        .map_err(PublishError::ValidationError)?;
+    if let Some(topic_id) = body.topic_id {
+        if !topic_exists(&pool, topic_id).await? {
+            return Err(PublishError::ValidationError(format!(
+                "{} is not a known topic.",
+                topic_id
+            )));
+        }
+    }
+    let list_ids = match &body.list_ids {
+        Some(list_ids) => {
+            if list_ids.is_empty() {
+                return Err(PublishError::ValidationError(
+                    "An issue must be sent to at least one list.".into(),
+                ));
             }
-            Err(error) => {
-                tracing::warn!(error.cause_chain = ?error, "Skipping a confirmed subscriber. \
-                    Their stored contact details are invalid" )
+            if let Some(list_id) = find_unknown_list(&pool, list_ids).await? {
+                return Err(PublishError::ValidationError(format!(
+                    "{} is not a known list.",
+                    list_id
+                )));
             }
+            list_ids.clone()
+        }
+        None => vec![get_default_list(pool.get_ref()).await?.list_id],
+    };
+    if let Some(segment_id) = body.segment_id {
+        if get_segment(pool.get_ref(), segment_id).await?.is_none() {
+            return Err(PublishError::ValidationError(format!(
+                "{} is not a known segment.",
+                segment_id
+            )));
+        }
+    }
+
+    let idempotency_key = idempotency_key(request.headers())
+        .map_err(|e| PublishError::ValidationError(e.to_string()))?;
+    let mut transaction = match &idempotency_key {
+        Some(key) => match try_processing(&pool, key, user_id).await? {
+            NextAction::StartProcessing(t) => t,
+            NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+        },
+        None => pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool")?,
+    };
+
+    // A `send_at` in the past means "as soon as possible"
+    let send_at = body.send_at.filter(|send_at| *send_at > Utc::now());
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &body.title,
+        &text_content,
+        &html_content,
+        send_at,
+        &IssueAudience {
+            list_ids: &list_ids,
+            topic_id: body.topic_id,
+            segment_id: body.segment_id,
+        },
+        body.track_opens,
+    )
+    .await
+    .context("Failed to store newsletter issue details")?;
+    if send_at.is_none() {
+        enqueue_delivery_tasks(&mut transaction, issue_id)
+            .await
+            .context("Failed to enqueue delivery tasks")?;
+    }
+
+    let response = HttpResponse::Ok().json(PublishResponse {
+        newsletter_issue_id: issue_id,
+        send_at,
+    });
+    match idempotency_key {
+        Some(key) => {
+            let response = save_response(transaction, &key, user_id, response).await?;
+            Ok(response)
+        }
+        None => {
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to store a newsletter issue.")?;
+            Ok(response)
         }
     }
+}
+
+#[tracing::instrument(
+    name = "Get the delivery report of a newsletter issue",
+    skip(newsletter_issue_id, pool, request),
+    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+)]
+pub async fn newsletter_issue_report(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, PublishError> {
+    authenticate(&request, &pool).await?;
+
+    match get_delivery_report(&pool, newsletter_issue_id.into_inner()).await? {
+        Some(report) => Ok(HttpResponse::Ok().json(report)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+/// Test copies are meant for a handful of reviewers, not for a real send.
+pub const MAX_TEST_RECIPIENTS: usize = 10;
+
+#[derive(serde::Deserialize)]
+pub struct TestBodyData {
+    title: String,
+    content: Content,
+    recipients: Vec<String>,
+}
+
+#[tracing::instrument(
+    name = "Send a test copy of a newsletter issue",
+    skip(body, pool, email_client, request),
+    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+)]
+pub async fn send_test_newsletter(
+    body: web::Json<TestBodyData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<dyn EmailSender>,
+    request: HttpRequest,
+) -> Result<HttpResponse, PublishError> {
+    authenticate(&request, &pool).await?;
+
+    let TestBodyData {
+        title,
+        content,
+        recipients,
+    } = body.0;
+    let recipients = parse_test_recipients(recipients).map_err(PublishError::ValidationError)?;
+    if let Some(email) = find_suppressed_recipient(&pool, &recipients).await? {
+        return Err(PublishError::ValidationError(format!(
+            "{} is on the suppression list.",
+            email
+        )));
+    }
+    let (html_content, text_content) = content.render();
+    let template = IssueTemplate::parse(&title, &html_content, &text_content)
+        .map_err(PublishError::ValidationError)?;
+    send_test_copy(email_client.get_ref(), &recipients, &template)
+        .await
+        .context("Failed to send a test copy of the newsletter issue")?;
+
     Ok(HttpResponse::Ok().finish())
 }
 
-// ----------
-struct ConfirmedSubscriber {
-    email: SubscriberEmail,
+pub fn parse_test_recipients(recipients: Vec<String>) -> Result<Vec<SubscriberEmail>, String> {
+    if recipients.is_empty() {
+        return Err("At least one recipient is required for a test copy.".into());
+    }
+    if recipients.len() > MAX_TEST_RECIPIENTS {
+        return Err(format!(
+            "A test copy can be sent to at most {} recipients.",
+            MAX_TEST_RECIPIENTS
+        ));
+    }
+    recipients.into_iter().map(SubscriberEmail::parse).collect()
 }
 
-#[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
-async fn get_confirmed_subscribers(
+/// Test copies don't go to suppressed addresses either.
+pub async fn find_suppressed_recipient(
     pool: &PgPool,
-) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
-    let confirmed_subscribers = sqlx::query!(
+    recipients: &[SubscriberEmail],
+) -> Result<Option<String>, anyhow::Error> {
+    let emails: Vec<&str> = recipients.iter().map(|r| r.as_ref()).collect();
+    find_suppressed(pool, &emails).await
+}
+
+/// Mail an issue straight to `recipients`, bypassing the delivery queue:
+/// nothing is stored, so a test copy never counts as a publication.
+///
+/// Reviewers are not subscribers: merge tags fall back to their defaults,
+/// and the unsubscribe and preferences links go nowhere.
+#[tracing::instrument(skip(email_client, template))]
+pub async fn send_test_copy(
+    email_client: &dyn EmailSender,
+    recipients: &[SubscriberEmail],
+    template: &IssueTemplate,
+) -> Result<(), SendEmailError> {
+    for recipient in recipients {
+        let context = MergeContext {
+            name: "",
+            email: recipient.as_ref(),
+            unsubscribe_url: "#",
+            preferences_url: "#",
+        };
+        let (subject, html_content, text_content) = template.render(&context);
+        email_client
+            .send_email(
+                recipient,
+                &format!("[TEST] {}", subject),
+                &html_content,
+                &text_content,
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn topic_exists(pool: &PgPool, topic_id: Uuid) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT EXISTS (SELECT 1 FROM topics WHERE topic_id = $1) as "exists!""#,
+        topic_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to check whether a topic exists.")?;
+
+    Ok(row.exists)
+}
+
+// ----------
+/// Who receives an issue: the confirmed members of `list_ids`, minus those
+/// who opted out of `topic_id` and those outside of `segment_id`, if set.
+pub struct IssueAudience<'a> {
+    pub list_ids: &'a [Uuid],
+    pub topic_id: Option<Uuid>,
+    pub segment_id: Option<Uuid>,
+}
+
+/// Store a newsletter issue: published straight away, or scheduled if `send_at` is set.
+/// Delivery tasks for a published issue must be enqueued by the caller.
+#[tracing::instrument(skip_all)]
+pub async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+    send_at: Option<DateTime<Utc>>,
+    audience: &IssueAudience<'_>,
+    track_opens: bool,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    let base_slug = IssueSlug::from_title(title);
+    let mut slug = base_slug.clone();
+    // Two issues can share a title, but not a permalink
+    for n in 2.. {
+        let n_inserted_rows = sqlx::query!(
+            r#"
+            INSERT INTO newsletter_issues (
+                newsletter_issue_id,
+                title,
+                text_content,
+                html_content,
+                slug,
+                status,
+                send_at,
+                published_at,
+                topic_id,
+                segment_id,
+                track_opens
+            )
+            VALUES (
+                $1, $2, $3, $4, $5,
+                CASE WHEN $6::timestamptz IS NULL THEN 'published' ELSE 'scheduled' END,
+                $6,
+                CASE WHEN $6::timestamptz IS NULL THEN now() END,
+                $7,
+                $8,
+                $9
+            )
+            ON CONFLICT (slug) DO NOTHING
+            "#,
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            slug.as_ref(),
+            send_at,
+            audience.topic_id,
+            audience.segment_id,
+            track_opens
+        )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+        if n_inserted_rows > 0 {
+            break;
+        }
+        slug = base_slug.with_suffix(n);
+    }
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+            SELECT $1, list_id FROM UNNEST($2::uuid[]) AS list_id
+        "#,
+        newsletter_issue_id,
+        audience.list_ids
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    Ok(newsletter_issue_id)
+}
+
+/// Queue a delivery for every subscriber in the audience of an issue.
+#[tracing::instrument(skip_all)]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    // The segment is read when the issue goes out, not when it was scheduled
+    let segment_filter = sqlx::query!(
         r#"
-        SELECT email
-            FROM subscriptions
-            WHERE status = 'confirmed'
+        SELECT g.filter::text as "filter!"
+            FROM newsletter_issues i
+            JOIN segments g ON g.segment_id = i.segment_id
+            WHERE i.newsletter_issue_id = $1
         "#,
+        newsletter_issue_id
     )
-    .fetch_all(pool)
+    .fetch_optional(&mut *transaction)
     .await?
-    .into_iter()
-    .map(|r| match SubscriberEmail::parse(r.email) {
-        Ok(email) => Ok(ConfirmedSubscriber { email }),
-        Err(err) => Err(anyhow::anyhow!(err)),
-    })
-    .collect();
+    .map(|r| serde_json::from_str::<SegmentFilter>(&r.filter))
+    .transpose()
+    .map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+
+    let mut query = QueryBuilder::new(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email
+        )
+        SELECT i.newsletter_issue_id, s.email
+            FROM subscriptions s, newsletter_issues i
+            WHERE i.newsletter_issue_id = "#,
+    );
+    query.push_bind(newsletter_issue_id);
+    query.push(
+        r#"
+                AND s.status = 'confirmed'
+                AND NOT EXISTS (
+                    SELECT 1 FROM suppressions x WHERE x.email = lower(trim(s.email))
+                )
+                AND EXISTS (
+                    SELECT 1
+                        FROM list_memberships m
+                        JOIN newsletter_issue_lists l ON l.list_id = m.list_id
+                        WHERE l.newsletter_issue_id = i.newsletter_issue_id
+                            AND m.subscriber_id = s.id
+                            AND m.status = 'confirmed'
+                )
+                AND NOT EXISTS (
+                    SELECT 1
+                        FROM topic_opt_outs o
+                        WHERE o.topic_id = i.topic_id AND o.subscriber_id = s.id
+                )"#,
+    );
+    if let Some(filter) = segment_filter {
+        query.push("\n                AND ");
+        filter.push_condition(&mut query);
+    }
+    query.build().execute(transaction).await?;
 
-    Ok(confirmed_subscribers)
+    Ok(())
 }
@@ -0,0 +1,237 @@
+use crate::startup::HmacSecret;
+use actix_web::http::header::{self, CacheControl, CacheDirective};
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use htmlescape::encode_minimal;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use tracing::Instrument;
+use uuid::Uuid;
+
+/// A transparent 1x1 GIF.
+const PIXEL: [u8; 43] = [
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+// Longer user agents are truncated before being stored
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+fn open_mac(
+    hmac_secret: &Secret<String>,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+) -> Hmac<sha2::Sha256> {
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(hmac_secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(format!("open:{}:{}", newsletter_issue_id, subscriber_id).as_bytes());
+    mac
+}
+
+/// The issue and the subscriber, followed by their signature, so that opens
+/// cannot be forged for somebody else.
+fn open_token(
+    hmac_secret: &Secret<String>,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+) -> String {
+    let mut token = Vec::with_capacity(64);
+    token.extend_from_slice(newsletter_issue_id.as_bytes());
+    token.extend_from_slice(subscriber_id.as_bytes());
+    token.extend_from_slice(
+        &open_mac(hmac_secret, newsletter_issue_id, subscriber_id)
+            .finalize()
+            .into_bytes(),
+    );
+    base64::encode_config(token, base64::URL_SAFE_NO_PAD)
+}
+
+/// The issue and the subscriber of a token, if it was signed by us.
+fn parse_open_token(hmac_secret: &Secret<String>, token: &str) -> Option<(Uuid, Uuid)> {
+    let token = base64::decode_config(token, base64::URL_SAFE_NO_PAD).ok()?;
+    if token.len() <= 32 {
+        return None;
+    }
+    let newsletter_issue_id = Uuid::from_slice(&token[..16]).ok()?;
+    let subscriber_id = Uuid::from_slice(&token[16..32]).ok()?;
+    open_mac(hmac_secret, newsletter_issue_id, subscriber_id)
+        .verify_slice(&token[32..])
+        .ok()?;
+    Some((newsletter_issue_id, subscriber_id))
+}
+
+/// The address of the tracking pixel of an issue, unique to each recipient.
+pub fn open_tracking_url(
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+) -> String {
+    format!(
+        "{}/t/open/{}",
+        base_url,
+        open_token(hmac_secret, newsletter_issue_id, subscriber_id)
+    )
+}
+
+/// Add the tracking pixel at the end of the body of an HTML email.
+pub fn add_tracking_pixel(html_content: &str, tracking_url: &str) -> String {
+    let pixel = format!(
+        r#"<img src="{}" width="1" height="1" alt="" style="display:block;border:0">"#,
+        encode_minimal(tracking_url)
+    );
+    // ASCII lowercasing keeps the byte offsets of the original
+    match html_content.to_ascii_lowercase().rfind("</body>") {
+        Some(end_of_body) => format!(
+            "{}{}{}",
+            &html_content[..end_of_body],
+            pixel,
+            &html_content[end_of_body..]
+        ),
+        None => format!("{}{}", html_content, pixel),
+    }
+}
+
+/// Record that a recipient opened an issue, when their mail client loads the
+/// tracking pixel.
+///
+/// The open is stored in the background: the pixel is returned without
+/// waiting for the database, and even if the open cannot be stored, as a
+/// broken image in the middle of an email would be worse than a missing open.
+#[tracing::instrument(
+    name = "Track an open",
+    skip(token, pool, secret, request),
+    fields(newsletter_issue_id = tracing::field::Empty, subscriber_id = tracing::field::Empty)
+)]
+pub async fn track_open(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+    request: HttpRequest,
+) -> HttpResponse {
+    let (newsletter_issue_id, subscriber_id) = match parse_open_token(&secret.0, &token) {
+        Some(ids) => ids,
+        None => return HttpResponse::NotFound().finish(),
+    };
+    tracing::Span::current()
+        .record(
+            "newsletter_issue_id",
+            &tracing::field::display(newsletter_issue_id),
+        )
+        .record("subscriber_id", &tracing::field::display(subscriber_id));
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.chars().take(MAX_USER_AGENT_LENGTH).collect::<String>());
+    let pool = pool.into_inner();
+    tokio::spawn(
+        async move {
+            if let Err(e) = record_open(
+                &pool,
+                newsletter_issue_id,
+                subscriber_id,
+                user_agent.as_deref(),
+            )
+            .await
+            {
+                tracing::error!(error.cause_chain = ?e, "Failed to record an open");
+            }
+        }
+        .instrument(tracing::Span::current()),
+    );
+
+    HttpResponse::Ok()
+        .content_type("image/gif")
+        // Every open must reach us, rather than a cached copy of the pixel
+        .insert_header(CacheControl(vec![
+            CacheDirective::NoStore,
+            CacheDirective::NoCache,
+            CacheDirective::MustRevalidate,
+            CacheDirective::Private,
+        ]))
+        .body(PIXEL.as_slice())
+}
+
+#[tracing::instrument(skip(pool))]
+async fn record_open(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    user_agent: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_opens (
+            open_id,
+            newsletter_issue_id,
+            subscriber_id,
+            opened_at,
+            user_agent
+        )
+        VALUES ($1, $2, $3, now(), $4)
+        "#,
+        Uuid::new_v4(),
+        newsletter_issue_id,
+        subscriber_id,
+        user_agent
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store an open.")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_tracking_pixel, open_token, parse_open_token};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn secret() -> Secret<String> {
+        Secret::new("long-and-very-secret-random-key".into())
+    }
+
+    #[test]
+    fn tokens_can_be_parsed_back() {
+        let newsletter_issue_id = Uuid::new_v4();
+        let subscriber_id = Uuid::new_v4();
+
+        let token = open_token(&secret(), newsletter_issue_id, subscriber_id);
+
+        assert_eq!(
+            parse_open_token(&secret(), &token),
+            Some((newsletter_issue_id, subscriber_id))
+        );
+    }
+
+    #[test]
+    fn tampered_tokens_are_rejected() {
+        let token = open_token(&secret(), Uuid::new_v4(), Uuid::new_v4());
+        let mut decoded = base64::decode_config(&token, base64::URL_SAFE_NO_PAD).unwrap();
+        // Somebody else's subscription, with the original signature
+        decoded[20] ^= 1;
+        let tampered = base64::encode_config(decoded, base64::URL_SAFE_NO_PAD);
+
+        assert_eq!(parse_open_token(&secret(), &tampered), None);
+        assert_eq!(parse_open_token(&secret(), "not-a-token"), None);
+        assert_eq!(
+            parse_open_token(&Secret::new("another-secret".into()), &token),
+            None
+        );
+    }
+
+    #[test]
+    fn the_pixel_goes_at_the_end_of_the_body() {
+        let html = add_tracking_pixel("<html><BODY><p>Hi</p></BODY></html>", "https://t/1");
+
+        assert_eq!(
+            html,
+            r#"<html><BODY><p>Hi</p><img src="https://t/1" width="1" height="1" alt="" style="display:block;border:0"></BODY></html>"#
+        );
+        assert!(add_tracking_pixel("<p>Hi</p>", "https://t/1").starts_with("<p>Hi</p><img "));
+    }
+}
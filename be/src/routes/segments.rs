@@ -0,0 +1,256 @@
+use super::newsletters::authenticate;
+use crate::domain::SubscriberEmail;
+use crate::lists::{find_unknown_list, get_default_list};
+use crate::routes::PublishError;
+use crate::segments::{
+    count_recipients, create_segment, get_segment, normalize_attribute_name, normalize_tag,
+    SegmentFilter,
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct TagsBodyData {
+    email: String,
+    tags: Vec<String>,
+}
+
+/// Replace the tags of a subscriber.
+#[tracing::instrument(
+    name = "Set the tags of a subscriber",
+    skip(body, pool, request),
+    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+)]
+pub async fn set_subscriber_tags(
+    body: web::Json<TagsBodyData>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, PublishError> {
+    authenticate(&request, &pool).await?;
+    let tags = body
+        .tags
+        .iter()
+        .map(|tag| normalize_tag(tag))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(PublishError::ValidationError)?;
+    let subscriber_id = match get_subscriber_id(&pool, &body.email).await? {
+        Some(subscriber_id) => subscriber_id,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    sqlx::query!(
+        r#"DELETE FROM subscriber_tags WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to clear the subscriber's tags.")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag)
+            SELECT $1, tag FROM UNNEST($2::text[]) AS tag
+            ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        &tags
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the subscriber's tags.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a subscriber's tags.")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(serde::Deserialize)]
+pub struct AttributesBodyData {
+    email: String,
+    attributes: HashMap<String, String>,
+}
+
+/// Replace the custom attributes of a subscriber.
+#[tracing::instrument(
+    name = "Set the attributes of a subscriber",
+    skip(body, pool, request),
+    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+)]
+pub async fn set_subscriber_attributes(
+    body: web::Json<AttributesBodyData>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, PublishError> {
+    authenticate(&request, &pool).await?;
+    let mut names = Vec::with_capacity(body.attributes.len());
+    let mut values = Vec::with_capacity(body.attributes.len());
+    for (name, value) in &body.attributes {
+        let name = normalize_attribute_name(name).map_err(PublishError::ValidationError)?;
+        if names.contains(&name) {
+            return Err(PublishError::ValidationError(format!(
+                "The attribute {} is given more than once.",
+                name
+            )));
+        }
+        names.push(name);
+        values.push(value.clone());
+    }
+    let subscriber_id = match get_subscriber_id(&pool, &body.email).await? {
+        Some(subscriber_id) => subscriber_id,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    sqlx::query!(
+        r#"DELETE FROM subscriber_attributes WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to clear the subscriber's attributes.")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_attributes (subscriber_id, name, value)
+            SELECT $1, name, value FROM UNNEST($2::text[], $3::text[]) AS a(name, value)
+        "#,
+        subscriber_id,
+        &names,
+        &values
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the subscriber's attributes.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a subscriber's attributes.")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+async fn get_subscriber_id(pool: &PgPool, email: &str) -> Result<Option<Uuid>, PublishError> {
+    let email = SubscriberEmail::parse(email.to_string()).map_err(PublishError::ValidationError)?;
+    let row = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE email = $1"#,
+        email.as_ref()
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscriber.")?;
+
+    Ok(row.map(|r| r.id))
+}
+
+#[derive(serde::Deserialize)]
+pub struct SegmentBodyData {
+    name: String,
+    filter: serde_json::Value,
+}
+
+#[derive(serde::Serialize)]
+struct SegmentResponse {
+    segment_id: Uuid,
+}
+
+#[tracing::instrument(
+    name = "Save a segment",
+    skip(body, pool, request),
+    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+)]
+pub async fn save_segment(
+    body: web::Json<SegmentBodyData>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, PublishError> {
+    authenticate(&request, &pool).await?;
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err(PublishError::ValidationError(
+            "The segment name must not be empty.".into(),
+        ));
+    }
+    let filter =
+        SegmentFilter::parse(&body.filter.to_string()).map_err(PublishError::ValidationError)?;
+
+    match create_segment(&pool, name, &filter).await? {
+        Some(segment_id) => Ok(HttpResponse::Ok().json(SegmentResponse { segment_id })),
+        None => Err(PublishError::ValidationError(format!(
+            "There already is a segment named {}.",
+            name
+        ))),
+    }
+}
+
+/// Either a saved segment or a filter to try out.
+#[derive(serde::Deserialize)]
+pub struct CountBodyData {
+    segment_id: Option<Uuid>,
+    filter: Option<serde_json::Value>,
+    // The default list if missing, as when publishing
+    list_ids: Option<Vec<Uuid>>,
+}
+
+#[derive(serde::Serialize)]
+struct CountResponse {
+    recipients: i64,
+}
+
+/// Preview how many subscribers an issue would reach.
+#[tracing::instrument(
+    name = "Count the recipients of a segment",
+    skip(body, pool, request),
+    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+)]
+pub async fn count_segment_recipients(
+    body: web::Json<CountBodyData>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, PublishError> {
+    authenticate(&request, &pool).await?;
+    let filter = match (body.segment_id, &body.filter) {
+        (Some(_), Some(_)) => {
+            return Err(PublishError::ValidationError(
+                "Count either a segment or a filter, not both.".into(),
+            ))
+        }
+        (Some(segment_id), None) => match get_segment(pool.get_ref(), segment_id).await? {
+            Some(segment) => Some(segment.filter),
+            None => {
+                return Err(PublishError::ValidationError(format!(
+                    "{} is not a known segment.",
+                    segment_id
+                )))
+            }
+        },
+        (None, Some(filter)) => {
+            Some(SegmentFilter::parse(&filter.to_string()).map_err(PublishError::ValidationError)?)
+        }
+        (None, None) => None,
+    };
+    let list_ids = match &body.list_ids {
+        Some(list_ids) => {
+            if let Some(list_id) = find_unknown_list(&pool, list_ids).await? {
+                return Err(PublishError::ValidationError(format!(
+                    "{} is not a known list.",
+                    list_id
+                )));
+            }
+            list_ids.clone()
+        }
+        None => vec![get_default_list(pool.get_ref()).await?.list_id],
+    };
+
+    let recipients = count_recipients(&pool, &list_ids, filter.as_ref()).await?;
+    Ok(HttpResponse::Ok().json(CountResponse { recipients }))
+}
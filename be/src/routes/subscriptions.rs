@@ -1,6 +1,8 @@
 use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
-use crate::email_client::EmailClient;
+use crate::email_client::{EmailSender, SendEmailError};
+use crate::lists::{get_list_by_slug, DEFAULT_LIST_SLUG};
 use crate::startup::ApplicationBaseUrl;
+use crate::suppressions::get_suppression;
 use actix_web::http::StatusCode;
 use actix_web::{web, HttpResponse, ResponseError};
 use anyhow::Context;
@@ -25,6 +27,8 @@ fn generate_subscription_token() -> String {
 pub struct FormData {
     email: String,
     name: String,
+    // The slug of the list to join, the default list if missing
+    list: Option<String>,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -48,10 +52,27 @@ impl TryFrom<FormData> for NewSubscriber {
 pub async fn subscribe(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
+    email_client: web::Data<dyn EmailSender>,
     base_url: web::Data<ApplicationBaseUrl>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    let mut form = form.into_inner();
+    let list_slug = form
+        .list
+        .take()
+        .unwrap_or_else(|| DEFAULT_LIST_SLUG.to_string());
+    let new_subscriber: NewSubscriber = form.try_into().map_err(SubscribeError::ValidationError)?;
+    let list = get_list_by_slug(pool.get_ref(), &list_slug)
+        .await?
+        .ok_or_else(|| {
+            SubscribeError::ValidationError(format!("{} is not a known list.", list_slug))
+        })?;
+    // Nothing tells them apart from a successful subscription: the suppression
+    // list is nobody's business
+    let suppression = get_suppression(pool.get_ref(), new_subscriber.email.as_ref()).await?;
+    if matches!(suppression, Some(s) if s.blocks_subscriptions()) {
+        tracing::info!("Ignoring a subscription from a suppressed address");
+        return Ok(HttpResponse::Ok().finish());
+    }
     let mut transaction = pool
         .begin()
         .await
@@ -60,10 +81,26 @@ pub async fn subscribe(
     let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
         .await
         .context("Failed to insert new subscriber in the database.")?;
-    let subscription_token = generate_subscription_token();
-    store_token(&mut transaction, subscriber_id, &subscription_token)
+    let needs_confirmation = insert_list_membership(&mut transaction, list.list_id, subscriber_id)
         .await
-        .context("Failed to store the confirmation token for a new subscriber.")?;
+        .context("Failed to add the subscriber to the list.")?;
+    if !needs_confirmation {
+        // Already a member: there is nothing to confirm
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to store a new subscriber.")?;
+        return Ok(HttpResponse::Ok().finish());
+    }
+    let subscription_token = generate_subscription_token();
+    store_token(
+        &mut transaction,
+        subscriber_id,
+        list.list_id,
+        &subscription_token,
+    )
+    .await
+    .context("Failed to store the confirmation token for a new subscriber.")?;
 
     transaction
         .commit()
@@ -71,45 +108,65 @@ pub async fn subscribe(
         .context("Failed to commit SQL transaction to store a new subscriber.")?;
 
     send_confirmation_email(
-        &email_client,
+        pool.get_ref(),
+        email_client.get_ref(),
         new_subscriber,
+        &list.name,
         &base_url.0,
         &subscription_token,
     )
-    .await
-    .context("Failed to send a confirmation email.")?;
+    .await?;
 
     Ok(HttpResponse::Ok().finish())
 }
 
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
-    skip(email_client, new_subscriber, base_url, subscription_token)
+    skip(pool, email_client, new_subscriber, base_url, subscription_token)
 )]
 pub async fn send_confirmation_email(
-    email_client: &EmailClient,
+    pool: &PgPool,
+    email_client: &dyn EmailSender,
     new_subscriber: NewSubscriber,
+    list_name: &str,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), SubscribeError> {
+    let suppression = get_suppression(pool, new_subscriber.email.as_ref()).await?;
+    if let Some(suppression) = suppression.filter(|s| s.blocks_subscriptions()) {
+        tracing::info!(
+            source = %suppression.source,
+            "Not sending a confirmation email to a suppressed address"
+        );
+        return Ok(());
+    }
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token
     );
     let plain_body = format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
+        "Welcome to {}!\nVisit {} to confirm your subscription.",
+        list_name, confirmation_link
     );
     let html_body = format!(
-        "Welcome to our newsletter!<br />\
+        "Welcome to {}!<br />\
         Click <a href=\"{}\">here</a> to confirm your subscription.",
+        htmlescape::encode_minimal(list_name),
         confirmation_link
     );
     email_client
         .send_email(&new_subscriber.email, "Welcome!", &html_body, &plain_body)
         .await
+        .map_err(|e| match e {
+            SendEmailError::QuotaExhausted(_) => SubscribeError::QuotaExhausted(e),
+            e => SubscribeError::UnexpectedError(
+                anyhow::Error::new(e).context("Failed to send a confirmation email."),
+            ),
+        })
 }
 
+/// Store a new subscriber, or return the id of the existing subscriber with
+/// the same email: they are joining another list, or joining one again.
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
     skip(new_subscriber, transaction)
@@ -118,26 +175,48 @@ pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
 ) -> Result<Uuid, sqlx::Error> {
-    // let subscriber_id = sqlx::types::Uuid::from_u128(Uuid::new_v4().as_u128());
-    let subscriber_id = Uuid::new_v4();
-    sqlx::query!(
+    let row = sqlx::query!(
         r#"
 		INSERT INTO subscriptions (id, email, name, subscribed_at, status)
 		    VALUES ($1, $2, $3, $4, 'pending_confirmation')
+		    ON CONFLICT (email) DO UPDATE SET email = EXCLUDED.email
+		    RETURNING id
 		"#,
-        subscriber_id,
+        Uuid::new_v4(),
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
         Utc::now()
     )
-    .execute(transaction)
+    .fetch_one(transaction)
     .await?;
-    // .map_err(|e| {
-    //     tracing::error!("Failed to execute query: {:?}", e);
-    //     e
-    // })?;
 
-    Ok(subscriber_id)
+    Ok(row.id)
+}
+
+/// Add a subscriber to a list, pending confirmation.
+/// Returns `false` if they already are a confirmed member.
+#[tracing::instrument(name = "Saving a list membership in the database", skip(transaction))]
+pub async fn insert_list_membership(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let n_affected_rows = sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at)
+            VALUES ($1, $2, 'pending_confirmation', now())
+            ON CONFLICT (list_id, subscriber_id) DO UPDATE
+                SET status = 'pending_confirmation', subscribed_at = now()
+                WHERE list_memberships.status <> 'confirmed'
+        "#,
+        list_id,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?
+    .rows_affected();
+
+    Ok(n_affected_rows > 0)
 }
 
 #[tracing::instrument(
@@ -147,15 +226,17 @@ pub async fn insert_subscriber(
 pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
+    list_id: Uuid,
     subscription_token: &str,
 ) -> Result<(), StoreTokenError> {
     sqlx::query!(
         r#"
-        INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-            VALUES ($1, $2)
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id, list_id)
+            VALUES ($1, $2, $3)
         "#,
         subscription_token,
-        subscriber_id
+        subscriber_id,
+        list_id
     )
     .execute(transaction)
     .await
@@ -171,6 +252,9 @@ pub async fn store_token(
 pub enum SubscribeError {
     #[error("{0}")]
     ValidationError(String),
+    // Subscribing again once the quota resets sends a new confirmation email
+    #[error("We cannot send any more emails today, please try again tomorrow.")]
+    QuotaExhausted(#[source] SendEmailError),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -185,6 +269,7 @@ impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
             SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::QuotaExhausted(_) => StatusCode::SERVICE_UNAVAILABLE,
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -1,5 +1,6 @@
 use actix_web::{web, HttpResponse};
 use sqlx::PgPool;
+use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
 pub struct Parameters {
@@ -8,14 +9,17 @@ pub struct Parameters {
 
 #[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool))]
 pub async fn confirm(parameters: web::Query<Parameters>, pool: web::Data<PgPool>) -> HttpResponse {
-    let id = match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
-        Ok(id) => id,
+    let ids = match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
+        Ok(ids) => ids,
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
-    match id {
+    match ids {
         None => HttpResponse::Unauthorized().finish(),
-        Some(subcriber_id) => {
-            if confirm_subscriber(&pool, subcriber_id).await.is_err() {
+        Some((subcriber_id, list_id)) => {
+            if confirm_subscriber(&pool, subcriber_id, list_id)
+                .await
+                .is_err()
+            {
                 return HttpResponse::InternalServerError().finish();
             }
             HttpResponse::Ok().finish()
@@ -26,8 +30,25 @@ pub async fn confirm(parameters: web::Query<Parameters>, pool: web::Data<PgPool>
 #[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
 pub async fn confirm_subscriber(
     pool: &PgPool,
-    subscriber_id: uuid::Uuid,
+    subscriber_id: Uuid,
+    list_id: Uuid,
 ) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+            UPDATE list_memberships
+                SET status = 'confirmed', confirmed_at = now()
+                WHERE list_id = $1 AND subscriber_id = $2 AND status <> 'confirmed'
+        "#,
+        list_id,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
     sqlx::query!(
         r#"
             UPDATE subscriptions
@@ -36,24 +57,41 @@ pub async fn confirm_subscriber(
         "#,
         subscriber_id
     )
-    .execute(pool)
+    .execute(&mut transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    // They asked to hear from us again
+    sqlx::query!(
+        r#"
+            DELETE FROM suppressions
+                WHERE source = 'unsubscribe'
+                AND email = (SELECT lower(trim(email)) FROM subscriptions WHERE id = $1)
+        "#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
     })?;
+    transaction.commit().await?;
 
     Ok(())
 }
 
+/// The subscriber and the list a confirmation token was issued for.
 #[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
 pub async fn get_subscriber_id_from_token(
     pool: &PgPool,
     subscription_token: &str,
-) -> Result<Option<uuid::Uuid>, sqlx::Error> {
+) -> Result<Option<(Uuid, Uuid)>, sqlx::Error> {
     let get_sub_id = sqlx::query!(
         r#"
-            SELECT subscriber_id
+            SELECT subscriber_id, list_id
                 FROM subscription_tokens
                 WHERE subscription_token = $1
         "#,
@@ -66,6 +104,6 @@ pub async fn get_subscriber_id_from_token(
         e
     })?;
 
-    let res = get_sub_id.map(|r| r.subscriber_id);
+    let res = get_sub_id.map(|r| (r.subscriber_id, r.list_id));
     Ok(res)
 }
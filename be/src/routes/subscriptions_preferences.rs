@@ -0,0 +1,559 @@
+use super::subscriptions_unsubscribe::{unsubscribe_from_all_lists, unsubscribed_page};
+use crate::domain::{DeliveryFrequency, SubscriberName};
+use crate::startup::HmacSecret;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use htmlescape::encode_minimal;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{Executor, PgPool, Postgres};
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// Links to the preference center stop working after this many days.
+const PREFERENCE_LINK_LIFETIME_DAYS: i64 = 30;
+
+fn preferences_mac(hmac_secret: &Secret<String>, token_id: Uuid) -> Hmac<sha2::Sha256> {
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(hmac_secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(format!("preferences:{}", token_id).as_bytes());
+    mac
+}
+
+/// Issue a link to the preference center of a subscriber.
+///
+/// The token is signed, so that forged links are turned away without a
+/// database round-trip, and stored, so that it can expire and be revoked.
+/// The link sent with an issue is created once per recipient and reused when
+/// the delivery is retried.
+#[tracing::instrument(skip(executor, hmac_secret))]
+pub async fn issue_preferences_url<'a, E>(
+    executor: E,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+    subscriber_id: Uuid,
+    newsletter_issue_id: Option<Uuid>,
+) -> Result<String, sqlx::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let now = Utc::now();
+    // The no-op update makes `RETURNING` yield the existing token on conflict
+    let token_id = sqlx::query!(
+        r#"
+        INSERT INTO preference_tokens (
+            token_id,
+            subscriber_id,
+            newsletter_issue_id,
+            created_at,
+            expires_at
+        )
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (subscriber_id, newsletter_issue_id)
+            DO UPDATE SET subscriber_id = EXCLUDED.subscriber_id
+        RETURNING token_id
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        newsletter_issue_id,
+        now,
+        now + Duration::days(PREFERENCE_LINK_LIFETIME_DAYS)
+    )
+    .fetch_one(executor)
+    .await?
+    .token_id;
+
+    let tag = hex::encode(
+        preferences_mac(hmac_secret, token_id)
+            .finalize()
+            .into_bytes(),
+    );
+    Ok(format!(
+        "{}/subscriptions/preferences?token={}.{}",
+        base_url, token_id, tag
+    ))
+}
+
+/// The subscriber a token was issued to, if it is genuine, unexpired and not revoked.
+#[tracing::instrument(name = "Get subscriber_id from preferences token", skip_all)]
+async fn get_subscriber_id_from_token(
+    pool: &PgPool,
+    hmac_secret: &Secret<String>,
+    token: &str,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let (token_id, tag) = match token.split_once('.') {
+        Some(parts) => parts,
+        None => return Ok(None),
+    };
+    let token_id = match Uuid::parse_str(token_id) {
+        Ok(token_id) => token_id,
+        Err(_) => return Ok(None),
+    };
+    let is_genuine = match hex::decode(tag) {
+        Ok(tag) => preferences_mac(hmac_secret, token_id)
+            .verify_slice(&tag)
+            .is_ok(),
+        Err(_) => false,
+    };
+    if !is_genuine {
+        return Ok(None);
+    }
+
+    let row = sqlx::query!(
+        r#"
+        SELECT subscriber_id
+            FROM preference_tokens
+            WHERE token_id = $1 AND revoked_at IS NULL AND expires_at > now()
+        "#,
+        token_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the preferences token.")?;
+
+    Ok(row.map(|r| r.subscriber_id))
+}
+
+fn invalid_link_page() -> HttpResponse {
+    HttpResponse::Unauthorized()
+        .content_type(ContentType::html())
+        .body(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Invalid link</title>
+            </head>
+            <body>
+                <p>This link has expired or has been revoked.
+                Use the link at the bottom of the latest issue you received.</p>
+            </body>
+            </html>
+            "#,
+        )
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesParameters {
+    token: String,
+}
+
+#[tracing::instrument(
+    name = "Show the preference center",
+    skip(parameters, flash_messages, pool, secret)
+)]
+pub async fn preferences_form(
+    parameters: web::Query<PreferencesParameters>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = match get_subscriber_id_from_token(&pool, &secret.0, &parameters.token)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber_id) => subscriber_id,
+        None => return Ok(invalid_link_page()),
+    };
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT email, name, status, delivery_frequency
+            FROM subscriptions
+            WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_one(pool.get_ref())
+    .await
+    .context("Failed to retrieve the subscriber's preferences.")
+    .map_err(e500)?;
+    if subscriber.status == "unsubscribed" {
+        return Ok(unsubscribed_page());
+    }
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let lists = sqlx::query!(
+        r#"
+        SELECT l.list_id, l.name, COALESCE(m.status = 'confirmed', false) as "member!"
+            FROM lists l
+            LEFT JOIN list_memberships m
+                ON m.list_id = l.list_id AND m.subscriber_id = $1
+            ORDER BY l.name
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the lists.")
+    .map_err(e500)?;
+    let mut lists_html = String::new();
+    for list in &lists {
+        writeln!(
+            lists_html,
+            r#"<label><input type="checkbox" name="list" value="{}"{}> {}</label><br>"#,
+            list.list_id,
+            if list.member { " checked" } else { "" },
+            encode_minimal(&list.name),
+        )
+        .unwrap();
+    }
+
+    let topics = sqlx::query!(
+        r#"
+        SELECT t.topic_id, t.name, o.subscriber_id IS NULL as "opted_in!"
+            FROM topics t
+            LEFT JOIN topic_opt_outs o
+                ON o.topic_id = t.topic_id AND o.subscriber_id = $1
+            ORDER BY t.name
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the topics.")
+    .map_err(e500)?;
+    let mut topics_html = String::new();
+    for topic in &topics {
+        writeln!(
+            topics_html,
+            r#"<label><input type="checkbox" name="topic" value="{}"{}> {}</label><br>"#,
+            topic.topic_id,
+            if topic.opted_in { " checked" } else { "" },
+            encode_minimal(&topic.name),
+        )
+        .unwrap();
+    }
+    if topics.is_empty() {
+        topics_html.push_str("<p>You receive every issue we publish.</p>");
+    }
+
+    let current_frequency = DeliveryFrequency::parse(&subscriber.delivery_frequency)
+        .unwrap_or(DeliveryFrequency::EveryIssue);
+    let mut frequencies_html = String::new();
+    for frequency in DeliveryFrequency::ALL {
+        writeln!(
+            frequencies_html,
+            r#"<option value="{}"{}>{}</option>"#,
+            frequency.as_str(),
+            if frequency == current_frequency {
+                " selected"
+            } else {
+                ""
+            },
+            frequency.label(),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Your preferences</title>
+            </head>
+            <body>
+                {msg_html}
+                <h1>Your preferences</h1>
+                <p>Subscribed as {email}</p>
+                <form action="/subscriptions/preferences" method="post">
+                    <input type="hidden" name="token" value="{token}">
+                    <label>Name
+                        <input type="text" name="name" value="{name}">
+                    </label>
+                    <fieldset>
+                        <legend>Lists</legend>
+                        {lists_html}
+                    </fieldset>
+                    <fieldset>
+                        <legend>Topics</legend>
+                        {topics_html}
+                    </fieldset>
+                    <label>Frequency
+                        <select name="delivery_frequency">
+                            {frequencies_html}
+                        </select>
+                    </label>
+                    <br>
+                    <button type="submit">Save</button>
+                </form>
+                <form action="/subscriptions/preferences/unsubscribe" method="post">
+                    <input type="hidden" name="token" value="{token}">
+                    <button type="submit">Unsubscribe from all emails</button>
+                </form>
+                <form action="/subscriptions/preferences/revoke" method="post">
+                    <input type="hidden" name="token" value="{token}">
+                    <button type="submit">Disable all links to this page</button>
+                </form>
+            </body>
+            </html>
+            "#,
+            email = encode_minimal(&subscriber.email),
+            name = encode_minimal(&subscriber.name),
+            token = encode_minimal(&parameters.token),
+        )))
+}
+
+#[tracing::instrument(name = "Save subscriber preferences", skip(form, pool, secret))]
+pub async fn save_preferences(
+    // A list of pairs, to receive every checked `list` and `topic`
+    form: web::Form<Vec<(String, String)>>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let field = |key: &str| {
+        form.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.clone())
+            .unwrap_or_default()
+    };
+    let token = field("token");
+    let subscriber_id = match get_subscriber_id_from_token(&pool, &secret.0, &token)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber_id) => subscriber_id,
+        None => return Ok(invalid_link_page()),
+    };
+    let preferences_path = format!("/subscriptions/preferences?token={}", token);
+
+    let name = match SubscriberName::parse(field("name")) {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(encode_minimal(&e)).send();
+            return Ok(see_other(&preferences_path));
+        }
+    };
+    let delivery_frequency = match DeliveryFrequency::parse(&field("delivery_frequency")) {
+        Ok(frequency) => frequency,
+        Err(e) => {
+            FlashMessage::error(encode_minimal(&e)).send();
+            return Ok(see_other(&preferences_path));
+        }
+    };
+    // Unknown or malformed lists and topics are ignored, as if they were unchecked
+    let checked = |key: &str| -> Vec<Uuid> {
+        form.iter()
+            .filter(|(k, _)| k == key)
+            .filter_map(|(_, v)| Uuid::parse_str(v).ok())
+            .collect()
+    };
+    let joined_lists = checked("list");
+    let opted_in_topics = checked("topic");
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+            SET name = $2, delivery_frequency = $3
+            WHERE id = $1
+        "#,
+        subscriber_id,
+        name.as_ref(),
+        delivery_frequency.as_str()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to update the subscriber's preferences.")
+    .map_err(e500)?;
+    // The link was mailed to the subscriber: joining a list from here needs
+    // no further confirmation.
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at, confirmed_at)
+        SELECT list_id, $1, 'confirmed', now(), now()
+            FROM lists
+            WHERE list_id = ANY($2)
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE
+            SET status = 'confirmed', confirmed_at = now()
+            WHERE list_memberships.status <> 'confirmed'
+        "#,
+        subscriber_id,
+        &joined_lists
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the subscriber's lists.")
+    .map_err(e500)?;
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships
+            SET status = 'unsubscribed'
+            WHERE subscriber_id = $1 AND list_id <> ALL($2)
+        "#,
+        subscriber_id,
+        &joined_lists
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to leave the unchecked lists.")
+    .map_err(e500)?;
+    sqlx::query!(
+        r#"DELETE FROM topic_opt_outs WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to clear the subscriber's topic opt-outs.")
+    .map_err(e500)?;
+    sqlx::query!(
+        r#"
+        INSERT INTO topic_opt_outs (subscriber_id, topic_id)
+        SELECT $1, topic_id
+            FROM topics
+            WHERE topic_id <> ALL($2)
+        "#,
+        subscriber_id,
+        &opted_in_topics
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the subscriber's topic opt-outs.")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to save subscriber preferences.")
+        .map_err(e500)?;
+
+    FlashMessage::info("Your preferences have been saved.").send();
+    Ok(see_other(&preferences_path))
+}
+
+#[derive(serde::Deserialize)]
+pub struct TokenFormData {
+    token: String,
+}
+
+#[tracing::instrument(
+    name = "Unsubscribe from the preference center",
+    skip(form, pool, secret)
+)]
+pub async fn unsubscribe_from_preferences(
+    form: web::Form<TokenFormData>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = match get_subscriber_id_from_token(&pool, &secret.0, &form.token)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber_id) => subscriber_id,
+        None => return Ok(invalid_link_page()),
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    unsubscribe_from_all_lists(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to unsubscribe the subscriber.")
+        .map_err(e500)?;
+    revoke_preference_tokens(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to revoke the subscriber's preferences tokens.")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber.")
+        .map_err(e500)?;
+
+    Ok(unsubscribed_page())
+}
+
+#[tracing::instrument(name = "Revoke preference links", skip(form, pool, secret))]
+pub async fn revoke_preference_links(
+    form: web::Form<TokenFormData>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = match get_subscriber_id_from_token(&pool, &secret.0, &form.token)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber_id) => subscriber_id,
+        None => return Ok(invalid_link_page()),
+    };
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    revoke_preference_tokens(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to revoke the subscriber's preferences tokens.")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to revoke preferences tokens.")
+        .map_err(e500)?;
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"
+            <!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Links disabled</title>
+            </head>
+            <body>
+                <p>All the links to your preferences have been disabled.
+                The next issue you receive will contain a new one.</p>
+            </body>
+            </html>
+            "#,
+    ))
+}
+
+/// Delete the tokens that can no longer be used, returning how many there were.
+#[tracing::instrument(skip(pool))]
+pub async fn prune_preference_tokens(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let n_deleted_rows = sqlx::query!(
+        r#"
+        DELETE FROM preference_tokens
+            WHERE expires_at <= now() OR revoked_at IS NOT NULL
+        "#
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(n_deleted_rows)
+}
+
+#[tracing::instrument(skip(transaction))]
+async fn revoke_preference_tokens(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE preference_tokens
+            SET revoked_at = now()
+            WHERE subscriber_id = $1 AND revoked_at IS NULL
+        "#,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
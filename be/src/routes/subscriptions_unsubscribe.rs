@@ -0,0 +1,190 @@
+use crate::startup::HmacSecret;
+use crate::suppressions::{add_suppression, SuppressionSource};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use htmlescape::encode_minimal;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    subscriber_id: Uuid,
+    token: String,
+}
+
+impl UnsubscribeParameters {
+    fn verify(&self, hmac_secret: &Secret<String>) -> bool {
+        match hex::decode(&self.token) {
+            Ok(tag) => unsubscribe_mac(hmac_secret, self.subscriber_id)
+                .verify_slice(&tag)
+                .is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+fn unsubscribe_mac(hmac_secret: &Secret<String>, subscriber_id: Uuid) -> Hmac<sha2::Sha256> {
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(hmac_secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(format!("unsubscribe:{}", subscriber_id).as_bytes());
+    mac
+}
+
+/// A per-subscriber link to unsubscribe, signed so that it cannot be forged
+/// for somebody else's subscription.
+pub fn unsubscribe_url(
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+    subscriber_id: Uuid,
+) -> String {
+    let tag = hex::encode(
+        unsubscribe_mac(hmac_secret, subscriber_id)
+            .finalize()
+            .into_bytes(),
+    );
+    format!(
+        "{}/subscriptions/unsubscribe?subscriber_id={}&token={}",
+        base_url, subscriber_id, tag
+    )
+}
+
+/// Ask for confirmation: link scanners follow links in emails, so a GET must
+/// never unsubscribe anybody.
+#[tracing::instrument(name = "Show the unsubscribe page", skip(parameters, pool, secret))]
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !parameters.verify(&secret.0) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+    let subscriber = sqlx::query!(
+        r#"SELECT email, status FROM subscriptions WHERE id = $1"#,
+        parameters.subscriber_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the subscriber.")
+    .map_err(e500)?;
+    let subscriber = match subscriber {
+        Some(subscriber) if subscriber.status != "unsubscribed" => subscriber,
+        _ => return Ok(unsubscribed_page()),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Unsubscribe</title>
+            </head>
+            <body>
+                <p>Do you want to stop receiving our newsletter at {email}?</p>
+                <form action="/subscriptions/unsubscribe?subscriber_id={subscriber_id}&amp;token={token}" method="post">
+                    <button type="submit">Unsubscribe</button>
+                </form>
+            </body>
+            </html>
+            "#,
+            email = encode_minimal(&subscriber.email),
+            subscriber_id = parameters.subscriber_id,
+            token = encode_minimal(&parameters.token),
+        )))
+}
+
+/// Both the confirmation form and the one-click `List-Unsubscribe-Post`
+/// requests of mail clients (RFC 8058) land here: the body is ignored.
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(parameters, pool, secret))]
+pub async fn unsubscribe(
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !parameters.verify(&secret.0) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    unsubscribe_from_all_lists(&mut transaction, parameters.subscriber_id)
+        .await
+        .context("Failed to unsubscribe the subscriber.")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber.")
+        .map_err(e500)?;
+
+    Ok(unsubscribed_page())
+}
+
+/// Unsubscribe from every list at once: joining a list again takes a new
+/// confirmation. The address goes on the suppression list until then.
+#[tracing::instrument(name = "Unsubscribe from all lists", skip(transaction))]
+pub async fn unsubscribe_from_all_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let subscriber = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+            SET status = 'unsubscribed'
+            WHERE id = $1
+            RETURNING email
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to update the subscriber's status.")?;
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships
+            SET status = 'unsubscribed'
+            WHERE subscriber_id = $1
+        "#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to leave the subscriber's lists.")?;
+    if let Some(subscriber) = subscriber {
+        add_suppression(
+            &mut *transaction,
+            &subscriber.email,
+            "Unsubscribed",
+            SuppressionSource::Unsubscribe,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+pub(super) fn unsubscribed_page() -> HttpResponse {
+    HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"
+            <!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Unsubscribed</title>
+            </head>
+            <body>
+                <p>You have been unsubscribed. You will not receive any more issues.</p>
+            </body>
+            </html>
+            "#,
+    )
+}
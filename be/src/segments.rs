@@ -0,0 +1,323 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres, QueryBuilder, Row};
+use uuid::Uuid;
+
+const MAX_FILTER_DEPTH: usize = 8;
+const MAX_TAG_LENGTH: usize = 64;
+const STATUSES: [&str; 3] = ["pending_confirmation", "confirmed", "unsubscribed"];
+
+/// A boolean filter over subscribers, e.g.
+/// `{"and": [{"tag": "beta"}, {"not": {"attribute": {"name": "plan", "equals": "free"}}}]}`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum SegmentFilter {
+    And(Vec<SegmentFilter>),
+    Or(Vec<SegmentFilter>),
+    Not(Box<SegmentFilter>),
+    Tag(String),
+    Status(String),
+    SubscribedBefore(DateTime<Utc>),
+    SubscribedAfter(DateTime<Utc>),
+    Attribute { name: String, equals: String },
+}
+
+impl SegmentFilter {
+    pub fn parse(json: &str) -> Result<SegmentFilter, String> {
+        let filter: SegmentFilter =
+            serde_json::from_str(json).map_err(|e| format!("Invalid segment filter: {}", e))?;
+        filter.validate(1)?;
+        Ok(filter)
+    }
+
+    fn validate(&self, depth: usize) -> Result<(), String> {
+        if depth > MAX_FILTER_DEPTH {
+            return Err(format!(
+                "A segment filter can nest at most {} levels.",
+                MAX_FILTER_DEPTH
+            ));
+        }
+        match self {
+            Self::And(filters) | Self::Or(filters) => filters
+                .iter()
+                .try_for_each(|filter| filter.validate(depth + 1)),
+            Self::Not(filter) => filter.validate(depth + 1),
+            Self::Tag(tag) => normalize_tag(tag).map(|_| ()),
+            Self::Status(status) if !STATUSES.contains(&status.as_str()) => Err(format!(
+                "{} is not a subscription status: use {}.",
+                status,
+                STATUSES.join(", ")
+            )),
+            Self::Attribute { name, .. } => normalize_attribute_name(name).map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Append the filter as a condition over `subscriptions s`.
+    pub fn push_condition(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        match self {
+            Self::And(filters) => push_all(builder, filters, " AND ", "TRUE"),
+            Self::Or(filters) => push_all(builder, filters, " OR ", "FALSE"),
+            Self::Not(filter) => {
+                builder.push("NOT ");
+                filter.push_condition(builder);
+            }
+            Self::Tag(tag) => {
+                builder.push(
+                    "EXISTS (SELECT 1 FROM subscriber_tags t \
+                    WHERE t.subscriber_id = s.id AND t.tag = ",
+                );
+                builder.push_bind(tag.trim().to_lowercase());
+                builder.push(")");
+            }
+            Self::Status(status) => {
+                builder.push("s.status = ");
+                builder.push_bind(status.clone());
+            }
+            Self::SubscribedBefore(date) => {
+                builder.push("s.subscribed_at < ");
+                builder.push_bind(*date);
+            }
+            Self::SubscribedAfter(date) => {
+                builder.push("s.subscribed_at >= ");
+                builder.push_bind(*date);
+            }
+            Self::Attribute { name, equals } => {
+                builder.push(
+                    "EXISTS (SELECT 1 FROM subscriber_attributes a \
+                    WHERE a.subscriber_id = s.id AND a.name = ",
+                );
+                builder.push_bind(name.trim().to_string());
+                builder.push(" AND a.value = ");
+                builder.push_bind(equals.clone());
+                builder.push(")");
+            }
+        }
+    }
+}
+
+fn push_all(
+    builder: &mut QueryBuilder<'_, Postgres>,
+    filters: &[SegmentFilter],
+    separator: &str,
+    empty: &str,
+) {
+    if filters.is_empty() {
+        builder.push(empty);
+        return;
+    }
+    builder.push("(");
+    for (i, filter) in filters.iter().enumerate() {
+        if i > 0 {
+            builder.push(separator);
+        }
+        filter.push_condition(builder);
+    }
+    builder.push(")");
+}
+
+/// Attribute names are stored trimmed, so that `"plan "` matches `"plan"`.
+pub fn normalize_attribute_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("An attribute name must not be empty.".into());
+    }
+    Ok(name.to_string())
+}
+
+/// Tags are matched case-insensitively: they are stored lowercase.
+pub fn normalize_tag(tag: &str) -> Result<String, String> {
+    let tag = tag.trim().to_lowercase();
+    if tag.is_empty() {
+        return Err("A tag must not be empty.".into());
+    }
+    if tag.chars().count() > MAX_TAG_LENGTH {
+        return Err(format!(
+            "A tag can be at most {} characters long.",
+            MAX_TAG_LENGTH
+        ));
+    }
+    Ok(tag)
+}
+
+#[derive(Debug)]
+pub struct Segment {
+    pub segment_id: Uuid,
+    pub name: String,
+    pub filter: SegmentFilter,
+}
+
+/// Store a new segment, unless its name is already taken.
+#[tracing::instrument(name = "Create a segment", skip(pool, filter))]
+pub async fn create_segment(
+    pool: &PgPool,
+    name: &str,
+    filter: &SegmentFilter,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let segment_id = Uuid::new_v4();
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO segments (segment_id, name, filter, created_at)
+            VALUES ($1, $2, $3::text::jsonb, now())
+            ON CONFLICT (name) DO NOTHING
+        "#,
+        segment_id,
+        name,
+        serde_json::to_string(filter)?
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the segment.")?
+    .rows_affected();
+
+    Ok((n_inserted_rows > 0).then_some(segment_id))
+}
+
+#[tracing::instrument(name = "Get a segment", skip(executor))]
+pub async fn get_segment<'a, E>(
+    executor: E,
+    segment_id: Uuid,
+) -> Result<Option<Segment>, anyhow::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let row = sqlx::query!(
+        r#"SELECT segment_id, name, filter::text as "filter!" FROM segments WHERE segment_id = $1"#,
+        segment_id
+    )
+    .fetch_optional(executor)
+    .await
+    .context("Failed to perform a query to retrieve a segment.")?;
+
+    row.map(|r| {
+        Ok(Segment {
+            segment_id: r.segment_id,
+            name: r.name,
+            filter: serde_json::from_str(&r.filter).context("A stored segment is invalid.")?,
+        })
+    })
+    .transpose()
+}
+
+#[tracing::instrument(name = "Get segments", skip(pool))]
+pub async fn get_segments(pool: &PgPool) -> Result<Vec<Segment>, anyhow::Error> {
+    sqlx::query!(
+        r#"SELECT segment_id, name, filter::text as "filter!" FROM segments ORDER BY name"#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve segments.")?
+    .into_iter()
+    .map(|r| {
+        Ok(Segment {
+            segment_id: r.segment_id,
+            name: r.name,
+            filter: serde_json::from_str(&r.filter).context("A stored segment is invalid.")?,
+        })
+    })
+    .collect()
+}
+
+/// The number of subscribers an issue sent to `list_ids` and `filter` would
+/// reach, before topic opt-outs: confirmed members of any of the lists who
+/// match the filter.
+#[tracing::instrument(name = "Count segment recipients", skip(pool, filter))]
+pub async fn count_recipients(
+    pool: &PgPool,
+    list_ids: &[Uuid],
+    filter: Option<&SegmentFilter>,
+) -> Result<i64, anyhow::Error> {
+    let mut builder = QueryBuilder::new(
+        "SELECT COUNT(*) FROM subscriptions s \
+        WHERE s.status = 'confirmed' \
+        AND NOT EXISTS (SELECT 1 FROM suppressions x WHERE x.email = lower(trim(s.email))) \
+        AND EXISTS (SELECT 1 FROM list_memberships m \
+        WHERE m.subscriber_id = s.id AND m.status = 'confirmed' AND m.list_id = ANY(",
+    );
+    builder.push_bind(list_ids.to_vec());
+    builder.push("))");
+    if let Some(filter) = filter {
+        builder.push(" AND ");
+        filter.push_condition(&mut builder);
+    }
+    let row = builder
+        .build()
+        .fetch_one(pool)
+        .await
+        .context("Failed to count the recipients of a segment.")?;
+
+    Ok(row.try_get(0)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_attribute_name, normalize_tag, SegmentFilter};
+    use claim::{assert_err, assert_ok};
+    use sqlx::{Execute, Postgres, QueryBuilder};
+
+    fn sql(filter: &SegmentFilter) -> String {
+        let mut builder = QueryBuilder::<Postgres>::new("");
+        filter.push_condition(&mut builder);
+        builder.build().sql().to_string()
+    }
+
+    #[test]
+    fn filters_are_parsed_from_json() {
+        let filter = SegmentFilter::parse(
+            r#"{"and": [
+                {"tag": "beta"},
+                {"not": {"attribute": {"name": "plan", "equals": "free"}}},
+                {"subscribed_after": "2022-01-01T00:00:00Z"}
+            ]}"#,
+        )
+        .unwrap();
+        assert!(matches!(filter, SegmentFilter::And(ref filters) if filters.len() == 3));
+    }
+
+    #[test]
+    fn invalid_filters_are_rejected() {
+        let cases = [
+            r#"{"tag": "  "}"#,
+            r#"{"status": "active"}"#,
+            r#"{"attribute": {"name": "", "equals": "pro"}}"#,
+            r#"{"subscribed_after": "yesterday"}"#,
+            r#"{"colour": "blue"}"#,
+            r#"{"not": {"not": {"not": {"not": {"not": {"not": {"not": {"not": {"tag": "x"}}}}}}}}}"#,
+        ];
+        for json in cases {
+            assert_err!(SegmentFilter::parse(json), "{} should be rejected", json);
+        }
+    }
+
+    #[test]
+    fn filters_are_compiled_to_sql_with_bound_values() {
+        let filter = SegmentFilter::parse(
+            r#"{"or": [{"tag": "Beta"}, {"and": [{"status": "confirmed"}, {"not": {"tag": "churned"}}]}]}"#,
+        )
+        .unwrap();
+        let sql = sql(&filter);
+        assert!(sql.starts_with("(EXISTS (SELECT 1 FROM subscriber_tags t"));
+        assert!(sql.contains(" OR (s.status = $2 AND NOT EXISTS"));
+        assert!(!sql.contains("Beta") && !sql.contains("churned"));
+    }
+
+    #[test]
+    fn empty_combinations_are_constants() {
+        assert_eq!(sql(&SegmentFilter::And(vec![])), "TRUE");
+        assert_eq!(sql(&SegmentFilter::Or(vec![])), "FALSE");
+    }
+
+    #[test]
+    fn attribute_names_are_trimmed() {
+        assert_eq!(normalize_attribute_name(" plan ").unwrap(), "plan");
+        assert_err!(normalize_attribute_name("  "));
+    }
+
+    #[test]
+    fn tags_are_trimmed_and_lowercased() {
+        assert_eq!(normalize_tag("  Beta Testers ").unwrap(), "beta testers");
+        assert_err!(normalize_tag(""));
+        assert_ok!(normalize_tag(&"x".repeat(64)));
+        assert_err!(normalize_tag(&"x".repeat(65)));
+    }
+}
@@ -1,9 +1,18 @@
 use crate::configuration::DatabaseSettings;
-use crate::configuration::Settings;
-use crate::email_client::EmailClient;
+use crate::configuration::{Settings, WebhookSettings};
+use crate::email_client::EmailSender;
 use crate::routes::{
-    admin_dashboard, change_password, change_password_form, confirm, health_check, home, login,
-    login_form, publish_newsletter, subscribe,
+    add_suppression_from_form, admin_dashboard, archived_issue, atom_feed, cancel_scheduled_issue,
+    change_password, change_password_form, confirm, count_segment_recipients, create_draft,
+    create_list, create_segment_from_form, create_topic, dead_letters, delete_draft, drafts,
+    edit_draft_form, health_check, home, issues_archive, json_feed, lists, login, login_form,
+    new_draft_form, newsletter_issue, newsletter_issue_report, newsletter_issues, postmark_webhook,
+    preferences_form, preview_draft, publish_draft, publish_newsletter, publish_newsletter_form,
+    publish_newsletter_from_form, remove_suppression_from_form, requeue_dead_letter,
+    reschedule_issue, revoke_preference_links, rss_feed, save_preferences, save_segment,
+    scheduled_issues, segments, send_test_draft, send_test_newsletter, set_subscriber_attributes,
+    set_subscriber_tags, subscribe, suppressions, topics, track_open, unsubscribe,
+    unsubscribe_form, unsubscribe_from_preferences, update_draft,
 };
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
@@ -17,6 +26,7 @@ use secrecy::Secret;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use std::net::TcpListener;
+use std::sync::Arc;
 use tracing_actix_web::TracingLogger;
 
 pub struct Application {
@@ -28,21 +38,14 @@ pub struct Application {
 pub struct HmacSecret(pub Secret<String>);
 
 impl Application {
-    pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
+    /// `email_client` is shared with the delivery worker, so that both
+    /// respect the same sending limits.
+    pub async fn build(
+        configuration: Settings,
+        email_client: Arc<dyn EmailSender>,
+    ) -> Result<Self, anyhow::Error> {
         let connection_pool = get_connection_pool(&configuration.database);
 
-        let sender_email = configuration
-            .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-        let timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url,
-            sender_email,
-            configuration.email_client.authorization_token,
-            timeout,
-        );
-
         let address = format!(
             "{}:{}",
             configuration.application.host, configuration.application.port
@@ -55,6 +58,7 @@ impl Application {
             email_client,
             configuration.application.base_url,
             configuration.application.hmac_secret,
+            configuration.webhooks,
             configuration.redis_uri,
         )
         .await?;
@@ -82,14 +86,16 @@ pub struct ApplicationBaseUrl(pub String);
 pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
-    email_client: EmailClient,
+    email_client: Arc<dyn EmailSender>,
     base_url: String,
     hmac_secret: Secret<String>,
+    webhooks: WebhookSettings,
     redis_uri: Secret<String>,
 ) -> Result<Server, anyhow::Error> {
     let db_pool = web::Data::new(db_pool);
-    let email_client = web::Data::new(email_client);
+    let email_client: web::Data<dyn EmailSender> = web::Data::from(email_client);
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let webhooks = web::Data::new(webhooks);
 
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
@@ -108,16 +114,117 @@ pub async fn run(
             .route("/health_check", web::get().to(health_check))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route(
+                "/subscriptions/preferences",
+                web::get().to(preferences_form),
+            )
+            .route(
+                "/subscriptions/preferences",
+                web::post().to(save_preferences),
+            )
+            .route(
+                "/subscriptions/preferences/unsubscribe",
+                web::post().to(unsubscribe_from_preferences),
+            )
+            .route(
+                "/subscriptions/preferences/revoke",
+                web::post().to(revoke_preference_links),
+            )
             .route("/newsletters", web::post().to(publish_newsletter))
+            .route("/newsletters/test", web::post().to(send_test_newsletter))
+            .route(
+                "/newsletters/{newsletter_issue_id}/report",
+                web::get().to(newsletter_issue_report),
+            )
+            .route("/subscribers/tags", web::put().to(set_subscriber_tags))
+            .route(
+                "/subscribers/attributes",
+                web::put().to(set_subscriber_attributes),
+            )
+            .route("/segments", web::post().to(save_segment))
+            .route("/segments/count", web::post().to(count_segment_recipients))
+            .route("/webhooks/postmark", web::post().to(postmark_webhook))
+            .route("/t/open/{token}", web::get().to(track_open))
             .route("/", web::get().to(home))
+            .route("/issues", web::get().to(issues_archive))
+            .route("/issues/{slug}", web::get().to(archived_issue))
+            .route("/feed.rss", web::get().to(rss_feed))
+            .route("/feed.atom", web::get().to(atom_feed))
+            .route("/feed.json", web::get().to(json_feed))
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
             .route("/admin/dashboard", web::get().to(admin_dashboard))
+            .route("/admin/newsletters", web::get().to(publish_newsletter_form))
+            .route(
+                "/admin/newsletters",
+                web::post().to(publish_newsletter_from_form),
+            )
             .route("/admin/password", web::get().to(change_password_form))
             .route("/admin/password", web::post().to(change_password))
+            .route("/admin/drafts", web::get().to(drafts))
+            .route("/admin/drafts", web::post().to(create_draft))
+            .route("/admin/drafts/new", web::get().to(new_draft_form))
+            .route("/admin/drafts/{draft_id}", web::get().to(edit_draft_form))
+            .route("/admin/drafts/{draft_id}", web::post().to(update_draft))
+            .route(
+                "/admin/drafts/{draft_id}/preview",
+                web::get().to(preview_draft),
+            )
+            .route(
+                "/admin/drafts/{draft_id}/publish",
+                web::post().to(publish_draft),
+            )
+            .route(
+                "/admin/drafts/{draft_id}/test",
+                web::post().to(send_test_draft),
+            )
+            .route(
+                "/admin/drafts/{draft_id}/delete",
+                web::post().to(delete_draft),
+            )
+            .route("/admin/issues", web::get().to(newsletter_issues))
+            .route(
+                "/admin/issues/{newsletter_issue_id}",
+                web::get().to(newsletter_issue),
+            )
+            .route("/admin/scheduled_issues", web::get().to(scheduled_issues))
+            .route("/admin/lists", web::get().to(lists))
+            .route("/admin/lists", web::post().to(create_list))
+            .route("/admin/segments", web::get().to(segments))
+            .route("/admin/segments", web::post().to(create_segment_from_form))
+            .route("/admin/topics", web::get().to(topics))
+            .route("/admin/topics", web::post().to(create_topic))
+            .route(
+                "/admin/scheduled_issues/{newsletter_issue_id}/reschedule",
+                web::post().to(reschedule_issue),
+            )
+            .route(
+                "/admin/scheduled_issues/{newsletter_issue_id}/cancel",
+                web::post().to(cancel_scheduled_issue),
+            )
+            .route("/admin/suppressions", web::get().to(suppressions))
+            .route(
+                "/admin/suppressions",
+                web::post().to(add_suppression_from_form),
+            )
+            .route(
+                "/admin/suppressions/delete",
+                web::post().to(remove_suppression_from_form),
+            )
+            .route("/admin/dead_letters", web::get().to(dead_letters))
+            .route(
+                "/admin/dead_letters/requeue",
+                web::post().to(requeue_dead_letter),
+            )
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(webhooks.clone())
             .app_data(web::Data::new(HmacSecret(hmac_secret.clone())))
     })
     .listen(listener)?
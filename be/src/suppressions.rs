@@ -0,0 +1,205 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres};
+
+/// How many entries the admin page shows at most.
+pub const MAX_SEARCH_RESULTS: i64 = 100;
+
+/// Where a suppression comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressionSource {
+    Manual,
+    Bounce,
+    Complaint,
+    Unsubscribe,
+    Import,
+}
+
+impl SuppressionSource {
+    pub const ALL: [SuppressionSource; 5] = [
+        SuppressionSource::Manual,
+        SuppressionSource::Bounce,
+        SuppressionSource::Complaint,
+        SuppressionSource::Unsubscribe,
+        SuppressionSource::Import,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionSource::Manual => "manual",
+            SuppressionSource::Bounce => "bounce",
+            SuppressionSource::Complaint => "complaint",
+            SuppressionSource::Unsubscribe => "unsubscribe",
+            SuppressionSource::Import => "import",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|source| source.as_str() == s)
+            .ok_or_else(|| format!("{} is not a known suppression source.", s))
+    }
+}
+
+#[derive(Debug)]
+pub struct Suppression {
+    pub email: String,
+    pub reason: String,
+    pub source: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Suppression {
+    /// Anybody who unsubscribed may join again: confirming the new
+    /// subscription lifts the suppression.
+    pub fn blocks_subscriptions(&self) -> bool {
+        self.source != SuppressionSource::Unsubscribe.as_str()
+    }
+}
+
+/// Suppressions are matched case-insensitively: they are stored trimmed and
+/// lowercase.
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+#[tracing::instrument(name = "Get the suppression of an address", skip(executor))]
+pub async fn get_suppression<'a, E>(
+    executor: E,
+    email: &str,
+) -> Result<Option<Suppression>, anyhow::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let suppression = sqlx::query_as!(
+        Suppression,
+        r#"
+        SELECT email, reason, source, created_at
+            FROM suppressions
+            WHERE email = $1
+        "#,
+        normalize_email(email)
+    )
+    .fetch_optional(executor)
+    .await
+    .context("Failed to perform a query to retrieve a suppression.")?;
+
+    Ok(suppression)
+}
+
+/// The first of `emails` that must not be mailed, if any.
+#[tracing::instrument(name = "Find a suppressed address", skip(pool))]
+pub async fn find_suppressed(
+    pool: &PgPool,
+    emails: &[&str],
+) -> Result<Option<String>, anyhow::Error> {
+    let emails: Vec<String> = emails.iter().map(|e| normalize_email(e)).collect();
+    let row = sqlx::query!(
+        r#"
+        SELECT email
+            FROM suppressions
+            WHERE email = ANY($1)
+            ORDER BY email
+            LIMIT 1
+        "#,
+        &emails
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up suppressed addresses.")?;
+
+    Ok(row.map(|r| r.email))
+}
+
+/// Suppress an address, keeping the reason and source of the first
+/// suppression. Returns `false` if it already was suppressed.
+#[tracing::instrument(name = "Add a suppression", skip(executor))]
+pub async fn add_suppression<'a, E>(
+    executor: E,
+    email: &str,
+    reason: &str,
+    source: SuppressionSource,
+) -> Result<bool, anyhow::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO suppressions (email, reason, source, created_at)
+            VALUES ($1, $2, $3, now())
+            ON CONFLICT (email) DO NOTHING
+        "#,
+        normalize_email(email),
+        reason,
+        source.as_str()
+    )
+    .execute(executor)
+    .await
+    .context("Failed to store a suppression.")?
+    .rows_affected();
+
+    Ok(n_inserted_rows > 0)
+}
+
+/// Returns `false` if the address wasn't suppressed.
+#[tracing::instrument(name = "Remove a suppression", skip(executor))]
+pub async fn remove_suppression<'a, E>(executor: E, email: &str) -> Result<bool, anyhow::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let n_deleted_rows = sqlx::query!(
+        r#"DELETE FROM suppressions WHERE email = $1"#,
+        normalize_email(email)
+    )
+    .execute(executor)
+    .await
+    .context("Failed to delete a suppression.")?
+    .rows_affected();
+
+    Ok(n_deleted_rows > 0)
+}
+
+/// The most recent suppressions whose address contains `query`.
+#[tracing::instrument(name = "Search suppressions", skip(pool))]
+pub async fn search_suppressions(
+    pool: &PgPool,
+    query: &str,
+) -> Result<Vec<Suppression>, anyhow::Error> {
+    let suppressions = sqlx::query_as!(
+        Suppression,
+        r#"
+        SELECT email, reason, source, created_at
+            FROM suppressions
+            WHERE strpos(email, $1) > 0
+            ORDER BY created_at DESC, email
+            LIMIT $2
+        "#,
+        normalize_email(query),
+        MAX_SEARCH_RESULTS
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to search suppressions.")?;
+
+    Ok(suppressions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_email, SuppressionSource};
+    use claim::assert_err;
+
+    #[test]
+    fn emails_are_trimmed_and_lowercased() {
+        assert_eq!(normalize_email("  LongLe@Gmail.com\n"), "longle@gmail.com");
+    }
+
+    #[test]
+    fn every_source_can_be_parsed_back() {
+        for source in SuppressionSource::ALL {
+            assert_eq!(SuppressionSource::parse(source.as_str()), Ok(source));
+        }
+        assert_err!(SuppressionSource::parse("Manual"));
+    }
+}
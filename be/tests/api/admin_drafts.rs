@@ -0,0 +1,319 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, body_partial_json, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn login(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+}
+
+/// Create a draft and return its path, e.g. `/admin/drafts/{draft_id}`.
+async fn create_draft(app: &TestApp) -> String {
+    let response = app
+        .post_draft(&serde_json::json!({
+            "title": "A draft issue",
+            "html_content": "<p>Draft body as <b>HTML</b></p>",
+            "text_content": "Draft body as plain text",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+async fn create_confirmed_subscriber(app: &TestApp) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+            VALUES ($1, 'longle@gmail.com', 'Long Le', now(), 'confirmed')
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.add_confirmed_subscribers_to_default_list().await;
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_drafts() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_drafts().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_create_a_draft() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_draft(&serde_json::json!({
+            "title": "A draft issue",
+            "html_content": "<p>Draft body as HTML</p>",
+            "text_content": "Draft body as plain text",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let n_drafts = sqlx::query!(r#"SELECT COUNT(*) as "n!" FROM newsletter_drafts"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_drafts, 0);
+}
+
+#[tokio::test]
+async fn a_draft_can_be_created_and_edited() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+
+    // Act - Part 1 - Create the draft
+    let draft_path = create_draft(&app).await;
+    let html_page = app.get_draft_html(&draft_path).await;
+    assert!(html_page.contains("<p><i>The draft has been saved.</i></p>"));
+    assert!(html_page.contains(r#"value="A draft issue""#));
+    assert!(html_page.contains("&lt;p&gt;Draft body as &lt;b&gt;HTML&lt;/b&gt;&lt;/p&gt;"));
+
+    // Act - Part 2 - Edit it
+    let response = app
+        .post_to_draft(
+            &draft_path,
+            &serde_json::json!({
+                "title": "An edited draft issue",
+                "html_content": "<p>Edited body</p>",
+                "text_content": "Edited body",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &draft_path);
+
+    // Act - Part 3 - The list of drafts
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains("An edited draft issue"));
+    assert!(!html_page.contains(">A draft issue<"));
+}
+
+#[tokio::test]
+async fn a_draft_must_have_a_title() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+
+    // Act
+    let response = app
+        .post_draft(&serde_json::json!({
+            "title": " ",
+            "html_content": "<p>Draft body as HTML</p>",
+            "text_content": "Draft body as plain text",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/drafts/new");
+    let html_page = app.get_new_draft_html().await;
+    assert!(html_page.contains("<p><i>The title must not be empty.</i></p>"));
+}
+
+#[tokio::test]
+async fn the_preview_shows_both_versions_of_the_email() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    let draft_path = create_draft(&app).await;
+
+    // Act
+    let html_page = app.get_draft_html(&format!("{}/preview", draft_path)).await;
+
+    // Assert
+    assert!(html_page.contains("Subject: A draft issue"));
+    assert!(
+        html_page.contains(r#"srcdoc="&lt;p&gt;Draft body as &lt;b&gt;HTML&lt;/b&gt;&lt;/p&gt;""#)
+    );
+    assert!(html_page.contains("<pre>Draft body as plain text</pre>"));
+}
+
+#[tokio::test]
+async fn the_preview_of_a_missing_draft_is_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+
+    // Act
+    let response = app
+        .get_draft(&format!("/admin/drafts/{}/preview", Uuid::new_v4()))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn a_draft_can_be_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    let draft_path = create_draft(&app).await;
+
+    // Act
+    let response = app
+        .post_to_draft(&format!("{}/delete", draft_path), &())
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/drafts");
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains("<p><i>The draft has been deleted.</i></p>"));
+    assert!(!html_page.contains("A draft issue"));
+}
+
+#[tokio::test]
+async fn drafts_are_not_delivered() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    login(&app).await;
+
+    // Act
+    create_draft(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn publishing_a_draft_delivers_it_once() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    login(&app).await;
+    let draft_path = create_draft(&app).await;
+
+    // Act - Part 1 - Publish
+    let response = app
+        .post_to_draft(&format!("{}/publish", draft_path), &())
+        .await;
+    assert_is_redirect_to(&response, "/admin/drafts");
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains("<p><i>The draft has been published.</i></p>"));
+    assert!(!html_page.contains("A draft issue"));
+
+    // Act - Part 2 - Publish again
+    app.post_to_draft(&format!("{}/publish", draft_path), &())
+        .await;
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains("<p><i>The draft no longer exists.</i></p>"));
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let issue = sqlx::query!("SELECT title, status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.title, "A draft issue");
+    assert_eq!(issue.status, "published");
+    // Mock verifies on Drop that we have sent the newsletter email **once**
+}
+
+#[tokio::test]
+async fn a_test_copy_of_a_draft_can_be_sent_to_reviewers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(serde_json::json!({
+            "Subject": "[TEST] A draft issue",
+            "TextBody": "Draft body as plain text"
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    login(&app).await;
+    let draft_path = create_draft(&app).await;
+
+    // Act
+    let response = app
+        .post_to_draft(
+            &format!("{}/test", draft_path),
+            &serde_json::json!({
+                "recipients": "first.reviewer@example.com,\nsecond.reviewer@example.com"
+            }),
+        )
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("{}/preview", draft_path));
+    let html_page = app.get_draft_html(&format!("{}/preview", draft_path)).await;
+    assert!(html_page.contains(
+        "<p><i>A test copy has been sent to first.reviewer@example.com, second.reviewer@example.com.</i></p>"
+    ));
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains("A draft issue"));
+}
+
+#[tokio::test]
+async fn invalid_test_recipients_are_escaped_on_the_preview() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    login(&app).await;
+    let draft_path = create_draft(&app).await;
+
+    // Act
+    let response = app
+        .post_to_draft(
+            &format!("{}/test", draft_path),
+            &serde_json::json!({
+                "recipients": "<script>alert(1)</script>"
+            }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("{}/preview", draft_path));
+    let html_page = app.get_draft_html(&format!("{}/preview", draft_path)).await;
+    assert!(html_page
+        .contains("&lt;script&gt;alert(1)&lt;/script&gt; is not a valid subscriber email."));
+    assert!(!html_page.contains("<script>"));
+}
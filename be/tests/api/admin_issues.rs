@@ -0,0 +1,73 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_delivery_reports() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_admin_issue(&Uuid::new_v4().to_string()).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn published_issues_are_listed_with_their_delivery_counts() {
+    // Arrange
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+            VALUES ($1, 'longle@gmail.com', 'Long Le', now(), 'confirmed')
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.add_confirmed_subscribers_to_default_list().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response: serde_json::Value = app
+        .post_newsletter(serde_json::json!({
+            "title": "Our first issue",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>"
+            }
+        }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let newsletter_issue_id = response["newsletter_issue_id"].as_str().unwrap();
+    app.dispatch_all_pending_emails().await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+
+    // Act - Part 1 - The list of issues
+    let html_page = app.get_admin_issues_html().await;
+    assert!(html_page.contains("Our first issue"));
+    assert!(html_page.contains(&format!("/admin/issues/{}", newsletter_issue_id)));
+
+    // Act - Part 2 - The report of the issue
+    let html_page = app
+        .get_admin_issue(newsletter_issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<li>Recipients: 1</li>"));
+    assert!(html_page.contains("<li>Sent: 1</li>"));
+}
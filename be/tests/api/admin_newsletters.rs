@@ -0,0 +1,230 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn login(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+}
+
+async fn create_confirmed_subscriber(app: &TestApp) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+            VALUES ($1, 'longle@gmail.com', 'Long Le', now(), 'confirmed')
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.add_confirmed_subscribers_to_default_list().await;
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_newsletter_form() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_publish_newsletter().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_publish_a_newsletter() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_to_confirmed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Submit newsletter form
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been published!</i></p>"));
+    app.dispatch_all_pending_emails().await;
+
+    // Mock verifies on Drop that we have sent the newsletter email
+}
+
+#[tokio::test]
+async fn newsletter_creation_is_idempotent() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Submit newsletter form
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act - Part 2 - Submit newsletter form **again**
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act - Part 3 - Follow the redirect
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been published!</i></p>"));
+    app.dispatch_all_pending_emails().await;
+
+    // Mock verifies on Drop that we have sent the newsletter email **once**
+}
+
+#[tokio::test]
+async fn a_newsletter_must_have_a_title() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The title must not be empty.</i></p>"));
+}
+
+#[tokio::test]
+async fn an_invalid_idempotency_key_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": ""
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn a_newsletter_with_an_unknown_merge_tag_is_not_published() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Hi {{ first_name }}",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(
+        "<p><i>Invalid text content: `first_name` is not a known merge tag: \
+        use `name`, `email`, `unsubscribe_url` or `preferences_url`.</i></p>"
+    ));
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) as "n!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn ticking_track_opens_turns_on_open_tracking_for_the_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "track_opens": "on",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let issue = sqlx::query!("SELECT track_opens FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(issue.track_opens);
+}
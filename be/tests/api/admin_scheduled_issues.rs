@@ -0,0 +1,146 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+async fn create_scheduled_issue(app: &TestApp) -> String {
+    let response: serde_json::Value = app
+        .post_newsletter(serde_json::json!({
+            "title": "A scheduled issue",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>"
+            },
+            "send_at": "2999-01-01T09:00:00+07:00"
+        }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    response["newsletter_issue_id"]
+        .as_str()
+        .unwrap()
+        .to_string()
+}
+
+async fn login(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_cancel_a_scheduled_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    let newsletter_issue_id = create_scheduled_issue(&app).await;
+
+    // Act
+    let response = app.post_cancel_scheduled_issue(&newsletter_issue_id).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let issue = sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "scheduled");
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_listed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_scheduled_issue(&app).await;
+    login(&app).await;
+
+    // Act
+    let html_page = app.get_scheduled_issues_html().await;
+
+    // Assert
+    assert!(html_page.contains("A scheduled issue"));
+    assert!(html_page.contains("2999-01-01T02:00:00+00:00"));
+}
+
+#[tokio::test]
+async fn a_scheduled_issue_can_be_rescheduled() {
+    // Arrange
+    let app = spawn_app().await;
+    let newsletter_issue_id = create_scheduled_issue(&app).await;
+    login(&app).await;
+
+    // Act - Part 1 - Reschedule
+    let response = app
+        .post_reschedule_issue(
+            &newsletter_issue_id,
+            &serde_json::json!({ "send_at": "2999-02-01T10:30:00-05:00" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/scheduled_issues");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_scheduled_issues_html().await;
+    assert!(html_page.contains("<p><i>The issue has been rescheduled.</i></p>"));
+    assert!(html_page.contains("2999-02-01T15:30:00+00:00"));
+}
+
+#[tokio::test]
+async fn an_issue_cannot_be_rescheduled_in_the_past() {
+    // Arrange
+    let app = spawn_app().await;
+    let newsletter_issue_id = create_scheduled_issue(&app).await;
+    login(&app).await;
+
+    // Act
+    app.post_reschedule_issue(
+        &newsletter_issue_id,
+        &serde_json::json!({ "send_at": "2001-01-01T00:00:00Z" }),
+    )
+    .await;
+
+    // Assert
+    let html_page = app.get_scheduled_issues_html().await;
+    assert!(html_page.contains("<p><i>The new time must be in the future.</i></p>"));
+    assert!(html_page.contains("2999-01-01T02:00:00+00:00"));
+}
+
+#[tokio::test]
+async fn a_cancelled_issue_is_never_delivered() {
+    // Arrange
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+            VALUES ($1, 'longle@gmail.com', 'Long Le', now(), 'confirmed')
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.add_confirmed_subscribers_to_default_list().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let newsletter_issue_id = create_scheduled_issue(&app).await;
+    login(&app).await;
+
+    // Act
+    let response = app.post_cancel_scheduled_issue(&newsletter_issue_id).await;
+    assert_is_redirect_to(&response, "/admin/scheduled_issues");
+    sqlx::query!("UPDATE newsletter_issues SET send_at = now() - interval '1 second'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.publish_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let html_page = app.get_scheduled_issues_html().await;
+    assert!(html_page.contains("<p><i>The issue has been cancelled.</i></p>"));
+    assert!(!html_page.contains("A scheduled issue"));
+}
@@ -0,0 +1,133 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+
+async fn create_dead_letter(app: &TestApp, subscriber_email: &str) -> Uuid {
+    let newsletter_issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id, title, text_content, html_content, slug, published_at
+        )
+        VALUES ($1, 'Newsletter title', 'Text', '<p>HTML</p>', $2, now())
+        "#,
+        newsletter_issue_id,
+        newsletter_issue_id.to_string()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_dead_letters (
+            newsletter_issue_id, subscriber_email, n_attempts, last_error, failed_at
+        )
+        VALUES ($1, $2, 5, 'A transient error was encountered while sending an email.', now())
+        "#,
+        newsletter_issue_id,
+        subscriber_email
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    newsletter_issue_id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_dead_letters() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_dead_letters().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_requeue_a_dead_letter() {
+    // Arrange
+    let app = spawn_app().await;
+    let newsletter_issue_id = create_dead_letter(&app, "longle@gmail.com").await;
+
+    // Act
+    let response = app
+        .post_requeue_dead_letter(&serde_json::json!({
+            "newsletter_issue_id": newsletter_issue_id,
+            "subscriber_email": "longle@gmail.com",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let n_dead_letters =
+        sqlx::query!("SELECT COUNT(*) as \"count!\" FROM issue_delivery_dead_letters")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .count;
+    assert_eq!(n_dead_letters, 1);
+}
+
+#[tokio::test]
+async fn dead_letters_are_listed_with_their_last_error() {
+    // Arrange
+    let app = spawn_app().await;
+    create_dead_letter(&app, "longle@gmail.com").await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+
+    // Act
+    let html_page = app.get_dead_letters_html().await;
+
+    // Assert
+    assert!(html_page.contains("longle@gmail.com"));
+    assert!(html_page.contains("A transient error was encountered while sending an email."));
+}
+
+#[tokio::test]
+async fn requeuing_a_dead_letter_moves_it_back_to_the_delivery_queue() {
+    // Arrange
+    let app = spawn_app().await;
+    let newsletter_issue_id = create_dead_letter(&app, "longle@gmail.com").await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+
+    // Act - Part 1 - Requeue
+    let response = app
+        .post_requeue_dead_letter(&serde_json::json!({
+            "newsletter_issue_id": newsletter_issue_id,
+            "subscriber_email": "longle@gmail.com",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dead_letters");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_dead_letters_html().await;
+    assert!(html_page.contains("<p><i>The delivery to longle@gmail.com has been requeued.</i></p>"));
+
+    // Assert
+    let task = sqlx::query!(
+        "SELECT newsletter_issue_id, subscriber_email, n_retries FROM issue_delivery_queue"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(task.newsletter_issue_id, newsletter_issue_id);
+    assert_eq!(task.subscriber_email, "longle@gmail.com");
+    assert_eq!(task.n_retries, 0);
+    let n_dead_letters =
+        sqlx::query!("SELECT COUNT(*) as \"count!\" FROM issue_delivery_dead_letters")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .count;
+    assert_eq!(n_dead_letters, 0);
+}
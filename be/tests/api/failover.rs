@@ -0,0 +1,87 @@
+use crate::helpers::{spawn_app_with, TestApp};
+use email_newsletter::configuration::{EmailProvider, ProviderSettings};
+use secrecy::Secret;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// An app whose primary provider is `app.email_server`, failing over to `backup`.
+async fn spawn_app_with_backup(backup: &MockServer) -> TestApp {
+    let backup = ProviderSettings {
+        provider: EmailProvider::Postmark,
+        base_url: backup.uri(),
+        authorization_token: Secret::new("backup-token".into()),
+        mailgun: None,
+        ses: None,
+        smtp: None,
+    };
+    spawn_app_with(|c| {
+        c.email_client.failover = vec![backup];
+        c.email_client.circuit_breaker_failure_threshold = 2;
+        c.email_client.circuit_breaker_cooldown_seconds = 60;
+    })
+    .await
+}
+
+async fn subscribe(app: &TestApp, name: &str) {
+    app.post_subscriptions(format!("name={0}&email={0}%40gmail.com", name))
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn confirmation_emails_go_through_the_backup_provider_when_the_primary_fails() {
+    // Arrange
+    let backup = MockServer::start().await;
+    let app = spawn_app_with_backup(&backup).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&backup)
+        .await;
+
+    // Act
+    subscribe(&app, "longle").await;
+
+    // Assert - the confirmation link works whichever provider sent it
+    let email_request = &backup.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn the_primary_provider_is_skipped_once_its_circuit_opens() {
+    // Arrange
+    let backup = MockServer::start().await;
+    let app = spawn_app_with_backup(&backup).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(4)
+        .mount(&backup)
+        .await;
+
+    // Act
+    for name in ["a", "b", "c", "d"] {
+        subscribe(&app, name).await;
+    }
+
+    // Assert - mock expectations are verified on drop
+}
@@ -0,0 +1,199 @@
+use crate::helpers::{spawn_app, TestApp};
+
+async fn publish_issue(app: &TestApp, title: &str) {
+    app.post_newsletter(serde_json::json!({
+        "title": title,
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+}
+
+async fn get_feed(app: &TestApp, path: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}{}", &app.address, path))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn the_rss_feed_lists_published_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    publish_issue(&app, "Our first issue").await;
+    app.post_newsletter(serde_json::json!({
+        "title": "A scheduled issue",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        },
+        "send_at": "2999-01-01T09:00:00Z"
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Act
+    let response = get_feed(&app, "/feed.rss").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/rss+xml; charset=utf-8"
+    );
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<title>Our first issue</title>"));
+    assert!(body.contains("<link>http://127.0.0.1/issues/our-first-issue</link>"));
+    assert!(body.contains("<description>&lt;p&gt;Newsletter body as HTML&lt;/p&gt;</description>"));
+    assert!(body.contains("+0000</pubDate>"));
+    assert!(!body.contains("A scheduled issue"));
+}
+
+#[tokio::test]
+async fn the_atom_feed_lists_published_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    publish_issue(&app, "Our first issue").await;
+
+    // Act
+    let response = get_feed(&app, "/feed.atom").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/atom+xml; charset=utf-8"
+    );
+    let body = response.text().await.unwrap();
+    assert!(body.contains(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#));
+    assert!(body.contains("<id>http://127.0.0.1/issues/our-first-issue</id>"));
+    assert!(body
+        .contains(r#"<content type="html">&lt;p&gt;Newsletter body as HTML&lt;/p&gt;</content>"#));
+}
+
+#[tokio::test]
+async fn the_json_feed_lists_published_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    publish_issue(&app, "Our first issue").await;
+    publish_issue(&app, "Our second issue").await;
+
+    // Act
+    let response = get_feed(&app, "/feed.json").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/feed+json; charset=utf-8"
+    );
+    let feed: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(feed["version"], "https://jsonfeed.org/version/1.1");
+    assert_eq!(feed["feed_url"], "http://127.0.0.1/feed.json");
+    let items = feed["items"].as_array().unwrap();
+    assert_eq!(items.len(), 2);
+    // Most recent first
+    assert_eq!(items[0]["title"], "Our second issue");
+    assert_eq!(items[1]["url"], "http://127.0.0.1/issues/our-first-issue");
+    assert_eq!(items[1]["content_html"], "<p>Newsletter body as HTML</p>");
+    let date_published = items[1]["date_published"].as_str().unwrap();
+    assert!(chrono::DateTime::parse_from_rfc3339(date_published).is_ok());
+}
+
+#[tokio::test]
+async fn an_empty_feed_is_still_valid() {
+    // Arrange
+    let app = spawn_app().await;
+
+    for path in ["/feed.rss", "/feed.atom", "/feed.json"] {
+        // Act
+        let response = get_feed(&app, path).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200);
+        assert!(response.headers().get("ETag").is_some());
+        assert!(response.headers().get("Last-Modified").is_none());
+    }
+}
+
+#[tokio::test]
+async fn feeds_support_conditional_requests() {
+    // Arrange
+    let app = spawn_app().await;
+    publish_issue(&app, "Our first issue").await;
+
+    for path in ["/feed.rss", "/feed.atom", "/feed.json"] {
+        let response = get_feed(&app, path).await;
+        let etag = response.headers()["ETag"].to_str().unwrap().to_owned();
+        let last_modified = response.headers()["Last-Modified"]
+            .to_str()
+            .unwrap()
+            .to_owned();
+
+        // Act - Part 1 - Same ETag
+        let response = app
+            .api_client
+            .get(format!("{}{}", &app.address, path))
+            .header("If-None-Match", &etag)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 304);
+        assert_eq!(response.headers()["ETag"].to_str().unwrap(), etag);
+
+        // Act - Part 2 - Not modified since
+        let response = app
+            .api_client
+            .get(format!("{}{}", &app.address, path))
+            .header("If-Modified-Since", &last_modified)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 304);
+
+        // Act - Part 3 - A stale ETag wins over If-Modified-Since
+        let response = app
+            .api_client
+            .get(format!("{}{}", &app.address, path))
+            .header("If-None-Match", r#""stale""#)
+            .header("If-Modified-Since", &last_modified)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+#[tokio::test]
+async fn a_new_issue_changes_the_etag() {
+    // Arrange
+    let app = spawn_app().await;
+    publish_issue(&app, "Our first issue").await;
+    let response = get_feed(&app, "/feed.rss").await;
+    let etag = response.headers()["ETag"].to_str().unwrap().to_owned();
+
+    // Act
+    publish_issue(&app, "Our second issue").await;
+    let response = app
+        .api_client
+        .get(format!("{}/feed.rss", &app.address))
+        .header("If-None-Match", &etag)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_ne!(response.headers()["ETag"].to_str().unwrap(), etag);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("<title>Our second issue</title>"));
+}
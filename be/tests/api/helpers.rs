@@ -1,12 +1,19 @@
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
-use email_newsletter::configuration::{get_configuration, DatabaseSettings};
+use email_newsletter::configuration::{
+    get_configuration, DatabaseSettings, Settings, WebhookSettings,
+};
+use email_newsletter::email_client::{EmailSender, RetryPolicy};
+use email_newsletter::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use email_newsletter::issue_scheduler::try_publish_due_issue;
 use email_newsletter::startup::{get_connection_pool, Application};
 use email_newsletter::telemetry::{get_subscriber, init_subscriber};
 use linkify::{LinkFinder, LinkKind};
 use once_cell::sync::Lazy;
 use reqwest::Url;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use std::sync::Arc;
 use tracing_subscriber::fmt::format;
 use uuid::Uuid;
 use wiremock::MockServer;
@@ -31,6 +38,10 @@ pub struct TestApp {
     pub port: u16,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
+    pub email_client: Arc<dyn EmailSender>,
+    pub retry_policy: RetryPolicy,
+    pub hmac_secret: Secret<String>,
+    pub webhooks: WebhookSettings,
 }
 
 /// TestUser
@@ -78,6 +89,73 @@ pub struct ConfirmationLinks {
 }
 
 impl TestApp {
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                self.email_client.as_ref(),
+                &self.retry_policy,
+                &self.address,
+                &self.hmac_secret,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    pub async fn publish_due_issues(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue =
+                try_publish_due_issue(&self.db_pool).await.unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    /// Tests that insert confirmed subscribers directly must also make them
+    /// members of the default list, as the confirmation link would.
+    pub async fn add_confirmed_subscribers_to_default_list(&self) {
+        sqlx::query!(
+            r#"
+            INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at, confirmed_at)
+            SELECT l.list_id, s.id, 'confirmed', s.subscribed_at, s.subscribed_at
+                FROM subscriptions s, lists l
+                WHERE s.status = 'confirmed' AND l.slug = 'newsletter'
+            ON CONFLICT DO NOTHING
+            "#
+        )
+        .execute(&self.db_pool)
+        .await
+        .unwrap();
+    }
+
+    pub async fn get_lists_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/lists", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_list<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/lists", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
             .post(&format!("{}/subscriptions", &self.address))
@@ -109,6 +187,20 @@ impl TestApp {
         ConfirmationLinks { html, plain_text }
     }
 
+    /// The one-click unsubscribe link of an issue, from its `List-Unsubscribe` header.
+    pub fn get_unsubscribe_link(&self, email_request: &wiremock::Request) -> Url {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let header = body["Headers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|h| h["Name"] == "List-Unsubscribe")
+            .unwrap();
+        let value = header["Value"].as_str().unwrap();
+        let raw_link = value.trim_start_matches('<').trim_end_matches('>');
+        Url::parse(raw_link).unwrap()
+    }
+
     pub async fn post_newsletter(&self, body: serde_json::Value) -> reqwest::Response {
         self.api_client
             .post(&format!("{}/newsletters", &self.address))
@@ -119,6 +211,258 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_test_newsletter(&self, body: serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/newsletters/test", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_newsletter_with_idempotency_key(
+        &self,
+        body: serde_json::Value,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/newsletters", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .header("Idempotency-Key", idempotency_key)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_newsletter_issue_report(
+        &self,
+        newsletter_issue_id: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/newsletters/{}/report",
+                &self.address, newsletter_issue_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_postmark_webhook(&self, body: serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/webhooks/postmark", &self.address))
+            .basic_auth(
+                &self.webhooks.username,
+                Some(self.webhooks.password.expose_secret()),
+            )
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn put_subscriber_tags(&self, body: serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .put(format!("{}/subscribers/tags", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn put_subscriber_attributes(&self, body: serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .put(format!("{}/subscribers/attributes", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_segment(&self, body: serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/segments", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_segment_count(&self, body: serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/segments/count", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_segments_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/segments", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_admin_segment<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/segments", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_issues_archive_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/issues", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_archived_issue(&self, slug: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/issues/{}", &self.address, slug))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_issues_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/issues", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_admin_issue(&self, newsletter_issue_id: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/issues/{}",
+                &self.address, newsletter_issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_scheduled_issues_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/scheduled_issues", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_reschedule_issue<Body>(
+        &self,
+        newsletter_issue_id: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/scheduled_issues/{}/reschedule",
+                &self.address, newsletter_issue_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_cancel_scheduled_issue(
+        &self,
+        newsletter_issue_id: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/scheduled_issues/{}/cancel",
+                &self.address, newsletter_issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_preferences(&self, token: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/subscriptions/preferences", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_preferences_html(&self, token: &str) -> String {
+        self.get_preferences(token).await.text().await.unwrap()
+    }
+
+    pub async fn post_preferences<Body>(&self, path: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize + ?Sized,
+    {
+        self.api_client
+            .post(format!(
+                "{}/subscriptions/preferences{}",
+                &self.address, path
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_topics_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/topics", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_topic<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/topics", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
@@ -154,6 +498,30 @@ impl TestApp {
         self.get_admin_dashboard().await.text().await.unwrap()
     }
 
+    pub async fn get_publish_newsletter(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/newsletters", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_publish_newsletter_html(&self) -> String {
+        self.get_publish_newsletter().await.text().await.unwrap()
+    }
+
+    pub async fn post_publish_newsletter<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
             .get(&format!("{}/admin/password", &self.address))
@@ -177,9 +545,134 @@ impl TestApp {
     pub async fn get_change_password_html(&self) -> String {
         self.get_change_password().await.text().await.unwrap()
     }
+
+    pub async fn get_dead_letters(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/dead_letters", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_dead_letters_html(&self) -> String {
+        self.get_dead_letters().await.text().await.unwrap()
+    }
+
+    pub async fn post_requeue_dead_letter<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/dead_letters/requeue", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_suppressions(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/suppressions", &self.address))
+            .query(&[("q", query)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_suppressions_html(&self, query: &str) -> String {
+        self.get_suppressions(query).await.text().await.unwrap()
+    }
+
+    pub async fn post_suppression<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/suppressions", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_remove_suppression<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/suppressions/delete", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_drafts(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/drafts", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_drafts_html(&self) -> String {
+        self.get_drafts().await.text().await.unwrap()
+    }
+
+    pub async fn get_new_draft_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/drafts/new", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_draft<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/drafts", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_draft(&self, draft_path: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}{}", &self.address, draft_path))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_draft_html(&self, draft_path: &str) -> String {
+        self.get_draft(draft_path).await.text().await.unwrap()
+    }
+
+    pub async fn post_to_draft<Body>(&self, draft_path: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}{}", &self.address, draft_path))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Spawn the application after tweaking its configuration, e.g. its sending limits.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
     let email_server = MockServer::start().await;
 
@@ -189,11 +682,14 @@ pub async fn spawn_app() -> TestApp {
         c.application.port = 0;
         // Use the mock server as email API
         c.email_client.base_url = email_server.uri();
+        configure(&mut c);
         c
     };
     configure_database(&configuration.database).await;
 
-    let application = Application::build(configuration.clone())
+    // Shared with the worker run by the tests, as in production
+    let email_client = configuration.email_client.clone().client();
+    let application = Application::build(configuration.clone(), email_client.clone())
         .await
         .expect("Failed to build application.");
 
@@ -212,6 +708,10 @@ pub async fn spawn_app() -> TestApp {
         port: app_port,
         test_user: TestUser::generate(),
         api_client: client,
+        email_client,
+        retry_policy: configuration.email_client.retry_policy(),
+        hmac_secret: configuration.application.hmac_secret.clone(),
+        webhooks: configuration.webhooks.clone(),
     };
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
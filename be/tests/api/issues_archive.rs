@@ -0,0 +1,309 @@
+use crate::helpers::{spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_issue(app: &TestApp, title: &str) {
+    app.post_newsletter(serde_json::json!({
+        "title": title,
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as <b>HTML</b></p>"
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+}
+
+#[tokio::test]
+async fn the_home_page_links_to_the_archive() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let html_page = app
+        .api_client
+        .get(&app.address)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains(r#"<a href="/issues">"#));
+}
+
+#[tokio::test]
+async fn published_issues_are_listed_in_the_archive() {
+    // Arrange
+    let app = spawn_app().await;
+    publish_issue(&app, "Our first issue").await;
+    app.post_newsletter(serde_json::json!({
+        "title": "A scheduled issue",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        },
+        "send_at": "2999-01-01T09:00:00Z"
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Act
+    let html_page = app.get_issues_archive_html().await;
+
+    // Assert
+    assert!(html_page.contains(r#"<a href="/issues/our-first-issue">Our first issue</a>"#));
+    assert!(!html_page.contains("A scheduled issue"));
+}
+
+#[tokio::test]
+async fn an_archived_issue_shows_its_html_version() {
+    // Arrange
+    let app = spawn_app().await;
+    publish_issue(&app, "Our first issue").await;
+
+    // Act
+    let response = app.get_archived_issue("our-first-issue").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h1>Our first issue</h1>"));
+    assert!(html_page.contains("<p>Newsletter body as <b>HTML</b></p>"));
+}
+
+#[tokio::test]
+async fn issues_sent_to_part_of_the_audience_are_not_archived() {
+    // Arrange
+    let app = spawn_app().await;
+    let private_list = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO lists (list_id, name, slug, created_at) VALUES ($1, 'Team', 'team', now())",
+        private_list
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let topic_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO topics (topic_id, name, created_at) VALUES ($1, 'Events', now())",
+        topic_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let segment: serde_json::Value = app
+        .post_segment(serde_json::json!({"name": "Beta", "filter": {"tag": "beta"}}))
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    // Act
+    publish_issue(&app, "Our first issue").await;
+    for (title, audience) in [
+        (
+            "Team notes",
+            serde_json::json!({"list_ids": [private_list]}),
+        ),
+        ("Meetup", serde_json::json!({"topic_id": topic_id})),
+        (
+            "Beta notes",
+            serde_json::json!({"segment_id": segment["segment_id"]}),
+        ),
+    ] {
+        let mut body = serde_json::json!({
+            "title": title,
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>"
+            }
+        });
+        body.as_object_mut()
+            .unwrap()
+            .extend(audience.as_object().unwrap().clone());
+        app.post_newsletter(body).await.error_for_status().unwrap();
+    }
+
+    // Assert
+    let html_page = app.get_issues_archive_html().await;
+    assert!(html_page.contains(r#"<a href="/issues/our-first-issue">"#));
+    for slug in ["team-notes", "meetup", "beta-notes"] {
+        assert!(!html_page.contains(slug));
+        assert_eq!(app.get_archived_issue(slug).await.status().as_u16(), 404);
+    }
+    let feed = reqwest::get(format!("{}/feed.rss", app.address))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(feed.contains("Our first issue"));
+    assert!(!feed.contains("Team notes"));
+}
+
+#[tokio::test]
+async fn hand_written_html_is_sanitized_in_the_archive() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_newsletter(serde_json::json!({
+        "title": "Our first issue",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": r#"<p onclick="steal()">Hello<script>steal()</script></p>"#
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Act
+    let html_page = app
+        .get_archived_issue("our-first-issue")
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains("<p>Hello</p>"));
+    assert!(!html_page.contains("steal()"));
+}
+
+#[tokio::test]
+async fn unknown_and_unpublished_issues_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+    app.post_newsletter(serde_json::json!({
+        "title": "A scheduled issue",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        },
+        "send_at": "2999-01-01T09:00:00Z"
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    for slug in ["a-scheduled-issue", "not-an-issue"] {
+        // Act
+        let response = app.get_archived_issue(slug).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 404);
+    }
+}
+
+#[tokio::test]
+async fn issues_with_the_same_title_get_distinct_permalinks() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    publish_issue(&app, "Weekly digest").await;
+    publish_issue(&app, "Weekly digest").await;
+
+    // Assert
+    let html_page = app.get_issues_archive_html().await;
+    assert!(html_page.contains(r#"<a href="/issues/weekly-digest">"#));
+    assert!(html_page.contains(r#"<a href="/issues/weekly-digest-2">"#));
+}
+
+#[tokio::test]
+async fn delivered_emails_link_to_the_archived_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+            VALUES ($1, 'longle@gmail.com', 'Long Le', now(), 'confirmed')
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.add_confirmed_subscribers_to_default_list().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    publish_issue(&app, "Our first issue").await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let permalink = format!("{}/issues/our-first-issue", app.address);
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains(&format!(r#"<a href="{}">"#, permalink)));
+    assert!(body["TextBody"].as_str().unwrap().contains(&permalink));
+    // The permalink works
+    let response = reqwest::get(&permalink).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn emails_of_issues_that_are_not_archived_do_not_link_to_the_archive() {
+    // Arrange
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+            VALUES ($1, 'longle@gmail.com', 'Long Le', now(), 'confirmed')
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.add_confirmed_subscribers_to_default_list().await;
+    let topic_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO topics (topic_id, name, created_at) VALUES ($1, 'Events', now())",
+        topic_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_newsletter(serde_json::json!({
+        "title": "Meetup",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        },
+        "topic_id": topic_id
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(!body["HtmlBody"].as_str().unwrap().contains("/issues/"));
+    assert!(!body["TextBody"].as_str().unwrap().contains("/issues/"));
+}
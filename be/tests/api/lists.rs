@@ -0,0 +1,361 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn create_list(app: &TestApp, name: &str, slug: &str) -> Uuid {
+    let list_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO lists (list_id, name, slug, created_at) VALUES ($1, $2, $3, now())",
+        list_id,
+        name,
+        slug
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    list_id
+}
+
+async fn membership_status(app: &TestApp, slug: &str) -> Option<String> {
+    sqlx::query!(
+        r#"
+        SELECT m.status
+            FROM list_memberships m
+            JOIN lists l ON l.list_id = m.list_id
+            WHERE l.slug = $1
+        "#,
+        slug
+    )
+    .fetch_optional(&app.db_pool)
+    .await
+    .unwrap()
+    .map(|r| r.status)
+}
+
+async fn mount_email_mock(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+/// Subscribe to a list and follow the link of the confirmation email.
+async fn subscribe_and_confirm(app: &TestApp, email: &str, slug: &str) {
+    app.post_subscriptions(format!("name=Long%20Le&email={}&list={}", email, slug))
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_link = app.get_confirmation_links(&email_request).html;
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn login(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+}
+
+#[tokio::test]
+async fn subscribing_without_a_list_joins_the_default_list() {
+    // Arrange
+    let app = spawn_app().await;
+    mount_email_mock(&app).await;
+
+    // Act
+    app.post_subscriptions("name=Long%20Le&email=longle%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    assert_eq!(
+        membership_status(&app, "newsletter").await.as_deref(),
+        Some("pending_confirmation")
+    );
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_returns_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=Long%20Le&email=longle%40gmail.com&list=nope".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn confirming_only_confirms_the_list_that_was_joined() {
+    // Arrange
+    let app = spawn_app().await;
+    create_list(&app, "Product updates", "product-updates").await;
+    mount_email_mock(&app).await;
+
+    // Act
+    subscribe_and_confirm(&app, "longle%40gmail.com", "product-updates").await;
+
+    // Assert
+    assert_eq!(
+        membership_status(&app, "product-updates").await.as_deref(),
+        Some("confirmed")
+    );
+    assert_eq!(membership_status(&app, "newsletter").await, None);
+}
+
+#[tokio::test]
+async fn joining_a_second_list_reuses_the_subscriber_and_asks_for_confirmation() {
+    // Arrange
+    let app = spawn_app().await;
+    create_list(&app, "Product updates", "product-updates").await;
+    mount_email_mock(&app).await;
+    subscribe_and_confirm(&app, "longle%40gmail.com", "newsletter").await;
+
+    // Act
+    app.post_subscriptions("name=Long%20Le&email=longle%40gmail.com&list=product-updates".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let n_subscribers = sqlx::query!(r#"SELECT COUNT(*) as "n!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_subscribers, 1);
+    assert_eq!(
+        membership_status(&app, "newsletter").await.as_deref(),
+        Some("confirmed")
+    );
+    assert_eq!(
+        membership_status(&app, "product-updates").await.as_deref(),
+        Some("pending_confirmation")
+    );
+    // One confirmation email per list
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn subscribing_again_to_a_confirmed_list_sends_no_email() {
+    // Arrange
+    let app = spawn_app().await;
+    mount_email_mock(&app).await;
+    subscribe_and_confirm(&app, "longle%40gmail.com", "newsletter").await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=Long%20Le&email=longle%40gmail.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn issues_are_only_delivered_to_the_members_of_the_chosen_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    let product_updates = create_list(&app, "Product updates", "product-updates").await;
+    mount_email_mock(&app).await;
+    subscribe_and_confirm(&app, "newsletter-reader%40gmail.com", "newsletter").await;
+    subscribe_and_confirm(&app, "product-fan%40gmail.com", "product-updates").await;
+    let n_confirmation_emails = app.email_server.received_requests().await.unwrap().len();
+
+    // Act
+    app.post_newsletter(serde_json::json!({
+        "title": "Release notes",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        },
+        "list_ids": [product_updates]
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let received_requests = app.email_server.received_requests().await.unwrap();
+    let recipients: Vec<String> = received_requests[n_confirmation_emails..]
+        .iter()
+        .map(|r| {
+            let body: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+            body["To"].as_str().unwrap().to_owned()
+        })
+        .collect();
+    assert_eq!(recipients, vec!["product-fan@gmail.com".to_string()]);
+}
+
+#[tokio::test]
+async fn subscribers_of_several_chosen_lists_receive_the_issue_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let product_updates = create_list(&app, "Product updates", "product-updates").await;
+    mount_email_mock(&app).await;
+    subscribe_and_confirm(&app, "longle%40gmail.com", "newsletter").await;
+    subscribe_and_confirm(&app, "longle%40gmail.com", "product-updates").await;
+    let default_list = sqlx::query!("SELECT list_id FROM lists WHERE slug = 'newsletter'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id;
+
+    // Act
+    app.post_newsletter(serde_json::json!({
+        "title": "Release notes",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        },
+        "list_ids": [default_list, product_updates]
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - two confirmation emails, one issue
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn publishing_to_no_list_or_an_unknown_list_is_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (serde_json::json!([]), "no list"),
+        (serde_json::json!([Uuid::new_v4()]), "an unknown list"),
+    ];
+
+    for (list_ids, description) in test_cases {
+        // Act
+        let response = app
+            .post_newsletter(serde_json::json!({
+                "title": "Newsletter title",
+                "content": {
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>"
+                },
+                "list_ids": list_ids
+            }))
+            .await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when publishing to {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn unsubscribing_leaves_every_list() {
+    // Arrange
+    let app = spawn_app().await;
+    create_list(&app, "Product updates", "product-updates").await;
+    mount_email_mock(&app).await;
+    subscribe_and_confirm(&app, "longle%40gmail.com", "newsletter").await;
+    subscribe_and_confirm(&app, "longle%40gmail.com", "product-updates").await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    let unsubscribe_url =
+        email_newsletter::routes::unsubscribe_url(&app.address, &app.hmac_secret, subscriber_id);
+
+    // Act
+    reqwest::Client::new()
+        .post(unsubscribe_url)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    assert_eq!(
+        membership_status(&app, "newsletter").await.as_deref(),
+        Some("unsubscribed")
+    );
+    assert_eq!(
+        membership_status(&app, "product-updates").await.as_deref(),
+        Some("unsubscribed")
+    );
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_lists() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_list(&serde_json::json!({"name": "Events", "slug": "events"}))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn admins_can_add_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+
+    // Act - Part 1 - Add a list
+    let response = app
+        .post_list(&serde_json::json!({"name": "Events", "slug": "events"}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/lists");
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("<p><i>The list Events has been added.</i></p>"));
+    assert!(html_page.contains("<td>events</td>"));
+
+    // Act - Part 2 - Reuse its slug
+    app.post_list(&serde_json::json!({"name": "More events", "slug": "events"}))
+        .await;
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains(
+        "<p><i>There already is a list named More events or with the slug events.</i></p>"
+    ));
+
+    // Act - Part 3 - An invalid slug
+    app.post_list(&serde_json::json!({"name": "Talks", "slug": "Our talks"}))
+        .await;
+    let html_page = app.get_lists_html().await;
+    assert!(html_page
+        .contains("<p><i>The slug must be made of lowercase letters, digits and dashes.</i></p>"));
+
+    // Assert - the default list and Events
+    let n_lists = sqlx::query!(r#"SELECT COUNT(*) as "n!" FROM lists"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_lists, 2);
+}
@@ -1,8 +1,25 @@
 mod admin_dashboard;
+mod admin_drafts;
+mod admin_issues;
+mod admin_newsletters;
+mod admin_scheduled_issues;
 mod change_password;
+mod dead_letters;
+mod failover;
+mod feeds;
 mod health_check;
 mod helpers;
+mod issues_archive;
+mod lists;
 mod login;
 mod newsletter;
+mod open_tracking;
+mod segments;
+mod sending_limits;
+mod smtp;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_preferences;
+mod subscriptions_unsubscribe;
+mod suppressions;
+mod webhooks;
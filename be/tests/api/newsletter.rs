@@ -1,6 +1,7 @@
 use crate::helpers::{spawn_app, ConfirmationLinks, TestApp};
+use email_newsletter::issue_scheduler::try_publish_due_issue;
 use uuid::Uuid;
-use wiremock::matchers::{any, method, path};
+use wiremock::matchers::{any, body_partial_json, method, path};
 use wiremock::{Mock, ResponseTemplate};
 
 #[tokio::test]
@@ -118,6 +119,7 @@ async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
     let response = app.post_newsletter(newsletter_request_body).await;
 
     assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
 }
 
 async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
@@ -182,6 +184,7 @@ async fn newsletters_are_delivered_to_confirmed_subscriber() {
 
     // Assert
     assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
 }
 
 #[tokio::test]
@@ -218,3 +221,882 @@ async fn newsletters_returns_400_for_invalid_data() {
         );
     }
 }
+
+#[tokio::test]
+async fn newsletter_creation_is_idempotent() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Publish a newsletter
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        }
+    });
+    let idempotency_key = Uuid::new_v4().to_string();
+    let response = app
+        .post_newsletter_with_idempotency_key(newsletter_request_body.clone(), &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act - Part 2 - Retry the same request
+    let response = app
+        .post_newsletter_with_idempotency_key(newsletter_request_body, &idempotency_key)
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email **once**
+}
+
+#[tokio::test]
+async fn concurrent_newsletter_submission_is_handled_gracefully() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Submit two newsletter requests concurrently
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        }
+    });
+    let idempotency_key = Uuid::new_v4().to_string();
+    let response1 =
+        app.post_newsletter_with_idempotency_key(newsletter_request_body.clone(), &idempotency_key);
+    let response2 =
+        app.post_newsletter_with_idempotency_key(newsletter_request_body, &idempotency_key);
+    let (response1, response2) = tokio::join!(response1, response2);
+
+    // Assert
+    assert_eq!(response1.status(), response2.status());
+    assert_eq!(
+        response1.text().await.unwrap(),
+        response2.text().await.unwrap()
+    );
+
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email **once**
+}
+
+#[tokio::test]
+async fn expired_idempotency_keys_are_processed_again() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        }
+    });
+    let idempotency_key = Uuid::new_v4().to_string();
+    app.post_newsletter_with_idempotency_key(newsletter_request_body.clone(), &idempotency_key)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Age the saved response past its time-to-live
+    sqlx::query!("UPDATE idempotency SET created_at = now() - interval '25 hours'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_newsletter_with_idempotency_key(newsletter_request_body, &idempotency_key)
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that the newsletter has been sent twice
+}
+
+#[tokio::test]
+async fn an_invalid_idempotency_key_is_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        }
+    });
+
+    // Act
+    let response = app
+        .post_newsletter_with_idempotency_key(newsletter_request_body, &"a".repeat(50))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn a_failed_delivery_does_not_prevent_delivery_to_other_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+            VALUES ($1, 'unreachable@gmail.com', 'Unreachable', now(), 'confirmed'),
+                   ($2, 'definitely-not-an-email', 'Invalid', now(), 'confirmed')
+        "#,
+        Uuid::new_v4(),
+        Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.add_confirmed_subscribers_to_default_list().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(
+            serde_json::json!({ "To": "unreachable@gmail.com" }),
+        ))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(
+            serde_json::json!({ "To": "longle@gmail.com" }),
+        ))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        }
+    });
+    let response = app.post_newsletter(newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let pending_tasks =
+        sqlx::query!("SELECT subscriber_email, n_retries FROM issue_delivery_queue")
+            .fetch_all(&app.db_pool)
+            .await
+            .unwrap();
+    // Only the transient failure is left, waiting to be retried
+    assert_eq!(pending_tasks.len(), 1);
+    assert_eq!(pending_tasks[0].subscriber_email, "unreachable@gmail.com");
+    assert_eq!(pending_tasks[0].n_retries, 1);
+}
+
+#[tokio::test]
+async fn transient_delivery_failures_are_retried_later() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - The first attempt fails
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        }
+    });
+    app.post_newsletter(newsletter_request_body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 1 - The retry is scheduled in the future
+    let task = sqlx::query!(
+        "SELECT n_retries, execute_after > now() as \"in_the_future!\" FROM issue_delivery_queue"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(task.n_retries, 1);
+    assert!(task.in_the_future);
+
+    // Act - Part 2 - The retry is due
+    sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 2
+    let n_pending_tasks = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_pending_tasks, 0);
+}
+
+#[tokio::test]
+async fn deliveries_are_moved_to_the_dead_letters_after_the_last_attempt() {
+    // Arrange
+    let mut app = spawn_app().await;
+    app.retry_policy.max_attempts = 3;
+    app.retry_policy.base_delay = std::time::Duration::ZERO;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        }
+    });
+    app.post_newsletter(newsletter_request_body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let dead_letter = sqlx::query!(
+        "SELECT subscriber_email, n_attempts, last_error FROM issue_delivery_dead_letters"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(dead_letter.subscriber_email, "longle@gmail.com");
+    assert_eq!(dead_letter.n_attempts, 3);
+    assert!(dead_letter.last_error.contains("500"));
+    let n_pending_tasks = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_pending_tasks, 0);
+}
+
+#[tokio::test]
+async fn permanent_delivery_failures_are_not_retried() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        }
+    });
+    app.post_newsletter(newsletter_request_body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let dead_letter = sqlx::query!("SELECT n_attempts FROM issue_delivery_dead_letters")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(dead_letter.n_attempts, 1);
+}
+
+#[tokio::test]
+async fn the_delivery_report_counts_recipients_in_each_state() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+            VALUES ($1, 'rejected@gmail.com', 'Rejected', now(), 'confirmed'),
+                   ($2, 'unreachable@gmail.com', 'Unreachable', now(), 'confirmed'),
+                   ($3, 'definitely-not-an-email', 'Invalid', now(), 'confirmed')
+        "#,
+        Uuid::new_v4(),
+        Uuid::new_v4(),
+        Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.add_confirmed_subscribers_to_default_list().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(
+            serde_json::json!({ "To": "rejected@gmail.com" }),
+        ))
+        .respond_with(ResponseTemplate::new(422))
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(
+            serde_json::json!({ "To": "unreachable@gmail.com" }),
+        ))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        }
+    });
+    let response: serde_json::Value = app
+        .post_newsletter(newsletter_request_body)
+        .await
+        .json()
+        .await
+        .unwrap();
+    let newsletter_issue_id = response["newsletter_issue_id"].as_str().unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Act
+    let response = app.get_newsletter_issue_report(newsletter_issue_id).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["title"], "Newsletter title");
+    assert_eq!(report["n_recipients"], 4);
+    assert_eq!(report["n_sent"], 1);
+    assert_eq!(report["n_pending"], 1);
+    assert_eq!(report["n_failed"], 1);
+    assert_eq!(report["n_skipped"], 1);
+    let failed_recipients = report["failed_recipients"].as_array().unwrap();
+    assert_eq!(failed_recipients.len(), 1);
+    assert_eq!(
+        failed_recipients[0]["subscriber_email"],
+        "rejected@gmail.com"
+    );
+    assert!(failed_recipients[0]["last_error"]
+        .as_str()
+        .unwrap()
+        .contains("422"));
+}
+
+#[tokio::test]
+async fn the_delivery_report_of_an_unknown_issue_is_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .get_newsletter_issue_report(&Uuid::new_v4().to_string())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn requests_for_a_delivery_report_missing_authorization_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!(
+            "{}/newsletters/{}/report",
+            &app.address,
+            Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_not_delivered_before_their_send_at() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        },
+        "send_at": "2999-01-01T09:00:00+07:00"
+    });
+    let response = app.post_newsletter(newsletter_request_body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["send_at"], "2999-01-01T02:00:00Z");
+    app.publish_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_delivered_once_their_send_at_has_passed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        },
+        "send_at": "2999-01-01T09:00:00+07:00"
+    });
+    app.post_newsletter(newsletter_request_body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    sqlx::query!("UPDATE newsletter_issues SET send_at = now() - interval '1 second'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.publish_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let issue = sqlx::query!("SELECT status, published_at FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "published");
+    assert!(issue.published_at.is_some());
+}
+
+#[tokio::test]
+async fn concurrent_schedulers_publish_a_due_issue_only_once() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        },
+        "send_at": "2999-01-01T09:00:00+07:00"
+    });
+    app.post_newsletter(newsletter_request_body)
+        .await
+        .error_for_status()
+        .unwrap();
+    sqlx::query!("UPDATE newsletter_issues SET send_at = now() - interval '1 second'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let (outcome1, outcome2) = tokio::join!(
+        try_publish_due_issue(&app.db_pool),
+        try_publish_due_issue(&app.db_pool)
+    );
+    outcome1.unwrap();
+    outcome2.unwrap();
+    app.publish_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+
+    // Mock verifies on Drop that we have sent the newsletter email **once**
+}
+
+#[tokio::test]
+async fn a_send_at_without_a_timezone_is_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        },
+        "send_at": "2999-01-01T09:00:00"
+    });
+    let response = app.post_newsletter(newsletter_request_body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn test_copies_are_sent_only_to_the_chosen_recipients() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(serde_json::json!({
+            "To": "reviewer@example.com",
+            "Subject": "[TEST] Newsletter title"
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_test_newsletter(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>"
+            },
+            "recipients": ["reviewer@example.com"]
+        }))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    // A test copy is not a publication
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) as "n!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn test_copies_require_a_small_list_of_valid_recipients() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let too_many: Vec<String> = (0..11)
+        .map(|i| format!("reviewer{}@example.com", i))
+        .collect();
+    let test_cases = vec![
+        (serde_json::json!([]), "no recipients"),
+        (serde_json::json!(["not-an-email"]), "an invalid recipient"),
+        (serde_json::json!(too_many), "too many recipients"),
+    ];
+
+    for (recipients, description) in test_cases {
+        // Act
+        let response = app
+            .post_test_newsletter(serde_json::json!({
+                "title": "Newsletter title",
+                "content": {
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>"
+                },
+                "recipients": recipients
+            }))
+            .await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload had {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_copies_require_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters/test", &app.address))
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>"
+            },
+            "recipients": ["reviewer@example.com"]
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn a_markdown_issue_is_delivered_as_html_and_plain_text() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "markdown": "# News\n\nRead [the blog](https://example.com/blog).\n\n<script>alert('hi')</script>"
+        }
+    });
+    let response = app.post_newsletter(newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    // The first email is the confirmation sent to the new subscriber
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_requests[1].body).unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains("<h1>News</h1>"));
+    assert!(html_body.contains(r#"<a href="https://example.com/blog""#));
+    assert!(!html_body.contains("<script>"));
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(text_body.contains("News\n====\n\nRead the blog [1].\n\n[1] https://example.com/blog"));
+}
+
+#[tokio::test]
+async fn merge_tags_are_replaced_with_each_subscriber_details() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "title": "News for {{ name }}",
+            "content": {
+                "text": "Hi {{ name | default: \"friend\" }}, this was sent to {{ email }}.\nBye: {{ unsubscribe_url }}",
+                "html": "<p>Hi {{ name }}</p><a href=\"{{ unsubscribe_url }}\">Unsubscribe</a>"
+            }
+        }))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_requests[1].body).unwrap();
+    assert_eq!(body["Subject"], "News for Long Le");
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(text_body.contains("Hi Long Le, this was sent to longle@gmail.com."));
+    assert!(text_body.contains("/subscriptions/unsubscribe?subscriber_id="));
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains("<p>Hi Long Le</p>"));
+    assert!(!html_body.contains("{{"));
+}
+
+#[tokio::test]
+async fn merge_tags_can_be_used_in_markdown_links() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "title": "News",
+            "content": {
+                "markdown": "Hi {{ name }}!\n\n[Unsubscribe]({{ unsubscribe_url }}) \
+                    or [manage your preferences]({{preferences_url}})."
+            }
+        }))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let unsubscribe_link = app.get_unsubscribe_link(&email_requests[1]);
+    let body: serde_json::Value = serde_json::from_slice(&email_requests[1].body).unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains("<p>Hi Long Le!</p>"));
+    assert!(html_body.contains(&format!(
+        r#"<a href="{}" rel="noopener noreferrer">Unsubscribe</a>"#,
+        unsubscribe_link.as_str().replace('&', "&amp;")
+    )));
+    assert!(html_body.contains("/subscriptions/preferences?token="));
+    assert!(!html_body.contains("{{"));
+    assert!(!html_body.contains("%7B%7B"));
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(text_body.contains(&format!("[1] {}", unsubscribe_link)));
+}
+
+#[tokio::test]
+async fn issues_with_invalid_merge_tags_are_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let test_cases = vec![
+        (
+            serde_json::json!({
+                "title": "News for {{ first_name }}",
+                "content": {"text": "Body", "html": "<p>Body</p>"}
+            }),
+            "an unknown merge tag in the title",
+        ),
+        (
+            serde_json::json!({
+                "title": "News",
+                "content": {"text": "Hi {{ name", "html": "<p>Body</p>"}
+            }),
+            "an unclosed merge tag",
+        ),
+        (
+            serde_json::json!({
+                "title": "News",
+                "content": {"markdown": "Hi {{ name | upper }}"}
+            }),
+            "an unknown filter",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = app.post_newsletter(body).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the issue had {}.",
+            description
+        );
+    }
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) as "n!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_issues, 0);
+}
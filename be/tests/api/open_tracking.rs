@@ -0,0 +1,173 @@
+use crate::helpers::{spawn_app, TestApp};
+use reqwest::Url;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn create_confirmed_subscriber(app: &TestApp) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+            VALUES ($1, 'longle@gmail.com', 'Long Le', now(), 'confirmed')
+        "#,
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.add_confirmed_subscribers_to_default_list().await;
+    subscriber_id
+}
+
+/// Publish an issue and return the HTML and plain-text bodies it was sent with.
+async fn deliver_an_issue(app: &TestApp, track_opens: bool) -> (String, String) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .named("Deliver an issue")
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_newsletter(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<html><body><p>Newsletter body as HTML</p></body></html>"
+        },
+        "track_opens": track_opens
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    (
+        body["HtmlBody"].as_str().unwrap().to_owned(),
+        body["TextBody"].as_str().unwrap().to_owned(),
+    )
+}
+
+/// The address of the tracking pixel of an HTML body.
+fn get_tracking_pixel_link(html_body: &str) -> Url {
+    let links: Vec<_> = linkify::LinkFinder::new()
+        .links(html_body)
+        .filter(|l| *l.kind() == linkify::LinkKind::Url && l.as_str().contains("/t/open/"))
+        .collect();
+    assert_eq!(links.len(), 1);
+    Url::parse(links[0].as_str()).unwrap()
+}
+
+#[tokio::test]
+async fn opens_are_not_tracked_unless_asked_for() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let (html_body, _) = deliver_an_issue(&app, false).await;
+
+    // Assert
+    assert!(!html_body.contains("/t/open/"));
+}
+
+#[tokio::test]
+async fn tracked_issues_carry_a_pixel_in_their_html_body_only() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let (html_body, text_body) = deliver_an_issue(&app, true).await;
+
+    // Assert
+    let pixel_link = get_tracking_pixel_link(&html_body);
+    assert!(pixel_link
+        .as_str()
+        .starts_with(&format!("{}/t/open/", app.address)));
+    assert!(html_body.contains(r#"style="display:block;border:0"></body>"#));
+    assert!(!text_body.contains("/t/open/"));
+}
+
+#[tokio::test]
+async fn loading_the_pixel_records_an_open() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let (html_body, _) = deliver_an_issue(&app, true).await;
+    let pixel_link = get_tracking_pixel_link(&html_body);
+
+    // Act
+    let response = app
+        .api_client
+        .get(pixel_link)
+        .header("User-Agent", "Mozilla/5.0 (Mail client)")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "image/gif");
+    assert!(response.headers()["Cache-Control"]
+        .to_str()
+        .unwrap()
+        .contains("no-store"));
+    assert_eq!(response.bytes().await.unwrap().len(), 43);
+    // The open is stored after the pixel has been returned
+    let mut open = None;
+    for _ in 0..50 {
+        open = sqlx::query!("SELECT subscriber_id, user_agent FROM issue_opens")
+            .fetch_optional(&app.db_pool)
+            .await
+            .unwrap();
+        if open.is_some() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    let open = open.expect("The open was never stored");
+    assert_eq!(open.subscriber_id, subscriber_id);
+    assert_eq!(
+        open.user_agent.as_deref(),
+        Some("Mozilla/5.0 (Mail client)")
+    );
+}
+
+#[tokio::test]
+async fn forged_tokens_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (html_body, _) = deliver_an_issue(&app, true).await;
+    let pixel_link = get_tracking_pixel_link(&html_body);
+    let token = pixel_link.path_segments().unwrap().next_back().unwrap();
+    // Somebody else's subscription, with the original signature
+    let replacement = if &token[30..31] == "A" { "B" } else { "A" };
+    let forged_token = format!("{}{}{}", &token[..30], replacement, &token[31..]);
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/t/open/{}", app.address, forged_token))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+    let n_opens = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM issue_opens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_opens, 0);
+}
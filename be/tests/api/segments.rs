@@ -0,0 +1,353 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Confirmed members of the default list.
+async fn create_confirmed_subscribers(app: &TestApp, emails: &[&str]) {
+    for email in emails {
+        sqlx::query!(
+            r#"
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+                VALUES ($1, $2, 'Long Le', now(), 'confirmed')
+            "#,
+            Uuid::new_v4(),
+            email
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+    app.add_confirmed_subscribers_to_default_list().await;
+}
+
+async fn tag(app: &TestApp, email: &str, tags: &[&str]) {
+    app.put_subscriber_tags(serde_json::json!({"email": email, "tags": tags}))
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+async fn save_segment(app: &TestApp, name: &str, filter: serde_json::Value) -> Uuid {
+    let response: serde_json::Value = app
+        .post_segment(serde_json::json!({"name": name, "filter": filter}))
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    Uuid::parse_str(response["segment_id"].as_str().unwrap()).unwrap()
+}
+
+async fn count(app: &TestApp, body: serde_json::Value) -> i64 {
+    let response: serde_json::Value = app
+        .post_segment_count(body)
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    response["recipients"].as_i64().unwrap()
+}
+
+#[tokio::test]
+async fn issues_sent_to_a_segment_only_reach_the_matching_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(
+        &app,
+        &["beta@gmail.com", "pro@gmail.com", "other@gmail.com"],
+    )
+    .await;
+    tag(&app, "beta@gmail.com", &["Beta"]).await;
+    app.put_subscriber_attributes(serde_json::json!({
+        "email": "pro@gmail.com",
+        "attributes": {"plan": "pro"}
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    let segment_id = save_segment(
+        &app,
+        "Early adopters",
+        serde_json::json!({"or": [
+            {"tag": "beta"},
+            {"attribute": {"name": "plan", "equals": "pro"}}
+        ]}),
+    )
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_newsletter(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        },
+        "segment_id": segment_id
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let mut recipients: Vec<String> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| {
+            let body: serde_json::Value = serde_json::from_slice(&r.body).unwrap();
+            body["To"].as_str().unwrap().to_owned()
+        })
+        .collect();
+    recipients.sort();
+    assert_eq!(recipients, vec!["beta@gmail.com", "pro@gmail.com"]);
+}
+
+#[tokio::test]
+async fn the_recipients_of_a_segment_can_be_counted_before_sending() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(&app, &["a@gmail.com", "b@gmail.com", "c@gmail.com"]).await;
+    tag(&app, "a@gmail.com", &["beta", "churned"]).await;
+    tag(&app, "b@gmail.com", &["beta"]).await;
+    let segment_id = save_segment(
+        &app,
+        "Active beta testers",
+        serde_json::json!({"and": [{"tag": "beta"}, {"not": {"tag": "churned"}}]}),
+    )
+    .await;
+
+    // Act & Assert
+    assert_eq!(count(&app, serde_json::json!({})).await, 3);
+    assert_eq!(
+        count(&app, serde_json::json!({"segment_id": segment_id})).await,
+        1
+    );
+    assert_eq!(
+        count(&app, serde_json::json!({"filter": {"tag": "beta"}})).await,
+        2
+    );
+    assert_eq!(
+        count(
+            &app,
+            serde_json::json!({"filter": {"subscribed_before": "2000-01-01T00:00:00Z"}})
+        )
+        .await,
+        0
+    );
+}
+
+#[tokio::test]
+async fn unconfirmed_subscribers_are_never_counted() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(&app, &["a@gmail.com"]).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let n_recipients = count(
+        &app,
+        serde_json::json!({"filter": {"status": "unsubscribed"}}),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(n_recipients, 0);
+}
+
+#[tokio::test]
+async fn invalid_segments_are_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    save_segment(&app, "Beta", serde_json::json!({"tag": "beta"})).await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"name": "Colours", "filter": {"colour": "blue"}}),
+            "an unknown filter",
+        ),
+        (
+            serde_json::json!({"name": "Active", "filter": {"status": "active"}}),
+            "an unknown status",
+        ),
+        (
+            serde_json::json!({"name": "", "filter": {"tag": "beta"}}),
+            "an empty name",
+        ),
+        (
+            serde_json::json!({"name": "Beta", "filter": {"tag": "beta"}}),
+            "a name already taken",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = app.post_segment(body).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request for {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn publishing_to_an_unknown_segment_is_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>"
+            },
+            "segment_id": Uuid::new_v4()
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn tagging_an_unknown_subscriber_returns_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .put_subscriber_tags(serde_json::json!({"email": "nobody@gmail.com", "tags": ["beta"]}))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn tags_are_replaced_and_stored_lowercase() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(&app, &["a@gmail.com"]).await;
+
+    // Act
+    tag(&app, "a@gmail.com", &["beta", "vip"]).await;
+    tag(&app, "a@gmail.com", &[" VIP ", "Speaker"]).await;
+
+    // Assert
+    let tags: Vec<String> = sqlx::query!("SELECT tag FROM subscriber_tags ORDER BY tag")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.tag)
+        .collect();
+    assert_eq!(tags, vec!["speaker", "vip"]);
+}
+
+#[tokio::test]
+async fn the_segment_api_requires_authentication() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/segments", &app.address))
+        .json(&serde_json::json!({"name": "Beta", "filter": {"tag": "beta"}}))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn admins_can_add_segments_and_see_their_size() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(&app, &["a@gmail.com", "b@gmail.com"]).await;
+    tag(&app, "a@gmail.com", &["beta"]).await;
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+
+    // Act - Part 1 - An invalid filter
+    let response = app
+        .post_admin_segment(&serde_json::json!({"name": "Beta", "filter": "{\"tag\": "}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/segments");
+    let html_page = app.get_segments_html().await;
+    assert!(html_page.contains("Invalid segment filter"));
+
+    // Act - Part 2 - A valid one
+    app.post_admin_segment(&serde_json::json!({"name": "Beta", "filter": r#"{"tag": "beta"}"#}))
+        .await;
+    let html_page = app.get_segments_html().await;
+
+    // Assert
+    assert!(html_page.contains("<p><i>The segment Beta has been added.</i></p>"));
+    assert!(html_page.contains("<td>Beta</td>"));
+    assert!(html_page.contains("<td>1</td>"));
+}
+
+#[tokio::test]
+async fn attribute_names_are_matched_regardless_of_surrounding_whitespace() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(&app, &["a@gmail.com", "b@gmail.com"]).await;
+    for email in ["a@gmail.com", "b@gmail.com"] {
+        app.put_subscriber_attributes(serde_json::json!({
+            "email": email,
+            "attributes": {"plan ": "pro"}
+        }))
+        .await
+        .error_for_status()
+        .unwrap();
+    }
+
+    // Act & Assert
+    for name in ["plan", " plan "] {
+        let filter = serde_json::json!({"attribute": {"name": name, "equals": "pro"}});
+        assert_eq!(count(&app, serde_json::json!({"filter": filter})).await, 2);
+    }
+}
+
+#[tokio::test]
+async fn attribute_names_that_collide_once_trimmed_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(&app, &["a@gmail.com"]).await;
+
+    // Act
+    let response = app
+        .put_subscriber_attributes(serde_json::json!({
+            "email": "a@gmail.com",
+            "attributes": {"plan": "pro", " plan": "free"}
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
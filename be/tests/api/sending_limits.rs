@@ -0,0 +1,85 @@
+use crate::helpers::{spawn_app_with, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn spawn_app_with_daily_cap(daily_cap: u32) -> TestApp {
+    spawn_app_with(|c| c.email_client.daily_cap = Some(daily_cap)).await
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_503_once_the_daily_quota_is_exhausted() {
+    // Arrange
+    let app = spawn_app_with_daily_cap(1).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=Long%20Le&email=longle%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_subscriptions("name=Le%20Long&email=lelong%40gmail.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 503);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("try again tomorrow"));
+}
+
+#[tokio::test]
+async fn deliveries_over_the_daily_quota_are_postponed_without_using_an_attempt() {
+    // Arrange - the quota goes to the confirmation email
+    let app = spawn_app_with_daily_cap(1).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=Long%20Le&email=longle%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_links(email_request).html;
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    app.post_newsletter(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let task = sqlx::query!(
+        r#"
+        SELECT n_retries, execute_after > now() as "in_the_future!"
+            FROM issue_delivery_queue
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(task.n_retries, 0);
+    assert!(task.in_the_future);
+}
@@ -0,0 +1,254 @@
+use crate::helpers::{spawn_app_with, TestApp};
+use email_newsletter::configuration::EmailProvider;
+use email_newsletter::email_client::{SmtpSettings, SmtpTls};
+use secrecy::Secret;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use uuid::Uuid;
+
+const USERNAME: &str = "newsletter";
+const PASSWORD: &str = "relay-password";
+
+#[derive(Debug, Clone)]
+struct ReceivedEmail {
+    mail_from: String,
+    rcpt_to: Vec<String>,
+    data: String,
+}
+
+/// A bare-bones SMTP server that accepts every email once authenticated.
+///
+/// It offers neither STARTTLS nor TLS: the relays of our deployments do, but
+/// we only need to check what goes over the wire here.
+#[derive(Clone)]
+struct SmtpStandIn {
+    port: u16,
+    emails: Arc<Mutex<Vec<ReceivedEmail>>>,
+    n_connections: Arc<AtomicUsize>,
+}
+
+impl SmtpStandIn {
+    async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stand_in = Self {
+            port: listener.local_addr().unwrap().port(),
+            emails: Arc::new(Mutex::new(Vec::new())),
+            n_connections: Arc::new(AtomicUsize::new(0)),
+        };
+        let server = stand_in.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                server.n_connections.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(server.clone().serve(stream));
+            }
+        });
+        stand_in
+    }
+
+    async fn serve(self, stream: tokio::net::TcpStream) {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut authenticated = false;
+        let mut email: Option<ReceivedEmail> = None;
+        writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let command = line.to_uppercase();
+            let reply: &[u8] = if command.starts_with("EHLO") {
+                b"250-localhost\r\n250-AUTH PLAIN\r\n250 8BITMIME\r\n"
+            } else if command.starts_with("AUTH PLAIN ") {
+                let credentials = base64::decode(&line["AUTH PLAIN ".len()..]).unwrap();
+                authenticated = credentials == format!("\0{}\0{}", USERNAME, PASSWORD).as_bytes();
+                if authenticated {
+                    b"235 2.7.0 Authentication successful\r\n"
+                } else {
+                    b"535 5.7.8 Authentication credentials invalid\r\n"
+                }
+            } else if command.starts_with("MAIL FROM:") {
+                if !authenticated {
+                    writer
+                        .write_all(b"530 5.7.0 Authentication required\r\n")
+                        .await
+                        .unwrap();
+                    continue;
+                }
+                email = Some(ReceivedEmail {
+                    mail_from: line["MAIL FROM:".len()..].to_string(),
+                    rcpt_to: Vec::new(),
+                    data: String::new(),
+                });
+                b"250 2.1.0 OK\r\n"
+            } else if command.starts_with("RCPT TO:") {
+                if let Some(email) = email.as_mut() {
+                    email.rcpt_to.push(line["RCPT TO:".len()..].to_string());
+                }
+                b"250 2.1.5 OK\r\n"
+            } else if command == "DATA" {
+                writer
+                    .write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")
+                    .await
+                    .unwrap();
+                let mut data = String::new();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if line == "." {
+                        break;
+                    }
+                    data.push_str(line.strip_prefix('.').unwrap_or(&line));
+                    data.push('\n');
+                }
+                if let Some(mut email) = email.take() {
+                    email.data = data;
+                    self.emails.lock().unwrap().push(email);
+                }
+                b"250 2.0.0 Queued\r\n"
+            } else if command == "RSET" || command == "NOOP" {
+                b"250 2.0.0 OK\r\n"
+            } else if command == "QUIT" {
+                writer.write_all(b"221 2.0.0 Bye\r\n").await.unwrap();
+                return;
+            } else {
+                b"502 5.5.2 Command not recognized\r\n"
+            };
+            writer.write_all(reply).await.unwrap();
+        }
+    }
+
+    fn emails(&self) -> Vec<ReceivedEmail> {
+        self.emails.lock().unwrap().clone()
+    }
+}
+
+async fn spawn_app_with_smtp(stand_in: &SmtpStandIn, tls: SmtpTls, password: &str) -> TestApp {
+    let settings = SmtpSettings {
+        host: "127.0.0.1".into(),
+        port: stand_in.port,
+        tls,
+        username: Some(USERNAME.into()),
+        password: Some(Secret::new(password.into())),
+        max_connections: 2,
+    };
+    spawn_app_with(|c| {
+        c.email_client.provider = EmailProvider::Smtp;
+        c.email_client.smtp = Some(settings);
+    })
+    .await
+}
+
+async fn create_confirmed_subscribers(app: &TestApp, emails: &[&str]) {
+    for email in emails {
+        sqlx::query!(
+            r#"
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+                VALUES ($1, $2, 'Long Le', now(), 'confirmed')
+            "#,
+            Uuid::new_v4(),
+            email
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+    app.add_confirmed_subscribers_to_default_list().await;
+}
+
+async fn publish_newsletter(app: &TestApp) {
+    app.post_newsletter(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+}
+
+#[tokio::test]
+async fn confirmation_emails_are_sent_through_the_smtp_relay_as_multipart() {
+    // Arrange
+    let stand_in = SmtpStandIn::start().await;
+    let app = spawn_app_with_smtp(&stand_in, SmtpTls::None, PASSWORD).await;
+
+    // Act
+    app.post_subscriptions("name=Long%20Le&email=longle%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let emails = stand_in.emails();
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0].mail_from, "<test@gmail.com>");
+    assert_eq!(emails[0].rcpt_to, vec!["<longle@gmail.com>"]);
+    let data = &emails[0].data;
+    assert!(data.contains("Subject: Welcome!"));
+    assert!(data.contains("Content-Type: multipart/alternative"));
+    assert!(data.contains("Content-Type: text/plain"));
+    assert!(data.contains("Content-Type: text/html"));
+    assert!(data.contains("Welcome to Newsletter!"));
+}
+
+#[tokio::test]
+async fn issues_are_delivered_over_pooled_smtp_connections() {
+    // Arrange
+    let stand_in = SmtpStandIn::start().await;
+    let app = spawn_app_with_smtp(&stand_in, SmtpTls::None, PASSWORD).await;
+    create_confirmed_subscribers(&app, &["a@gmail.com", "b@gmail.com", "c@gmail.com"]).await;
+
+    // Act
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let emails = stand_in.emails();
+    assert_eq!(emails.len(), 3);
+    assert!(emails.iter().all(|e| e
+        .data
+        .contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click")));
+    // The worker sends one email at a time, over the same connection
+    assert_eq!(stand_in.n_connections.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn deliveries_refused_by_the_relay_are_moved_to_the_dead_letters() {
+    // Arrange
+    let stand_in = SmtpStandIn::start().await;
+    let app = spawn_app_with_smtp(&stand_in, SmtpTls::None, "wrong-password").await;
+    create_confirmed_subscribers(&app, &["longle@gmail.com"]).await;
+
+    // Act
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - a 535 reply is permanent
+    assert!(stand_in.emails().is_empty());
+    let n_dead_letters =
+        sqlx::query!(r#"SELECT COUNT(*) as "n!" FROM issue_delivery_dead_letters"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .n;
+    assert_eq!(n_dead_letters, 1);
+}
+
+#[tokio::test]
+async fn starttls_is_required_when_configured() {
+    // Arrange
+    let stand_in = SmtpStandIn::start().await;
+    let app = spawn_app_with_smtp(&stand_in, SmtpTls::Starttls, PASSWORD).await;
+    create_confirmed_subscribers(&app, &["longle@gmail.com"]).await;
+
+    // Act
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - nothing went out in clear text, the delivery will be retried
+    assert!(stand_in.emails().is_empty());
+    let task = sqlx::query!("SELECT n_retries FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(task.n_retries, 1);
+}
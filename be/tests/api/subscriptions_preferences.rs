@@ -0,0 +1,609 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use email_newsletter::routes::{issue_preferences_url, prune_preference_tokens};
+use linkify::{LinkFinder, LinkKind};
+use reqwest::Url;
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn create_confirmed_subscriber(app: &TestApp) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+            VALUES ($1, 'longle@gmail.com', 'Long Le', now(), 'confirmed')
+        "#,
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.add_confirmed_subscribers_to_default_list().await;
+    subscriber_id
+}
+
+async fn create_topic(app: &TestApp, name: &str) -> Uuid {
+    let topic_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO topics (topic_id, name, created_at) VALUES ($1, $2, now())",
+        topic_id,
+        name
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    topic_id
+}
+
+/// A fresh preferences token for the subscriber, as found in the link of an issue.
+async fn preferences_token(app: &TestApp, subscriber_id: Uuid) -> String {
+    let url = issue_preferences_url(
+        &app.db_pool,
+        &app.address,
+        &app.hmac_secret,
+        subscriber_id,
+        None,
+    )
+    .await
+    .unwrap();
+    token_from_url(&url)
+}
+
+fn token_from_url(url: &str) -> String {
+    Url::parse(url)
+        .unwrap()
+        .query_pairs()
+        .find(|(k, _)| k == "token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+async fn login(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+}
+
+#[tokio::test]
+async fn issues_link_to_the_preference_center() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Receive an issue
+    app.post_newsletter(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let preferences_link = LinkFinder::new()
+        .links(body["TextBody"].as_str().unwrap())
+        .filter(|l| *l.kind() == LinkKind::Url)
+        .map(|l| l.as_str().to_owned())
+        .find(|l| l.contains("/subscriptions/preferences"))
+        .unwrap();
+
+    // Act - Part 2 - Follow the link
+    let html_page = app
+        .get_preferences_html(&token_from_url(&preferences_link))
+        .await;
+
+    // Assert
+    assert!(html_page.contains("Subscribed as longle@gmail.com"));
+    assert!(html_page.contains(r#"value="Long Le""#));
+}
+
+#[tokio::test]
+async fn subscribers_can_change_their_name_topics_and_frequency() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let releases = create_topic(&app, "Releases").await;
+    let events = create_topic(&app, "Events").await;
+    let token = preferences_token(&app, subscriber_id).await;
+
+    // Act - Part 1 - Save the preferences
+    let response = app
+        .post_preferences(
+            "",
+            &[
+                ("token", token.as_str()),
+                ("name", "Le Long"),
+                ("topic", &releases.to_string()),
+                ("delivery_frequency", "weekly"),
+            ],
+        )
+        .await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/subscriptions/preferences?token={}", token),
+    );
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_preferences_html(&token).await;
+    assert!(html_page.contains("<p><i>Your preferences have been saved.</i></p>"));
+
+    // Assert
+    let saved = sqlx::query!(
+        "SELECT name, delivery_frequency FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.name, "Le Long");
+    assert_eq!(saved.delivery_frequency, "weekly");
+    let opt_outs: Vec<Uuid> = sqlx::query!(
+        "SELECT topic_id FROM topic_opt_outs WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.topic_id)
+    .collect();
+    assert_eq!(opt_outs, vec![events]);
+}
+
+#[tokio::test]
+async fn subscribers_can_join_and_leave_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let product_updates = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO lists (list_id, name, slug, created_at) \
+        VALUES ($1, 'Product updates', 'product-updates', now())",
+        product_updates
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let token = preferences_token(&app, subscriber_id).await;
+    let html_page = app.get_preferences_html(&token).await;
+    assert!(html_page.contains(&format!(
+        r#"<input type="checkbox" name="list" value="{}"> Product updates"#,
+        product_updates
+    )));
+
+    // Act - Leave the default list, join the other one
+    app.post_preferences(
+        "",
+        &[
+            ("token", token.as_str()),
+            ("name", "Long Le"),
+            ("list", &product_updates.to_string()),
+            ("delivery_frequency", "every_issue"),
+        ],
+    )
+    .await;
+
+    // Assert
+    let memberships: Vec<(String, String)> = sqlx::query!(
+        r#"
+        SELECT l.slug, m.status
+            FROM list_memberships m
+            JOIN lists l ON l.list_id = m.list_id
+            WHERE m.subscriber_id = $1
+            ORDER BY l.slug
+        "#,
+        subscriber_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.slug, r.status))
+    .collect();
+    assert_eq!(
+        memberships,
+        vec![
+            ("newsletter".to_string(), "unsubscribed".to_string()),
+            ("product-updates".to_string(), "confirmed".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn an_invalid_name_is_not_saved() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let token = preferences_token(&app, subscriber_id).await;
+
+    // Act
+    app.post_preferences(
+        "",
+        &[
+            ("token", token.as_str()),
+            ("name", "  "),
+            ("delivery_frequency", "weekly"),
+        ],
+    )
+    .await;
+
+    // Assert
+    let html_page = app.get_preferences_html(&token).await;
+    assert!(html_page.contains("is not a valid subscriber name."));
+    let saved = sqlx::query!(
+        "SELECT name, delivery_frequency FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.name, "Long Le");
+    assert_eq!(saved.delivery_frequency, "every_issue");
+}
+
+#[tokio::test]
+async fn issues_about_a_topic_skip_the_subscribers_who_opted_out() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let events = create_topic(&app, "Events").await;
+    sqlx::query!(
+        "INSERT INTO topic_opt_outs (subscriber_id, topic_id) VALUES ($1, $2)",
+        subscriber_id,
+        events
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "title": "Meetup next week",
+            "content": {"markdown": "See you there!"},
+            "topic_id": events
+        }))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn an_unknown_topic_is_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "title": "Meetup next week",
+            "content": {"markdown": "See you there!"},
+            "topic_id": Uuid::new_v4()
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn a_weekly_subscriber_receives_at_most_one_issue_a_week() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    sqlx::query!(
+        "UPDATE subscriptions SET delivery_frequency = 'weekly' WHERE id = $1",
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    for title in ["First issue", "Second issue"] {
+        app.post_newsletter(serde_json::json!({
+            "title": title,
+            "content": {"markdown": "Newsletter body"}
+        }))
+        .await
+        .error_for_status()
+        .unwrap();
+        app.dispatch_all_pending_emails().await;
+    }
+
+    // Assert
+    let outcome = sqlx::query!("SELECT outcome FROM issue_delivery_log")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .outcome;
+    assert_eq!(outcome, "sent");
+    // The second issue waits for a week after the first one
+    let delay = sqlx::query!(
+        r#"
+        SELECT EXTRACT(EPOCH FROM q.execute_after - l.recorded_at)::float8 AS "seconds!"
+            FROM issue_delivery_queue q, issue_delivery_log l
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .seconds;
+    assert!((delay - 7. * 24. * 3600.).abs() < 1.);
+}
+
+#[tokio::test]
+async fn a_postponed_issue_is_delivered_once_the_week_is_over() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    sqlx::query!(
+        "UPDATE subscriptions SET delivery_frequency = 'weekly' WHERE id = $1",
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    for title in ["First issue", "Second issue"] {
+        app.post_newsletter(serde_json::json!({
+            "title": title,
+            "content": {"markdown": "Newsletter body"}
+        }))
+        .await
+        .error_for_status()
+        .unwrap();
+        app.dispatch_all_pending_emails().await;
+    }
+
+    // Act - a week goes by
+    sqlx::query!("UPDATE issue_delivery_log SET recorded_at = recorded_at - interval '7 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!(
+        "UPDATE issue_delivery_queue SET execute_after = execute_after - interval '7 days'"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let outcomes: Vec<String> = sqlx::query!(
+        r#"
+        SELECT l.outcome
+            FROM issue_delivery_log l
+            JOIN newsletter_issues i USING (newsletter_issue_id)
+            ORDER BY i.published_at
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.outcome)
+    .collect();
+    assert_eq!(outcomes, vec!["sent", "sent"]);
+}
+
+#[tokio::test]
+async fn forged_and_expired_tokens_are_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let token = preferences_token(&app, subscriber_id).await;
+    let (token_id, _) = token.split_once('.').unwrap();
+    let forged_token = format!("{}.{}", token_id, "ab".repeat(32));
+    let expired_token = preferences_token(&app, subscriber_id).await;
+    let (expired_token_id, _) = expired_token.split_once('.').unwrap();
+    sqlx::query!(
+        "UPDATE preference_tokens SET expires_at = now() - interval '1 day' WHERE token_id = $1",
+        Uuid::parse_str(expired_token_id).unwrap()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    for token in [forged_token.as_str(), expired_token.as_str(), "garbage"] {
+        // Act
+        let response = app.get_preferences(token).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 401);
+    }
+}
+
+#[tokio::test]
+async fn subscribers_can_revoke_their_preference_links() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let token = preferences_token(&app, subscriber_id).await;
+    let other_token = preferences_token(&app, subscriber_id).await;
+
+    // Act
+    let response = app
+        .post_preferences("/revoke", &[("token", token.as_str())])
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.get_preferences(&token).await.status().as_u16(), 401);
+    assert_eq!(
+        app.get_preferences(&other_token).await.status().as_u16(),
+        401
+    );
+    // A link issued later works again
+    let new_token = preferences_token(&app, subscriber_id).await;
+    assert_eq!(app.get_preferences(&new_token).await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscribers_can_unsubscribe_from_the_preference_center() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let token = preferences_token(&app, subscriber_id).await;
+
+    // Act
+    let response = app
+        .post_preferences("/unsubscribe", &[("token", token.as_str())])
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let status = sqlx::query!(
+        "SELECT status FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .status;
+    assert_eq!(status, "unsubscribed");
+    assert_eq!(app.get_preferences(&token).await.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_topics() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.post_topic(&serde_json::json!({"name": "Events"})).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn admins_can_add_topics() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+
+    // Act - Part 1 - Add a topic
+    let response = app.post_topic(&serde_json::json!({"name": "Events"})).await;
+    assert_is_redirect_to(&response, "/admin/topics");
+    let html_page = app.get_topics_html().await;
+    assert!(html_page.contains("<p><i>The topic Events has been added.</i></p>"));
+
+    // Act - Part 2 - Add it again
+    app.post_topic(&serde_json::json!({"name": "Events"})).await;
+    let html_page = app.get_topics_html().await;
+    assert!(html_page.contains("<p><i>There already is a topic named Events.</i></p>"));
+
+    // Assert
+    let n_topics = sqlx::query!(r#"SELECT COUNT(*) as "n!" FROM topics"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_topics, 1);
+}
+
+#[tokio::test]
+async fn retried_deliveries_reuse_the_preferences_link() {
+    // Arrange
+    let mut app = spawn_app().await;
+    app.retry_policy.base_delay = std::time::Duration::ZERO;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_newsletter(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let n_tokens = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM preference_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_tokens, 1);
+}
+
+#[tokio::test]
+async fn expired_and_revoked_tokens_are_pruned() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let token = preferences_token(&app, subscriber_id).await;
+    let expired_token = preferences_token(&app, subscriber_id).await;
+    let (expired_token_id, _) = expired_token.split_once('.').unwrap();
+    sqlx::query!(
+        "UPDATE preference_tokens SET expires_at = now() - interval '1 day' WHERE token_id = $1",
+        Uuid::parse_str(expired_token_id).unwrap()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.post_preferences("/revoke", &[("token", token.as_str())])
+        .await
+        .error_for_status()
+        .unwrap();
+    let valid_token = preferences_token(&app, subscriber_id).await;
+
+    // Act
+    let n_pruned = prune_preference_tokens(&app.db_pool).await.unwrap();
+
+    // Assert
+    assert_eq!(n_pruned, 2);
+    assert_eq!(
+        app.get_preferences(&valid_token).await.status().as_u16(),
+        200
+    );
+}
@@ -0,0 +1,298 @@
+use crate::helpers::{spawn_app, TestApp};
+use reqwest::Url;
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn create_confirmed_subscriber(app: &TestApp) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+            VALUES ($1, 'longle@gmail.com', 'Long Le', now(), 'confirmed')
+        "#,
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.add_confirmed_subscribers_to_default_list().await;
+    subscriber_id
+}
+
+async fn subscription_status(app: &TestApp, subscriber_id: Uuid) -> String {
+    sqlx::query!(
+        "SELECT status FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .status
+}
+
+async fn suppression_source(app: &TestApp) -> Option<String> {
+    sqlx::query!("SELECT source FROM suppressions WHERE email = 'longle@gmail.com'")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap()
+        .map(|r| r.source)
+}
+
+/// Publish an issue and return the unsubscribe link it was sent with.
+async fn deliver_an_issue(app: &TestApp) -> Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .named("Deliver an issue")
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_newsletter(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_unsubscribe_link(&email_request)
+}
+
+#[tokio::test]
+async fn issues_carry_an_unsubscribe_link_and_one_click_headers() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+
+    // Act
+    let unsubscribe_link = deliver_an_issue(&app).await;
+
+    // Assert
+    assert!(unsubscribe_link
+        .as_str()
+        .starts_with(&format!("{}/subscriptions/unsubscribe?", app.address)));
+    assert!(unsubscribe_link
+        .query()
+        .unwrap()
+        .contains(&subscriber_id.to_string()));
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["Headers"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!({
+            "Name": "List-Unsubscribe-Post",
+            "Value": "List-Unsubscribe=One-Click"
+        })));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains(unsubscribe_link.as_str()));
+    let escaped_link = unsubscribe_link.as_str().replace('&', "&amp;");
+    assert!(body["HtmlBody"].as_str().unwrap().contains(&escaped_link));
+}
+
+#[tokio::test]
+async fn the_unsubscribe_link_asks_for_confirmation_first() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let unsubscribe_link = deliver_an_issue(&app).await;
+
+    // Act
+    let response = reqwest::get(unsubscribe_link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("longle@gmail.com"));
+    assert!(html_page.contains(r#"method="post""#));
+    assert_eq!(subscription_status(&app, subscriber_id).await, "confirmed");
+}
+
+#[tokio::test]
+async fn a_one_click_post_unsubscribes_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let unsubscribe_link = deliver_an_issue(&app).await;
+
+    // Act - the request a mail client sends, as described in RFC 8058
+    let response = reqwest::Client::new()
+        .post(unsubscribe_link)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        subscription_status(&app, subscriber_id).await,
+        "unsubscribed"
+    );
+    assert_eq!(
+        suppression_source(&app).await.as_deref(),
+        Some("unsubscribe")
+    );
+}
+
+#[tokio::test]
+async fn subscribing_again_lifts_the_unsubscribe_suppression() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let unsubscribe_link = deliver_an_issue(&app).await;
+    reqwest::Client::new()
+        .post(unsubscribe_link)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .named("Send a confirmation")
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions("name=Long%20Le&email=longle%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    assert_eq!(subscription_status(&app, subscriber_id).await, "confirmed");
+    assert_eq!(suppression_source(&app).await, None);
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_do_not_receive_new_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let unsubscribe_link = deliver_an_issue(&app).await;
+    reqwest::Client::new()
+        .post(unsubscribe_link)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletter(serde_json::json!({
+            "title": "Another newsletter",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>"
+            }
+        }))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn queued_deliveries_are_skipped_after_unsubscribing() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletter(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Act
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'unsubscribed' WHERE id = $1",
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let outcome = sqlx::query!("SELECT outcome FROM issue_delivery_log")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .outcome;
+    assert_eq!(outcome, "skipped");
+}
+
+#[tokio::test]
+async fn a_forged_unsubscribe_link_is_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let forged_link = format!(
+        "{}/subscriptions/unsubscribe?subscriber_id={}&token={}",
+        app.address,
+        subscriber_id,
+        "ab".repeat(32)
+    );
+
+    // Act
+    let get_response = reqwest::get(&forged_link).await.unwrap();
+    let post_response = reqwest::Client::new()
+        .post(&forged_link)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(get_response.status().as_u16(), 401);
+    assert_eq!(post_response.status().as_u16(), 401);
+    assert_eq!(subscription_status(&app, subscriber_id).await, "confirmed");
+}
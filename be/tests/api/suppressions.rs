@@ -0,0 +1,325 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn login(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+}
+
+async fn suppress(app: &TestApp, email: &str) {
+    let response = app
+        .post_suppression(&serde_json::json!({
+            "email": email,
+            "source": "manual",
+            "reason": "Asked us by email"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+}
+
+async fn n_suppressions(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) as "n!" FROM suppressions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_the_suppression_list() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let responses = [
+        app.get_suppressions("").await,
+        app.post_suppression(&serde_json::json!({
+            "email": "longle@gmail.com",
+            "source": "manual",
+            "reason": "Asked us by email"
+        }))
+        .await,
+        app.post_remove_suppression(&serde_json::json!({"email": "longle@gmail.com"}))
+            .await,
+    ];
+
+    // Assert
+    for response in responses {
+        assert_is_redirect_to(&response, "/login");
+    }
+    assert_eq!(n_suppressions(&app).await, 0);
+}
+
+#[tokio::test]
+async fn suppressed_addresses_are_stored_normalized_and_can_be_searched() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+
+    // Act
+    suppress(&app, " LongLe@Gmail.com").await;
+    let html_page = app.get_suppressions_html("").await;
+    assert!(html_page.contains("<p><i>longle@gmail.com has been suppressed.</i></p>"));
+    suppress(&app, "lelong@gmail.com").await;
+
+    // Assert
+    let html_page = app.get_suppressions_html("LONGLE").await;
+    assert!(html_page.contains("<td>longle@gmail.com</td>"));
+    assert!(html_page.contains("<td>Asked us by email</td>"));
+    assert!(!html_page.contains("<td>lelong@gmail.com</td>"));
+}
+
+#[tokio::test]
+async fn invalid_suppressions_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    let test_cases = [
+        (
+            serde_json::json!({"email": "not-an-email", "source": "manual", "reason": "Typo"}),
+            "not-an-email is not a valid subscriber email.",
+        ),
+        (
+            serde_json::json!({"email": "longle@gmail.com", "source": "spam", "reason": "Spam"}),
+            "spam is not a known suppression source.",
+        ),
+        (
+            serde_json::json!({"email": "longle@gmail.com", "source": "manual", "reason": " "}),
+            "The reason must not be empty.",
+        ),
+    ];
+
+    for (body, error_message) in test_cases {
+        // Act
+        let response = app.post_suppression(&body).await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/suppressions");
+        let html_page = app.get_suppressions_html("").await;
+        assert!(html_page.contains(error_message), "{}", html_page);
+    }
+    assert_eq!(n_suppressions(&app).await, 0);
+}
+
+#[tokio::test]
+async fn removed_addresses_can_subscribe_again() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    suppress(&app, "longle@gmail.com").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_remove_suppression(&serde_json::json!({"email": "LongLe@gmail.com"}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+    let html_page = app.get_suppressions_html("").await;
+    assert!(html_page.contains("LongLe@gmail.com can be mailed again."));
+    app.post_subscriptions("name=Long%20Le&email=longle%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert - the confirmation email went out
+    assert_eq!(n_suppressions(&app).await, 0);
+}
+
+#[tokio::test]
+async fn suppressed_addresses_are_not_mailed_when_they_subscribe_again() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    suppress(&app, "longle@gmail.com").await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=Long%20Le&email=LongLe%40gmail.com".into())
+        .await;
+
+    // Assert - nothing gives the suppression away
+    assert_eq!(response.status().as_u16(), 200);
+    let n_subscribers = sqlx::query!(r#"SELECT COUNT(*) as "n!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_subscribers, 0);
+}
+
+#[tokio::test]
+async fn issues_are_not_delivered_to_suppressed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    for email in ["longle@gmail.com", "lelong@gmail.com"] {
+        sqlx::query!(
+            r#"
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+                VALUES ($1, $2, 'Long Le', now(), 'confirmed')
+            "#,
+            Uuid::new_v4(),
+            email
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+    app.add_confirmed_subscribers_to_default_list().await;
+    // Queued before the address was suppressed
+    app.post_newsletter(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    suppress(&app, "longle@gmail.com").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "lelong@gmail.com");
+    let details = sqlx::query!(
+        "SELECT details FROM issue_delivery_log WHERE subscriber_email = 'longle@gmail.com'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .details;
+    assert_eq!(
+        details.as_deref(),
+        Some("The address is suppressed (manual): Asked us by email")
+    );
+}
+
+#[tokio::test]
+async fn test_copies_are_not_sent_to_suppressed_addresses() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    suppress(&app, "longle@gmail.com").await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_test_newsletter(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>"
+            },
+            "recipients": ["reviewer@gmail.com", "LongLe@gmail.com"]
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn bounces_reported_by_the_webhook_add_a_suppression() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+
+    // Act - the address doesn't belong to any subscriber
+    app.post_postmark_webhook(serde_json::json!({
+        "RecordType": "Bounce",
+        "Type": "HardBounce",
+        "Email": "nobody@gmail.com"
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Assert
+    let html_page = app.get_suppressions_html("nobody").await;
+    assert!(html_page.contains("<td>bounce</td>"));
+    assert!(html_page.contains("<td>Hard bounce</td>"));
+}
+
+#[tokio::test]
+async fn addresses_removed_from_the_list_after_a_bounce_are_mailed_again() {
+    // Arrange
+    let app = spawn_app().await;
+    login(&app).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+            VALUES ($1, 'longle@gmail.com', 'Long Le', now(), 'confirmed')
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.add_confirmed_subscribers_to_default_list().await;
+    app.post_postmark_webhook(serde_json::json!({
+        "RecordType": "Bounce",
+        "Type": "HardBounce",
+        "Email": "longle@gmail.com"
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    let response = app
+        .post_remove_suppression(&serde_json::json!({"email": "longle@gmail.com"}))
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_newsletter(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "longle@gmail.com");
+}
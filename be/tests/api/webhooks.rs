@@ -0,0 +1,307 @@
+use crate::helpers::{spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+struct BounceState {
+    suppression_source: Option<String>,
+    soft_bounces: i32,
+}
+
+async fn create_confirmed_subscriber(app: &TestApp, email: &str) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+            VALUES ($1, $2, 'Long Le', now(), 'confirmed')
+        "#,
+        Uuid::new_v4(),
+        email
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.add_confirmed_subscribers_to_default_list().await;
+}
+
+async fn bounce_state(app: &TestApp, email: &str) -> BounceState {
+    sqlx::query_as!(
+        BounceState,
+        r#"
+        SELECT x.source AS "suppression_source?", s.soft_bounces
+            FROM subscriptions s
+            LEFT JOIN suppressions x ON x.email = lower(trim(s.email))
+            WHERE s.email = $1
+        "#,
+        email
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+fn bounce(bounce_type: &str, email: &str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "ID": 4323372036854775807u64,
+        "Type": bounce_type,
+        "TypeCode": 1,
+        "Name": "Hard bounce",
+        "MessageStream": "outbound",
+        "Email": email,
+        "BouncedAt": "2022-09-15T16:09:19Z",
+        "Inactive": true
+    })
+}
+
+#[tokio::test]
+async fn webhooks_without_valid_credentials_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "longle@gmail.com").await;
+
+    for password in [None, Some("wrong-password")] {
+        // Act
+        let mut request = app
+            .api_client
+            .post(format!("{}/webhooks/postmark", &app.address))
+            .json(&bounce("HardBounce", "longle@gmail.com"));
+        if let Some(password) = password {
+            request = request.basic_auth(&app.webhooks.username, Some(password));
+        }
+        let response = request.send().await.unwrap();
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(
+            response.headers()["WWW-Authenticate"],
+            r#"Basic realm="webhooks""#
+        );
+    }
+    let state = bounce_state(&app, "longle@gmail.com").await;
+    assert_eq!(state.suppression_source, None);
+}
+
+#[tokio::test]
+async fn hard_bounces_suppress_the_address() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "longle@gmail.com").await;
+
+    // Act - Postmark sends addresses as they were given to it
+    let response = app
+        .post_postmark_webhook(bounce("HardBounce", "LongLe@gmail.com"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let state = bounce_state(&app, "longle@gmail.com").await;
+    assert_eq!(state.suppression_source.as_deref(), Some("bounce"));
+}
+
+#[tokio::test]
+async fn spam_complaints_suppress_the_address() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "longle@gmail.com").await;
+
+    // Act
+    let response = app
+        .post_postmark_webhook(serde_json::json!({
+            "RecordType": "SpamComplaint",
+            "ID": 42,
+            "Type": "SpamComplaint",
+            "TypeCode": 512,
+            "Email": "longle@gmail.com",
+            "BouncedAt": "2022-09-15T16:09:19Z"
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let state = bounce_state(&app, "longle@gmail.com").await;
+    assert_eq!(state.suppression_source.as_deref(), Some("complaint"));
+}
+
+#[tokio::test]
+async fn soft_bounces_are_counted_without_suppressing_the_address() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "longle@gmail.com").await;
+
+    // Act
+    for _ in 0..2 {
+        app.post_postmark_webhook(bounce("SoftBounce", "longle@gmail.com"))
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+
+    // Assert
+    let state = bounce_state(&app, "longle@gmail.com").await;
+    assert_eq!(state.soft_bounces, 2);
+    assert_eq!(state.suppression_source, None);
+}
+
+#[tokio::test]
+async fn subscription_changes_suppress_and_reactivate_the_address() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "longle@gmail.com").await;
+    let change = |suppression_reason: Option<&str>| {
+        serde_json::json!({
+            "RecordType": "SubscriptionChange",
+            "MessageStream": "outbound",
+            "Recipient": "longle@gmail.com",
+            "SuppressSending": suppression_reason.is_some(),
+            "SuppressionReason": suppression_reason,
+            "ChangedAt": "2022-09-15T16:09:19Z"
+        })
+    };
+
+    // Act - Part 1 - Suppress
+    app.post_postmark_webhook(change(Some("HardBounce")))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert - Part 1
+    let state = bounce_state(&app, "longle@gmail.com").await;
+    assert_eq!(state.suppression_source.as_deref(), Some("bounce"));
+
+    // Act - Part 2 - Reactivate
+    app.post_postmark_webhook(change(None))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert - Part 2
+    let state = bounce_state(&app, "longle@gmail.com").await;
+    assert_eq!(state.suppression_source, None);
+}
+
+#[tokio::test]
+async fn manual_suppressions_outlive_a_reactivation() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "longle@gmail.com").await;
+    let change = |suppress_sending: bool| {
+        serde_json::json!({
+            "RecordType": "SubscriptionChange",
+            "MessageStream": "outbound",
+            "Recipient": "longle@gmail.com",
+            "SuppressSending": suppress_sending,
+            "SuppressionReason": suppress_sending.then_some("ManualSuppression"),
+            "ChangedAt": "2022-09-15T16:09:19Z"
+        })
+    };
+
+    // Act
+    for suppress_sending in [true, false] {
+        app.post_postmark_webhook(change(suppress_sending))
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+
+    // Assert - only an admin can lift it
+    let state = bounce_state(&app, "longle@gmail.com").await;
+    assert_eq!(state.suppression_source.as_deref(), Some("manual"));
+}
+
+#[tokio::test]
+async fn events_we_do_not_track_or_for_unknown_addresses_are_acknowledged() {
+    // Arrange
+    let app = spawn_app().await;
+    let events = [
+        serde_json::json!({
+            "RecordType": "Delivery",
+            "Recipient": "longle@gmail.com",
+            "DeliveredAt": "2022-09-15T16:09:19Z"
+        }),
+        bounce("HardBounce", "nobody@gmail.com"),
+    ];
+
+    for event in events {
+        // Act
+        let response = app.post_postmark_webhook(event).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+#[tokio::test]
+async fn issues_are_not_delivered_to_suppressed_addresses() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "longle@gmail.com").await;
+    create_confirmed_subscriber(&app, "lelong@gmail.com").await;
+    app.post_postmark_webhook(bounce("HardBounce", "longle@gmail.com"))
+        .await
+        .error_for_status()
+        .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_newsletter(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "lelong@gmail.com");
+}
+
+#[tokio::test]
+async fn queued_deliveries_to_addresses_suppressed_since_are_skipped() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "longle@gmail.com").await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletter(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>"
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Act
+    app.post_postmark_webhook(bounce("HardBounce", "longle@gmail.com"))
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let log = sqlx::query!("SELECT outcome, details FROM issue_delivery_log")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(log.outcome, "skipped");
+    assert_eq!(
+        log.details.as_deref(),
+        Some("The address is suppressed (bounce): Hard bounce")
+    );
+}